@@ -103,3 +103,213 @@ pub fn derive_from_enum(item: proc_macro::TokenStream) -> proc_macro::TokenStrea
 pub fn from(attr: proc_macro::TokenStream, _: proc_macro::TokenStream) -> proc_macro::TokenStream {
     attr
 }
+
+// Declares a Halo tag struct by the absolute offset of each field rather than
+// by hand-counted padding. Every field needs an #[offset(..)] except the
+// #[deku(skip)] ones, which aren't read from the tag and are left out of the
+// layout entirely, and the struct needs its total #[tag_layout(size = ..)]. The padding between fields is derived from
+// each field's crate::halo::layout::TagLayout::SIZE (Vec<u8> fields use their
+// deku count instead), overlapping fields fail to compile, and the struct gets
+// a TagLayout impl of its own so it can be nested and dumped field by field.
+#[proc_macro_attribute]
+pub fn tag_layout(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut size: Option<syn::LitInt> = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("size") {
+            size = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `size = ..`"))
+        }
+    });
+    syn::parse_macro_input!(attr with attr_parser);
+    let mut input = syn::parse_macro_input!(item as syn::ItemStruct);
+    match expand_tag_layout(size, &mut input) {
+        Ok(impls) => quote!{ #input #impls }.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+enum FieldSize {
+    Bytes(usize),
+    Layout(Box<syn::Type>),
+}
+
+impl FieldSize {
+    fn to_tokens(&self) -> proc_macro2::TokenStream {
+        match self {
+            FieldSize::Bytes(n) => quote!{ #n },
+            FieldSize::Layout(ty) => quote!{ <#ty as crate::halo::layout::TagLayout>::SIZE },
+        }
+    }
+}
+
+fn is_byte_vec(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return false;
+    };
+    if segment.ident != "Vec" {
+        return false;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return false;
+    };
+    matches!(args.args.first(), Some(syn::GenericArgument::Type(syn::Type::Path(inner))) if inner.path.is_ident("u8"))
+}
+
+fn deku_skip(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("deku") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(skip)
+}
+
+fn deku_count(field: &syn::Field) -> syn::Result<Option<usize>> {
+    let mut count = None;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("deku") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("count") {
+                let value: syn::LitStr = meta.value()?.parse()?;
+                count = Some(value.parse::<syn::LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("pad_bytes_before") || meta.path.is_ident("pad_bytes_after") {
+                return Err(meta.error("padding is derived from the field offsets"));
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(count)
+}
+
+fn expand_tag_layout(size: Option<syn::LitInt>, input: &mut syn::ItemStruct) -> syn::Result<proc_macro2::TokenStream> {
+    let Some(size) = size else {
+        return Err(syn::Error::new_spanned(&input.ident, "missing #[tag_layout(size = ..)]"));
+    };
+    let struct_size: usize = size.base10_parse()?;
+    let struct_identifier = input.ident.clone();
+    let struct_name = struct_identifier.to_string();
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let syn::Fields::Named(fields) = &mut input.fields else {
+        return Err(syn::Error::new_spanned(&input.ident, "tag layouts need named fields"));
+    };
+
+    let mut assertions = proc_macro2::TokenStream::new();
+    let mut dumps = proc_macro2::TokenStream::new();
+    let mut previous: Option<(usize, FieldSize)> = None;
+    let mut last_read_field = None;
+    for (i, field) in fields.named.iter().enumerate() {
+        if !deku_skip(field)? {
+            last_read_field = Some(i);
+        }
+    }
+    let Some(last_read_field) = last_read_field else {
+        return Err(syn::Error::new_spanned(&input.ident, "tag layouts need at least one field that isn't skipped"));
+    };
+    for (i, field) in fields.named.iter_mut().enumerate() {
+        let identifier = field.ident.clone().unwrap();
+        if deku_skip(field)? {
+            if field.attrs.iter().any(|attr| attr.path().is_ident("offset")) {
+                return Err(syn::Error::new_spanned(&identifier, "skipped fields aren't read, so they have no #[offset(..)]"));
+            }
+            continue;
+        }
+        let Some(index) = field.attrs.iter().position(|attr| attr.path().is_ident("offset")) else {
+            return Err(syn::Error::new_spanned(&identifier, "missing #[offset(..)]"));
+        };
+        let offset: usize = field.attrs.remove(index).parse_args::<syn::LitInt>()?.base10_parse()?;
+
+        let field_size = if is_byte_vec(&field.ty) {
+            match deku_count(field)? {
+                Some(count) => FieldSize::Bytes(count),
+                None => return Err(syn::Error::new_spanned(&field.ty, "byte fields need a literal #[deku(count = \"..\")]")),
+            }
+        } else {
+            deku_count(field)?;
+            FieldSize::Layout(Box::new(field.ty.clone()))
+        };
+
+        let pad_before = match &previous {
+            Some((previous_offset, previous_size)) => {
+                let previous_size = previous_size.to_tokens();
+                let message = format!("{}.{} overlaps the field before it", struct_name, identifier);
+                assertions.extend(quote!{ assert!(#offset >= #previous_offset + #previous_size, #message); });
+                Some(quote!{ #offset - (#previous_offset + #previous_size) }.to_string())
+            },
+            None if offset > 0 => Some(offset.to_string()),
+            None => None,
+        };
+        if let Some(pad_before) = pad_before {
+            field.attrs.push(syn::parse_quote!{ #[deku(pad_bytes_before = #pad_before)] });
+        }
+
+        if i == last_read_field {
+            let end = field_size.to_tokens();
+            let message = format!("{}.{} runs past the end of the tag", struct_name, identifier);
+            assertions.extend(quote!{ assert!(#offset + #end <= #struct_size, #message); });
+            let pad_after = quote!{ #struct_size - (#offset + #end) }.to_string();
+            field.attrs.push(syn::parse_quote!{ #[deku(pad_bytes_after = #pad_after)] });
+        }
+
+        let name = identifier.to_string();
+        let ty = &field.ty;
+        let type_name = quote!{ #ty }.to_string().replace(' ', "");
+        dumps.extend(match &field_size {
+            FieldSize::Bytes(_) => quote!{
+                crate::halo::layout::TagField {
+                    name: #name.to_string(),
+                    offset: #offset,
+                    type_name: #type_name,
+                    value: crate::halo::layout::describe_bytes(&self.#identifier),
+                    fields: Vec::new(),
+                },
+            },
+            FieldSize::Layout(ty) => quote!{
+                crate::halo::layout::TagField {
+                    name: #name.to_string(),
+                    offset: #offset,
+                    type_name: #type_name,
+                    value: <#ty as crate::halo::layout::TagLayout>::describe(&self.#identifier),
+                    fields: <#ty as crate::halo::layout::TagLayout>::fields(&self.#identifier),
+                },
+            },
+        });
+        previous = Some((offset, field_size));
+    }
+
+    Ok(quote!{
+        // offsets are literals, so a field at 0x01 reads as `x >= 1 + y`
+        #[allow(clippy::int_plus_one)]
+        const _: () = { #assertions };
+
+        impl #impl_generics crate::halo::layout::TagLayout for #struct_identifier #type_generics #where_clause {
+            const SIZE: usize = #struct_size;
+
+            fn describe(&self) -> String {
+                #struct_name.to_string()
+            }
+
+            fn fields(&self) -> Vec<crate::halo::layout::TagField> {
+                vec![#dumps]
+            }
+        }
+    })
+}
@@ -0,0 +1,354 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use nalgebra_glm::{make_mat4, make_quat, make_vec3, mat4_to_mat3, mat3_to_quat, quat_to_mat4, translation, Mat4, Quat, Vec3};
+use wasm_bindgen::prelude::*;
+
+use crate::spline::{get_point_hermite, CatmullRomSpline, CatmullRomType, Spline};
+
+const CAMERA_PATH_MAGIC: &[u8; 4] = b"NCPT";
+const CAMERA_PATH_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32, // in seconds
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub fov: f32, // vertical, in radians
+}
+
+impl CameraKeyframe {
+    pub fn world_matrix(&self) -> Mat4 {
+        translation(&self.position) * quat_to_mat4(&self.orientation)
+    }
+}
+
+// A timed sequence of camera keyframes. Positions follow a centripetal Catmull-Rom spline
+// through the keyframes, orientations are blended with SQUAD, and the FOV uses a scalar
+// Catmull-Rom curve, so playback is smooth through every keyframe.
+//
+// The serialized form is little-endian: the magic "NCPT", a u32 version, a u32 keyframe
+// count, then per keyframe the time, position (xyz), orientation (xyzw) and FOV as f32s.
+#[wasm_bindgen(js_name = "CameraPath")]
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+    positions: Option<CatmullRomSpline>,
+    squad_controls: Vec<Quat>,
+}
+
+impl CameraPath {
+    pub fn from_keyframes(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let mut path = CameraPath {
+            keyframes,
+            ..Default::default()
+        };
+        path.rebuild();
+        path
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    pub fn push_keyframe(&mut self, keyframe: CameraKeyframe) {
+        let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+        self.rebuild();
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    fn rebuild(&mut self) {
+        for keyframe in &mut self.keyframes {
+            keyframe.orientation = keyframe.orientation.normalize();
+        }
+        // keep neighboring quaternions in the same hemisphere so we always take the short way
+        for i in 1..self.keyframes.len() {
+            let prev = self.keyframes[i - 1].orientation;
+            let curr = &mut self.keyframes[i].orientation;
+            if prev.dot(curr) < 0.0 {
+                *curr = -*curr;
+            }
+        }
+
+        let points: Vec<Vec3> = self.keyframes.iter().map(|k| k.position).collect();
+        self.positions = CatmullRomSpline::new(points, CatmullRomType::Centripetal).ok();
+
+        let n = self.keyframes.len();
+        self.squad_controls = (0..n).map(|i| {
+            let curr = self.keyframes[i].orientation;
+            if i == 0 || i == n - 1 {
+                curr
+            } else {
+                squad_control_point(&self.keyframes[i - 1].orientation, &curr, &self.keyframes[i + 1].orientation)
+            }
+        }).collect();
+    }
+
+    // finds the keyframe interval containing time, and how far along it we are
+    fn find_interval(&self, time: f32) -> (usize, f32) {
+        let last = self.keyframes.len() - 1;
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        if index == 0 {
+            return (0, 0.0);
+        } else if index > last {
+            return (last.saturating_sub(1), 1.0);
+        }
+        let start = &self.keyframes[index - 1];
+        let end = &self.keyframes[index];
+        let span = end.time - start.time;
+        let t = if span > 0.0 { (time - start.time) / span } else { 1.0 };
+        (index - 1, t.clamp(0.0, 1.0))
+    }
+
+    // returns the interpolated camera state at the given time, clamped to the path's extents
+    pub fn sample(&self, time: f32) -> Option<CameraKeyframe> {
+        if self.keyframes.len() < 2 {
+            return self.keyframes.first().copied();
+        }
+        let (i, t) = self.find_interval(time);
+        let start = &self.keyframes[i];
+        let end = &self.keyframes[i + 1];
+
+        let position = match &self.positions {
+            Some(spline) => spline.evaluate_segment(i, t),
+            None => start.position.lerp(&end.position, t),
+        };
+        let orientation = squad(&start.orientation, &end.orientation, &self.squad_controls[i], &self.squad_controls[i + 1], t);
+
+        // uniform catmull-rom tangents for the fov, mirroring the neighbors at either end
+        let fov_prev = if i > 0 { self.keyframes[i - 1].fov } else { 2.0 * start.fov - end.fov };
+        let fov_next = if i + 2 < self.keyframes.len() { self.keyframes[i + 2].fov } else { 2.0 * end.fov - start.fov };
+        let fov = get_point_hermite(start.fov, (end.fov - fov_prev) * 0.5, end.fov, (fov_next - start.fov) * 0.5, t);
+
+        Some(CameraKeyframe {
+            time: start.time + (end.time - start.time) * t,
+            position,
+            orientation,
+            fov,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(12 + self.keyframes.len() * 36);
+        data.extend_from_slice(CAMERA_PATH_MAGIC);
+        // writes to a Vec can't fail
+        data.write_u32::<LittleEndian>(CAMERA_PATH_VERSION).unwrap();
+        data.write_u32::<LittleEndian>(self.keyframes.len() as u32).unwrap();
+        for keyframe in &self.keyframes {
+            let values = [
+                keyframe.time,
+                keyframe.position.x, keyframe.position.y, keyframe.position.z,
+                keyframe.orientation.i, keyframe.orientation.j, keyframe.orientation.k, keyframe.orientation.w,
+                keyframe.fov,
+            ];
+            for v in values {
+                data.write_f32::<LittleEndian>(v).unwrap();
+            }
+        }
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = Cursor::new(data);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != CAMERA_PATH_MAGIC {
+            return Err(anyhow!("invalid camera path magic {:?}", magic));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != CAMERA_PATH_VERSION {
+            return Err(anyhow!("unsupported camera path version {}", version));
+        }
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut keyframes = Vec::new();
+        for _ in 0..count {
+            let mut values = [0.0; 9];
+            reader.read_f32_into::<LittleEndian>(&mut values)?;
+            keyframes.push(CameraKeyframe {
+                time: values[0],
+                position: make_vec3(&values[1..4]),
+                orientation: make_quat(&values[4..8]),
+                fov: values[8],
+            });
+        }
+        Ok(CameraPath::from_keyframes(keyframes))
+    }
+}
+
+#[wasm_bindgen(js_class = "CameraPath")]
+impl CameraPath {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        CameraPath::default()
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<CameraPath, String> {
+        CameraPath::from_bytes(data).map_err(|err| err.to_string())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    // records the camera's current world matrix (column-major) at the given time
+    pub fn add_keyframe_from_matrix(&mut self, time: f32, world_matrix_slice: &[f32], fov: f32) {
+        assert_eq!(world_matrix_slice.len(), 16);
+        let world_matrix = make_mat4(world_matrix_slice);
+        self.push_keyframe(CameraKeyframe {
+            time,
+            position: world_matrix.column(3).xyz(),
+            orientation: mat3_to_quat(&mat4_to_mat3(&world_matrix)),
+            fov,
+        });
+    }
+
+    pub fn add_keyframe(&mut self, time: f32, position_slice: &[f32], orientation_slice: &[f32], fov: f32) {
+        assert_eq!(position_slice.len(), 3);
+        assert_eq!(orientation_slice.len(), 4);
+        self.push_keyframe(CameraKeyframe {
+            time,
+            position: make_vec3(position_slice),
+            orientation: make_quat(orientation_slice),
+            fov,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.rebuild();
+    }
+
+    pub fn num_keyframes(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn get_duration(&self) -> f32 {
+        self.duration()
+    }
+
+    pub fn get_start_time(&self) -> f32 {
+        self.keyframes.first().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    // fills out_world_matrix with the camera's column-major world matrix at the given time,
+    // and returns its FOV. returns None if the path has no keyframes.
+    pub fn sample_world_matrix(&self, time: f32, out_world_matrix: &mut [f32]) -> Option<f32> {
+        assert_eq!(out_world_matrix.len(), 16);
+        let keyframe = self.sample(time)?;
+        out_world_matrix.copy_from_slice(keyframe.world_matrix().as_slice());
+        Some(keyframe.fov)
+    }
+
+    pub fn get_position(&self, time: f32) -> Option<Vec<f32>> {
+        self.sample(time).map(|k| k.position.as_slice().to_vec())
+    }
+
+    // as xyzw
+    pub fn get_orientation(&self, time: f32) -> Option<Vec<f32>> {
+        self.sample(time).map(|k| k.orientation.coords.as_slice().to_vec())
+    }
+
+    pub fn get_fov(&self, time: f32) -> Option<f32> {
+        self.sample(time).map(|k| k.fov)
+    }
+}
+
+pub fn slerp(a: &Quat, b: &Quat, t: f32) -> Quat {
+    let mut cos_theta = a.dot(b);
+    let mut b = *b;
+    if cos_theta < 0.0 {
+        b = -b;
+        cos_theta = -cos_theta;
+    }
+    if cos_theta > 0.9995 {
+        // too close for a stable slerp, and a normalized lerp is indistinguishable anyway
+        return a.lerp(&b, t).normalize();
+    }
+    let theta = cos_theta.acos();
+    let sin_theta = theta.sin();
+    let wa = ((1.0 - t) * theta).sin() / sin_theta;
+    let wb = (t * theta).sin() / sin_theta;
+    (a * wa + b * wb).normalize()
+}
+
+// the inner control point for SQUAD at curr, given its neighboring keyframes
+fn squad_control_point(prev: &Quat, curr: &Quat, next: &Quat) -> Quat {
+    let inv = curr.conjugate();
+    let log_next = (inv * next).ln();
+    let log_prev = (inv * prev).ln();
+    (curr * ((log_next + log_prev) * -0.25).exp()).normalize()
+}
+
+pub fn squad(q0: &Quat, q1: &Quat, s0: &Quat, s1: &Quat, t: f32) -> Quat {
+    let outer = slerp(q0, q1, t);
+    let inner = slerp(s0, s1, t);
+    slerp(&outer, &inner, 2.0 * t * (1.0 - t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{quat_angle_axis, vec3};
+
+    fn keyframe(time: f32, x: f32, angle: f32, fov: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position: vec3(x, 0.0, 0.0),
+            orientation: quat_angle_axis(angle, &vec3(0.0, 1.0, 0.0)),
+            fov,
+        }
+    }
+
+    fn test_path() -> CameraPath {
+        CameraPath::from_keyframes(vec![
+            keyframe(2.0, 5.0, 1.0, 1.0),
+            keyframe(0.0, 0.0, 0.0, 1.0),
+            keyframe(1.0, 2.0, 0.5, 1.2),
+            keyframe(3.0, 6.0, 1.5, 1.0),
+        ])
+    }
+
+    #[test]
+    fn test_passes_through_keyframes() {
+        let path = test_path();
+        assert_eq!(path.duration(), 3.0);
+        for keyframe in path.keyframes() {
+            let sample = path.sample(keyframe.time).unwrap();
+            assert!(sample.position.metric_distance(&keyframe.position) < 1e-4);
+            assert!(sample.orientation.dot(&keyframe.orientation).abs() > 0.9999);
+            assert!((sample.fov - keyframe.fov).abs() < 1e-4);
+        }
+        // clamped at either end
+        assert_eq!(path.sample(-5.0).unwrap().position, path.keyframes()[0].position);
+        assert_eq!(path.sample(50.0).unwrap().position, path.keyframes()[3].position);
+    }
+
+    #[test]
+    fn test_squad_is_normalized() {
+        let path = test_path();
+        for i in 0..=30 {
+            let sample = path.sample(i as f32 * 0.1).unwrap();
+            assert!((sample.orientation.norm() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let path = test_path();
+        let data = path.to_bytes();
+        assert_eq!(data.len(), 12 + 4 * 36);
+        let loaded = CameraPath::from_bytes(&data).unwrap();
+        assert_eq!(loaded.keyframes(), path.keyframes());
+        assert!(CameraPath::from_bytes(&data[0..20]).is_err());
+        assert!(CameraPath::from_bytes(b"nope").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use core::f32;
+use std::cmp::Ordering;
 
 use nalgebra_glm::{make_mat4, make_vec3, triangle_normal, vec2, vec4, Mat4, Vec3, Vec2};
 use wasm_bindgen::prelude::*;
@@ -46,6 +47,31 @@ impl Plane {
         self.d = transformed.w;
     }
 
+    // Sutherland-Hodgman clip of a convex polygon against this plane, keeping the part on the
+    // positive side (the same side a ConvexHull considers "inside")
+    pub fn clip_polygon(&self, polygon: &[Vec3]) -> Vec<Vec3> {
+        let mut result = Vec::with_capacity(polygon.len() + 1);
+        let Some(last) = polygon.last() else {
+            return result;
+        };
+        let mut prev = *last;
+        let mut prev_dist = self.distance(&prev);
+        for curr in polygon {
+            let curr_dist = self.distance(curr);
+            if curr_dist >= 0.0 {
+                if prev_dist < 0.0 {
+                    result.push(lerp_by_distance(&prev, prev_dist, curr, curr_dist));
+                }
+                result.push(*curr);
+            } else if prev_dist >= 0.0 {
+                result.push(lerp_by_distance(&prev, prev_dist, curr, curr_dist));
+            }
+            prev = *curr;
+            prev_dist = curr_dist;
+        }
+        result
+    }
+
     pub fn major_axis(&self) -> Axis {
         let mut max_axis = f32::NEG_INFINITY;
         let mut axis = 3;
@@ -64,6 +90,12 @@ impl Plane {
     }
 }
 
+// finds the point between a and b where the signed plane distance crosses zero
+fn lerp_by_distance(a: &Vec3, a_dist: f32, b: &Vec3, b_dist: f32) -> Vec3 {
+    let t = a_dist / (a_dist - b_dist);
+    a + (b - a) * t
+}
+
 #[derive(Debug, Clone)]
 pub struct AABB {
     pub min: Vec3,
@@ -226,6 +258,60 @@ impl ConvexHull {
         }
     }
 
+    pub fn clip_polygon(&self, polygon: &[Vec3]) -> Vec<Vec3> {
+        let mut result = polygon.to_vec();
+        for plane in &self.planes {
+            if result.len() < 3 {
+                result.clear();
+                break;
+            }
+            result = plane.clip_polygon(&result);
+        }
+        result
+    }
+
+    // Builds the hull formed by an eye point looking through a convex polygon (e.g. a portal
+    // that's already been clipped to the current frustum). Each edge of the polygon contributes
+    // one side plane passing through the eye. Returns None if the polygon is degenerate as seen
+    // from the eye.
+    pub fn from_eye_and_polygon(eye: &Vec3, polygon: &[Vec3]) -> Option<ConvexHull> {
+        if polygon.len() < 3 {
+            return None;
+        }
+        let centroid: Vec3 = polygon.iter().sum::<Vec3>() / polygon.len() as f32;
+        let mut planes = Vec::with_capacity(polygon.len());
+        for i in 0..polygon.len() {
+            let a = &polygon[i];
+            let b = &polygon[(i + 1) % polygon.len()];
+            let normal = (a - eye).cross(&(b - eye));
+            let mag = normal.magnitude();
+            if mag < 1e-6 {
+                // zero-length edge, or an edge that's collinear with the eye
+                continue;
+            }
+            let mut plane = Plane::new(normal / mag, -normal.dot(eye) / mag);
+            if plane.distance(&centroid) < 0.0 {
+                plane.negate();
+            }
+            planes.push(plane);
+        }
+        if planes.len() < 3 {
+            return None;
+        }
+        Some(ConvexHull { planes })
+    }
+
+    // Narrows this hull to the region visible through the given portal polygon, or returns None
+    // if the portal lies entirely outside of it. The hull's own planes (e.g. the near and far
+    // planes of a view frustum) are kept, with the portal's side planes added to them.
+    pub fn narrow_to_portal(&self, eye: &Vec3, portal: &[Vec3]) -> Option<ConvexHull> {
+        let clipped = self.clip_polygon(portal);
+        let sides = ConvexHull::from_eye_and_polygon(eye, &clipped)?;
+        let mut planes = self.planes.clone();
+        planes.extend(sides.planes);
+        Some(ConvexHull { planes })
+    }
+
     pub fn transform(&mut self, mat: &Mat4) {
         let mut inv_transpose_mat = mat.try_inverse().unwrap();
         inv_transpose_mat.transpose_mut();
@@ -282,6 +368,14 @@ impl ConvexHull {
         self.contains_sphere(&center, radius)
     }
 
+    pub fn js_narrow_to_portal(&self, eye_slice: &[f32], portal_slice: &[f32]) -> Option<ConvexHull> {
+        assert_eq!(eye_slice.len(), 3);
+        assert_eq!(portal_slice.len() % 3, 0);
+        let eye = make_vec3(eye_slice);
+        let portal: Vec<Vec3> = portal_slice.chunks_exact(3).map(make_vec3).collect();
+        self.narrow_to_portal(&eye, &portal)
+    }
+
     pub fn js_transform(&mut self, mat_slice: &[f32]) {
         assert_eq!(mat_slice.iter().count(), 16);
         let mat = make_mat4(mat_slice);
@@ -377,3 +471,434 @@ pub fn point_inside_convex_polygon(point: &Vec2, polygon_vertices: &[Vec2]) -> b
     }
     true
 }
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+    Degenerate,
+}
+
+// shoelace formula, positive for counter-clockwise polygons
+pub fn polygon_signed_area(polygon_vertices: &[Vec2]) -> f32 {
+    let num_verts = polygon_vertices.len();
+    let mut area = 0.0;
+    for i in 0..num_verts {
+        let a = &polygon_vertices[i];
+        let b = &polygon_vertices[(i + 1) % num_verts];
+        area += a.perp(b);
+    }
+    area * 0.5
+}
+
+pub fn polygon_area(polygon_vertices: &[Vec2]) -> f32 {
+    polygon_signed_area(polygon_vertices).abs()
+}
+
+pub fn polygon_winding(polygon_vertices: &[Vec2]) -> Winding {
+    let area = polygon_signed_area(polygon_vertices);
+    if area > f32::EPSILON {
+        Winding::CounterClockwise
+    } else if area < -f32::EPSILON {
+        Winding::Clockwise
+    } else {
+        Winding::Degenerate
+    }
+}
+
+// area-weighted centroid, falling back to the vertex average for degenerate polygons
+pub fn polygon_centroid(polygon_vertices: &[Vec2]) -> Vec2 {
+    let num_verts = polygon_vertices.len();
+    if num_verts == 0 {
+        return Vec2::zeros();
+    }
+    let mut area = 0.0;
+    let mut centroid = Vec2::zeros();
+    for i in 0..num_verts {
+        let a = &polygon_vertices[i];
+        let b = &polygon_vertices[(i + 1) % num_verts];
+        let cross = a.perp(b);
+        area += cross;
+        centroid += (a + b) * cross;
+    }
+    if area.abs() < f32::EPSILON {
+        return polygon_vertices.iter().sum::<Vec2>() / num_verts as f32;
+    }
+    centroid / (3.0 * area)
+}
+
+// twice the signed area of the triangle abc, positive if it turns left
+fn cross_2d(a: &Vec2, b: &Vec2, c: &Vec2) -> f32 {
+    (b - a).perp(&(c - a))
+}
+
+fn point_inside_triangle(p: &Vec2, a: &Vec2, b: &Vec2, c: &Vec2) -> bool {
+    let d0 = cross_2d(a, b, p);
+    let d1 = cross_2d(b, c, p);
+    let d2 = cross_2d(c, a, p);
+    (d0 >= 0.0 && d1 >= 0.0 && d2 >= 0.0) || (d0 <= 0.0 && d1 <= 0.0 && d2 <= 0.0)
+}
+
+// Andrew's monotone chain. the hull is returned counter-clockwise without collinear points
+pub fn convex_hull_2d(points: &[Vec2]) -> Vec<Vec2> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| {
+        a.x.partial_cmp(&b.x)
+            .unwrap_or(Ordering::Equal)
+            .then(a.y.partial_cmp(&b.y).unwrap_or(Ordering::Equal))
+    });
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(sorted.len() * 2);
+    for p in &sorted {
+        while hull.len() >= 2 && cross_2d(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+    let lower_len = hull.len() + 1;
+    for p in sorted.iter().rev().skip(1) {
+        while hull.len() >= lower_len && cross_2d(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+    // the last point is the first one again
+    hull.pop();
+    hull
+}
+
+// Ear clipping triangulation of a simple polygon with optional holes. The
+// returned indices address the outer ring's vertices followed by each hole's
+// vertices, in order, and every triangle is wound counter-clockwise regardless
+// of the input winding.
+pub fn triangulate_polygon(outer: &[Vec2], holes: &[Vec<Vec2>]) -> Vec<u32> {
+    if outer.len() < 3 {
+        return Vec::new();
+    }
+
+    let mut points: Vec<Vec2> = outer.to_vec();
+    let mut ring: Vec<usize> = (0..outer.len()).collect();
+    if polygon_signed_area(outer) < 0.0 {
+        ring.reverse();
+    }
+
+    // holes have to run the opposite way to the outer ring so they can be spliced in
+    let mut hole_rings = Vec::with_capacity(holes.len());
+    for hole in holes {
+        let start = points.len();
+        points.extend_from_slice(hole);
+        if hole.len() < 3 {
+            continue;
+        }
+        let mut hole_ring: Vec<usize> = (start..start + hole.len()).collect();
+        if polygon_signed_area(hole) > 0.0 {
+            hole_ring.reverse();
+        }
+        hole_rings.push(hole_ring);
+    }
+
+    // bridge the rightmost holes first, so later bridges can't cross earlier ones
+    let max_x = |hole_ring: &Vec<usize>| hole_ring.iter()
+        .map(|&i| points[i].x)
+        .fold(f32::NEG_INFINITY, f32::max);
+    hole_rings.sort_by(|a, b| max_x(b).partial_cmp(&max_x(a)).unwrap_or(Ordering::Equal));
+    for hole_ring in &hole_rings {
+        if let Some(bridged) = bridge_hole(&points, &ring, hole_ring) {
+            ring = bridged;
+        }
+    }
+
+    clip_ears(&points, ring)
+}
+
+// Triangulates a planar 3D polygon by projecting it along its dominant axis.
+// Triangles keep the winding of the outer ring.
+pub fn triangulate_polygon_3d(outer: &[Vec3], holes: &[Vec<Vec3>]) -> Vec<u32> {
+    // Newell's method, which is robust to collinear and slightly concave runs
+    let mut normal = Vec3::zeros();
+    for i in 0..outer.len() {
+        let a = &outer[i];
+        let b = &outer[(i + 1) % outer.len()];
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    let axis = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+        Axis::X
+    } else if normal.y.abs() >= normal.z.abs() {
+        Axis::Y
+    } else {
+        Axis::Z
+    };
+
+    let project = |ring: &[Vec3]| -> Vec<Vec2> {
+        ring.iter().map(|v| project_vec3_to_vec2(v, axis)).collect()
+    };
+    let outer_2d = project(outer);
+    let holes_2d: Vec<Vec<Vec2>> = holes.iter().map(|hole| project(hole)).collect();
+    let mut indices = triangulate_polygon(&outer_2d, &holes_2d);
+    if polygon_signed_area(&outer_2d) < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+    indices
+}
+
+// connects a (clockwise) hole to the (counter-clockwise) outer ring with a
+// pair of coincident edges, following Eberly's "Triangulation by Ear Clipping"
+fn bridge_hole(points: &[Vec2], ring: &[usize], hole: &[usize]) -> Option<Vec<usize>> {
+    let (m_pos, m) = hole.iter()
+        .enumerate()
+        .map(|(pos, &i)| (pos, points[i]))
+        .max_by(|(_, a), (_, b)| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal))?;
+
+    // cast a ray from m towards +x and find the closest ring edge it hits. on
+    // a counter-clockwise ring, the edges facing the ray are the ones going up
+    let num_verts = ring.len();
+    let mut closest_x = f32::INFINITY;
+    let mut p_pos = None;
+    for i in 0..num_verts {
+        let j = (i + 1) % num_verts;
+        let a = &points[ring[i]];
+        let b = &points[ring[j]];
+        if a.y > m.y || b.y < m.y || a.y == b.y {
+            continue;
+        }
+        let x = a.x + (m.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if x < m.x || x >= closest_x {
+            continue;
+        }
+        closest_x = x;
+        p_pos = Some(if a.y == m.y {
+            i
+        } else if b.y == m.y || b.x > a.x {
+            j
+        } else {
+            i
+        });
+    }
+    let mut p_pos = p_pos?;
+    let intersection = vec2(closest_x, m.y);
+    let p = points[ring[p_pos]];
+
+    // if any reflex vertices sit inside the triangle (m, intersection, p), they
+    // might hide p from m, so use the one closest in angle to the ray instead
+    if p != intersection {
+        let mut best_tan = f32::INFINITY;
+        for i in 0..num_verts {
+            let v = &points[ring[i]];
+            if i == p_pos || *v == p || v.x < m.x {
+                continue;
+            }
+            let prev = &points[ring[(i + num_verts - 1) % num_verts]];
+            let next = &points[ring[(i + 1) % num_verts]];
+            if cross_2d(prev, v, next) >= 0.0 || !point_inside_triangle(v, &m, &intersection, &p) {
+                continue;
+            }
+            let tan = (v.y - m.y).abs() / (v.x - m.x);
+            if tan < best_tan || (tan == best_tan && v.x > points[ring[p_pos]].x) {
+                best_tan = tan;
+                p_pos = i;
+            }
+        }
+    }
+
+    let mut bridged = Vec::with_capacity(ring.len() + hole.len() + 2);
+    bridged.extend_from_slice(&ring[..=p_pos]);
+    bridged.extend(hole[m_pos..].iter().chain(&hole[..=m_pos]));
+    bridged.extend_from_slice(&ring[p_pos..]);
+    Some(bridged)
+}
+
+fn is_ear(points: &[Vec2], ring: &[usize], pos: usize, allow_degenerate: bool) -> bool {
+    let num_verts = ring.len();
+    let a = &points[ring[(pos + num_verts - 1) % num_verts]];
+    let b = &points[ring[pos]];
+    let c = &points[ring[(pos + 1) % num_verts]];
+    let area = cross_2d(a, b, c);
+    if area < 0.0 || (area == 0.0 && !allow_degenerate) {
+        return false;
+    }
+    if area == 0.0 {
+        return true;
+    }
+    // vertices duplicated by hole bridges share positions with the corners, so
+    // compare by position rather than index
+    ring.iter()
+        .map(|&i| &points[i])
+        .filter(|v| *v != a && *v != b && *v != c)
+        .all(|v| !point_inside_triangle(v, a, b, c))
+}
+
+fn clip_ears(points: &[Vec2], mut ring: Vec<usize>) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(ring.len().saturating_sub(2) * 3);
+    let mut pos = 0;
+    let mut misses = 0;
+    let mut allow_degenerate = false;
+    while ring.len() > 3 {
+        let num_verts = ring.len();
+        if is_ear(points, &ring, pos, allow_degenerate) {
+            let prev = ring[(pos + num_verts - 1) % num_verts];
+            let next = ring[(pos + 1) % num_verts];
+            let curr = ring.remove(pos);
+            if cross_2d(&points[prev], &points[curr], &points[next]) != 0.0 {
+                indices.extend_from_slice(&[prev as u32, curr as u32, next as u32]);
+            }
+            // step back so the previous vertex gets reconsidered
+            pos = (pos + ring.len() - 1) % ring.len();
+            misses = 0;
+            allow_degenerate = false;
+        } else {
+            pos = (pos + 1) % num_verts;
+            misses += 1;
+            if misses >= num_verts {
+                if allow_degenerate {
+                    // self-intersecting input, give up and fan out whatever's left
+                    break;
+                }
+                // let collinear vertices go before giving up
+                allow_degenerate = true;
+                misses = 0;
+            }
+        }
+    }
+    for i in 1..ring.len().saturating_sub(1) {
+        indices.extend_from_slice(&[ring[0] as u32, ring[i] as u32, ring[i + 1] as u32]);
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_square(z: f32) -> Vec<Vec3> {
+        vec![
+            Vec3::new(-1.0, -1.0, z),
+            Vec3::new(1.0, -1.0, z),
+            Vec3::new(1.0, 1.0, z),
+            Vec3::new(-1.0, 1.0, z),
+        ]
+    }
+
+    #[test]
+    fn test_clip_polygon_to_plane() {
+        // keep everything with x >= 0
+        let plane = Plane::new(Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let clipped = plane.clip_polygon(&unit_square(0.0));
+        assert_eq!(clipped.len(), 4);
+        for p in &clipped {
+            assert!(p.x >= 0.0);
+        }
+
+        let plane = Plane::new(Vec3::new(1.0, 0.0, 0.0), -5.0);
+        assert!(plane.clip_polygon(&unit_square(0.0)).is_empty());
+    }
+
+    #[test]
+    fn test_narrow_to_portal() {
+        let eye = Vec3::new(0.0, 0.0, 0.0);
+        let portal = unit_square(-2.0);
+        let hull = ConvexHull::from_eye_and_polygon(&eye, &portal).unwrap();
+        assert_eq!(hull.planes.len(), 4);
+        assert!(hull.contains_point(&Vec3::new(0.0, 0.0, -10.0)));
+        assert!(!hull.contains_point(&Vec3::new(0.0, 0.0, 10.0)));
+        assert!(!hull.contains_point(&Vec3::new(5.0, 0.0, -2.0)));
+
+        // a second portal that only half overlaps the first one should shrink the hull
+        let offset: Vec<Vec3> = unit_square(-4.0).iter().map(|p| p + Vec3::new(2.0, 0.0, 0.0)).collect();
+        let narrowed = hull.narrow_to_portal(&eye, &offset).unwrap();
+        assert!(narrowed.contains_point(&Vec3::new(1.5, 0.0, -4.0)));
+        assert!(!narrowed.contains_point(&Vec3::new(0.5, 0.0, -4.0)));
+        assert!(!narrowed.contains_point(&Vec3::new(2.5, 0.0, -4.0)));
+
+        // and one that's entirely outside should be culled
+        let outside: Vec<Vec3> = unit_square(-2.0).iter().map(|p| p + Vec3::new(10.0, 0.0, 0.0)).collect();
+        assert!(hull.narrow_to_portal(&eye, &outside).is_none());
+    }
+
+    #[test]
+    fn test_narrow_to_portal_keeps_far_plane() {
+        let eye = Vec3::new(0.0, 0.0, 0.0);
+        let mut frustum = ConvexHull::from_eye_and_polygon(&eye, &unit_square(-1.0)).unwrap();
+        // keep everything with z >= -20
+        frustum.planes.push(Plane::new(Vec3::new(0.0, 0.0, 1.0), 20.0));
+        assert!(!frustum.contains_point(&Vec3::new(0.0, 0.0, -30.0)));
+
+        let narrowed = frustum.narrow_to_portal(&eye, &unit_square(-5.0)).unwrap();
+        assert!(narrowed.contains_point(&Vec3::new(0.0, 0.0, -10.0)));
+        assert!(!narrowed.contains_point(&Vec3::new(0.0, 0.0, -30.0)));
+    }
+
+    fn triangulated_area(points: &[Vec2], indices: &[u32]) -> f32 {
+        indices.chunks_exact(3)
+            .map(|t| {
+                let area = cross_2d(&points[t[0] as usize], &points[t[1] as usize], &points[t[2] as usize]) * 0.5;
+                assert!(area > 0.0);
+                area
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_polygon_area_and_centroid() {
+        let square = vec![vec2(0.0, 0.0), vec2(2.0, 0.0), vec2(2.0, 2.0), vec2(0.0, 2.0)];
+        assert_eq!(polygon_signed_area(&square), 4.0);
+        assert_eq!(polygon_winding(&square), Winding::CounterClockwise);
+        assert_eq!(polygon_centroid(&square), vec2(1.0, 1.0));
+
+        let reversed: Vec<Vec2> = square.iter().rev().cloned().collect();
+        assert_eq!(polygon_signed_area(&reversed), -4.0);
+        assert_eq!(polygon_area(&reversed), 4.0);
+        assert_eq!(polygon_winding(&reversed), Winding::Clockwise);
+    }
+
+    #[test]
+    fn test_convex_hull_2d() {
+        let points = vec![
+            vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(2.0, 0.0), vec2(1.0, 0.0),
+            vec2(2.0, 2.0), vec2(0.5, 1.5), vec2(0.0, 2.0), vec2(2.0, 0.0),
+        ];
+        let hull = convex_hull_2d(&points);
+        assert_eq!(hull, vec![vec2(0.0, 0.0), vec2(2.0, 0.0), vec2(2.0, 2.0), vec2(0.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_triangulate_concave_polygon() {
+        // an L shape, given clockwise
+        let l_shape = vec![
+            vec2(0.0, 0.0), vec2(0.0, 2.0), vec2(1.0, 2.0),
+            vec2(1.0, 1.0), vec2(2.0, 1.0), vec2(2.0, 0.0),
+        ];
+        let indices = triangulate_polygon(&l_shape, &[]);
+        assert_eq!(indices.len(), 4 * 3);
+        assert_eq!(triangulated_area(&l_shape, &indices), 3.0);
+    }
+
+    #[test]
+    fn test_triangulate_polygon_with_holes() {
+        let outer = vec![vec2(0.0, 0.0), vec2(6.0, 0.0), vec2(6.0, 4.0), vec2(0.0, 4.0)];
+        let holes = vec![
+            vec![vec2(1.0, 1.0), vec2(2.0, 1.0), vec2(2.0, 3.0), vec2(1.0, 3.0)],
+            vec![vec2(4.0, 1.0), vec2(5.0, 1.0), vec2(5.0, 2.0), vec2(4.0, 2.0)],
+        ];
+        let indices = triangulate_polygon(&outer, &holes);
+        let points: Vec<Vec2> = outer.iter().chain(holes.iter().flatten()).cloned().collect();
+        assert!(indices.iter().all(|&i| (i as usize) < points.len()));
+        assert!((triangulated_area(&points, &indices) - 21.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_triangulate_polygon_3d_keeps_winding() {
+        let square = unit_square(-2.0);
+        let indices = triangulate_polygon_3d(&square, &[]);
+        assert_eq!(indices.len(), 6);
+        let normal = triangle_normal(&square[indices[0] as usize], &square[indices[1] as usize], &square[indices[2] as usize]);
+        assert!(normal.z > 0.0);
+    }
+}
@@ -0,0 +1,617 @@
+use std::io::Cursor;
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt};
+use deku::prelude::*;
+use nalgebra_glm::{quat_normalize, quat_slerp, quat_to_mat4, scaling, translation, vec3, Mat4, Quat, Vec3, Vec4};
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::model::GbxModel;
+use crate::halo::util::trim_tag_string;
+
+const ANIMATION_FLAG_COMPRESSED: u16 = 0x1;
+const ANIMATION_FLAG_25_HZ: u16 = 0x4;
+
+#[tag_layout(size = 0x80)]
+#[wasm_bindgen(js_name = "HaloModelAnimations")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ModelAnimations {
+    // skip the object, unit, weapon, vehicle, device, damage, first person and sound blocks
+    #[offset(0x60)] pub limp_body_node_radius: f32,
+    #[offset(0x64)] pub flags: u16,
+    #[offset(0x68)] pub(crate) nodes: Block<AnimationNode>,
+    #[offset(0x74)] pub(crate) animations: Block<Animation>,
+}
+
+#[wasm_bindgen(js_class = "HaloModelAnimations")]
+impl ModelAnimations {
+    pub fn get_nodes(&self) -> Vec<AnimationNode> {
+        self.nodes.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_animation_names(&self) -> Vec<String> {
+        self.animations.items.as_deref().unwrap_or_default().iter()
+            .map(|animation| animation.get_name())
+            .collect()
+    }
+}
+
+impl ModelAnimations {
+    // model space transforms for every node of the given animation. nodes are
+    // ordered so that parents always come before their children. nodes the
+    // animation doesn't cover keep their bind pose, i.e. the model's defaults
+    pub fn evaluate_node_matrices(&self, animation_index: usize, time: f64, bind_pose: &[NodeTransform]) -> Vec<Mat4> {
+        let nodes = self.nodes.items.as_deref().unwrap_or_default();
+        let mut result: Vec<Mat4> = Vec::with_capacity(nodes.len());
+        let animation = self.animations.items.as_ref()
+            .and_then(|animations| animations.get(animation_index));
+        for (i, node) in nodes.iter().enumerate() {
+            let local = animation.and_then(|animation| animation.sample(i, time))
+                .or_else(|| bind_pose.get(i).copied())
+                .unwrap_or_default()
+                .to_mat4();
+            let world = match node.parent_node_index {
+                parent if parent >= 0 && (parent as usize) < i => result[parent as usize] * local,
+                _ => local,
+            };
+            result.push(world);
+        }
+        result
+    }
+}
+
+#[tag_layout(size = 0x40)]
+#[wasm_bindgen(js_name = "HaloAnimationNode")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct AnimationNode {
+    #[offset(0x20)] pub next_sibling_node_index: i16,
+    #[offset(0x22)] pub first_child_node_index: i16,
+    #[offset(0x24)] pub parent_node_index: i16,
+    #[offset(0x28)] pub node_joint_flags: u32,
+    #[offset(0x2C)] pub base_vector: Vector3D,
+    #[offset(0x38)] pub vector_range: f32,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum AnimationType {
+    Base = 0,
+    Overlay = 1,
+    Replacement = 2,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum AnimationFrameInfoType {
+    None = 0,
+    DxDy = 1,
+    DxDyDyaw = 2,
+    DxDyDzDyaw = 3,
+}
+
+impl_tag_layout!(AnimationType => 2, AnimationFrameInfoType => 2);
+
+#[derive(Debug, Clone, Copy)]
+pub struct NodeTransform {
+    pub rotation: Quat,
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        NodeTransform {
+            rotation: Quat::identity(),
+            translation: Vec3::zeros(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn lerp(&self, other: &NodeTransform, t: f32) -> NodeTransform {
+        NodeTransform {
+            rotation: quat_slerp(&self.rotation, &other.rotation, t),
+            translation: self.translation.lerp(&other.translation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        translation(&self.translation) * quat_to_mat4(&self.rotation) * scaling(&vec3(self.scale, self.scale, self.scale))
+    }
+}
+
+#[tag_layout(size = 0xB4)]
+#[wasm_bindgen(js_name = "HaloAnimation")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Animation {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub animation_type: AnimationType,
+    #[offset(0x22)] pub frame_count: i16,
+    #[offset(0x24)] pub frame_size: i16,
+    #[offset(0x26)] pub frame_info_type: AnimationFrameInfoType,
+    #[offset(0x28)] pub node_list_checksum: i32,
+    #[offset(0x2C)] pub node_count: i16,
+    #[offset(0x2E)] pub loop_frame_index: i16,
+    #[offset(0x30)] pub weight: f32,
+    #[offset(0x34)] pub key_frame_index: i16,
+    #[offset(0x36)] pub second_key_frame_index: i16,
+    #[offset(0x38)] pub next_animation: i16,
+    #[offset(0x3A)] pub flags: u16,
+    #[offset(0x3C)] pub sound: i16,
+    #[offset(0x3E)] pub sound_frame_index: i16,
+    #[offset(0x40)] pub left_foot_frame_index: i8,
+    #[offset(0x41)] pub right_foot_frame_index: i8,
+    #[offset(0x42)] pub first_permutation_index: i16,
+    #[offset(0x44)] pub chance_to_play: f32,
+    #[offset(0x48)] pub(crate) frame_info: TagDataOffset,
+    #[offset(0x5C)] pub(crate) node_translation_flags: [u32; 2],
+    #[offset(0x6C)] pub(crate) node_rotation_flags: [u32; 2],
+    #[offset(0x7C)] pub(crate) node_scale_flags: [u32; 2],
+    #[offset(0x88)] pub(crate) offset_to_compressed_data: u32,
+    #[offset(0x8C)] pub(crate) default_data: TagDataOffset,
+    #[offset(0xA0)] pub(crate) frame_data: TagDataOffset,
+    // decoded by read_data(), frame_count * node_count transforms
+    #[deku(skip)]
+    pub(crate) frames: Vec<NodeTransform>,
+    #[deku(skip)]
+    pub(crate) root_motion: Vec<Vec4>,
+}
+
+#[wasm_bindgen(js_class = "HaloAnimation")]
+impl Animation {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+
+    pub fn get_frame_rate(&self) -> f32 {
+        if self.flags & ANIMATION_FLAG_25_HZ != 0 { 25.0 } else { 30.0 }
+    }
+
+    pub fn get_duration(&self) -> f32 {
+        self.frame_count.max(0) as f32 / self.get_frame_rate()
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & ANIMATION_FLAG_COMPRESSED != 0
+    }
+
+    // root motion for a frame as (dx, dy, dz, dyaw), with unused components left at zero
+    pub fn get_root_motion(&self, frame: usize) -> Option<Vec<f32>> {
+        self.root_motion.get(frame).map(|motion| motion.as_slice().to_vec())
+    }
+}
+
+impl Animation {
+    fn has_flag(flags: &[u32], node: usize) -> bool {
+        flags.get(node / 32).is_some_and(|word| word & (1 << (node % 32)) != 0)
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.node_count.max(0) as usize
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frame_count.max(0) as usize
+    }
+
+    pub(crate) fn read_data(&mut self, data: &mut deku::reader::Reader<Cursor<Vec<u8>>>, offset: i64) -> Result<()> {
+        let frame_info = self.frame_info.read_data(data, offset)?;
+        let default_data = self.default_data.read_data(data, offset)?;
+        let frame_data = self.frame_data.read_data(data, offset)?;
+        self.root_motion = self.decode_frame_info(&frame_info)?;
+        self.frames = if self.is_compressed() {
+            let compressed = frame_data.get(self.offset_to_compressed_data as usize..)
+                .ok_or_else(|| MapReaderError::InvalidTag(format!("compressed data offset {} out of bounds", self.offset_to_compressed_data)))?;
+            self.decode_compressed_frames(compressed)?
+        } else {
+            self.decode_frames(&default_data, &frame_data)?
+        };
+        Ok(())
+    }
+
+    fn decode_frame_info(&self, frame_info: &[u8]) -> Result<Vec<Vec4>> {
+        let mut reader = Cursor::new(frame_info);
+        let mut result = Vec::with_capacity(self.num_frames());
+        for _ in 0..self.num_frames() {
+            let mut motion = Vec4::zeros();
+            match self.frame_info_type {
+                AnimationFrameInfoType::None => {},
+                AnimationFrameInfoType::DxDy => {
+                    motion.x = reader.read_f32::<LittleEndian>()?;
+                    motion.y = reader.read_f32::<LittleEndian>()?;
+                },
+                AnimationFrameInfoType::DxDyDyaw => {
+                    motion.x = reader.read_f32::<LittleEndian>()?;
+                    motion.y = reader.read_f32::<LittleEndian>()?;
+                    motion.w = reader.read_f32::<LittleEndian>()?;
+                },
+                AnimationFrameInfoType::DxDyDzDyaw => {
+                    motion.x = reader.read_f32::<LittleEndian>()?;
+                    motion.y = reader.read_f32::<LittleEndian>()?;
+                    motion.z = reader.read_f32::<LittleEndian>()?;
+                    motion.w = reader.read_f32::<LittleEndian>()?;
+                },
+            }
+            result.push(motion);
+        }
+        Ok(result)
+    }
+
+    // uncompressed animations store every animated channel for every frame,
+    // and the default data holds the channels that never change
+    fn decode_frames(&self, default_data: &[u8], frame_data: &[u8]) -> Result<Vec<NodeTransform>> {
+        let num_nodes = self.num_nodes();
+        let mut defaults = vec![NodeTransform::default(); num_nodes];
+        let mut reader = Cursor::new(default_data);
+        for (i, transform) in defaults.iter_mut().enumerate() {
+            if !Animation::has_flag(&self.node_rotation_flags, i) {
+                transform.rotation = read_rotation(&mut reader)?;
+            }
+            if !Animation::has_flag(&self.node_translation_flags, i) {
+                transform.translation = read_translation(&mut reader)?;
+            }
+            if !Animation::has_flag(&self.node_scale_flags, i) {
+                transform.scale = reader.read_f32::<LittleEndian>()?;
+            }
+        }
+
+        let mut result = Vec::with_capacity(self.num_frames() * num_nodes);
+        for frame in 0..self.num_frames() {
+            let frame_start = frame * self.frame_size.max(0) as usize;
+            let frame_bytes = frame_data.get(frame_start..)
+                .ok_or_else(|| MapReaderError::InvalidTag(format!("frame {} out of bounds", frame)))?;
+            let mut reader = Cursor::new(frame_bytes);
+            for (i, default) in defaults.iter().enumerate() {
+                let mut transform = *default;
+                if Animation::has_flag(&self.node_rotation_flags, i) {
+                    transform.rotation = read_rotation(&mut reader)?;
+                }
+                if Animation::has_flag(&self.node_translation_flags, i) {
+                    transform.translation = read_translation(&mut reader)?;
+                }
+                if Animation::has_flag(&self.node_scale_flags, i) {
+                    transform.scale = reader.read_f32::<LittleEndian>()?;
+                }
+                result.push(transform);
+            }
+        }
+        Ok(result)
+    }
+
+    // Compressed animations start with a header of (keyframe headers, keyframes,
+    // defaults, values) offsets for the rotation, translation and scale channels.
+    // Each node's keyframe header packs a keyframe count in its low 12 bits and
+    // the index of its first keyframe in the rest. Nodes without keyframes use
+    // their default value for the whole animation.
+    fn decode_compressed_frames(&self, data: &[u8]) -> Result<Vec<NodeTransform>> {
+        let mut reader = Cursor::new(data);
+        let mut header = [0u32; 12];
+        for value in header.iter_mut() {
+            *value = reader.read_u32::<LittleEndian>()?;
+        }
+        let num_nodes = self.num_nodes();
+        let num_frames = self.num_frames();
+        let mut result = vec![NodeTransform::default(); num_frames * num_nodes];
+
+        let rotations = CompressedChannel::new(data, &header[0..4], 6);
+        let translations = CompressedChannel::new(data, &header[4..8], 12);
+        let scales = CompressedChannel::new(data, &header[8..12], 4);
+        for node in 0..num_nodes {
+            let node_rotations = rotations.decode(node, num_frames, read_compressed_rotation, quat_slerp)?;
+            let node_translations = translations.decode(node, num_frames, read_translation, |a, b, t| a.lerp(b, t))?;
+            let node_scales = scales.decode(node, num_frames, |r| Ok(r.read_f32::<LittleEndian>()?), |a, b, t| a + (b - a) * t)?;
+            for frame in 0..num_frames {
+                let transform = &mut result[frame * num_nodes + node];
+                transform.rotation = node_rotations[frame];
+                transform.translation = node_translations[frame];
+                transform.scale = node_scales[frame];
+            }
+        }
+        Ok(result)
+    }
+
+    // looping animations return to the loop frame rather than the first one
+    fn frame_at_time(&self, time: f64) -> (usize, usize, f32) {
+        let num_frames = self.num_frames();
+        if num_frames <= 1 {
+            return (0, 0, 0.0);
+        }
+        let loop_start = (self.loop_frame_index.max(0) as usize).min(num_frames - 1);
+        let mut frame = time.max(0.0) * self.get_frame_rate() as f64;
+        if frame >= num_frames as f64 {
+            let loop_length = (num_frames - loop_start) as f64;
+            frame = loop_start as f64 + (frame - num_frames as f64) % loop_length;
+        }
+        let current = (frame as usize).min(num_frames - 1);
+        let next = if current + 1 < num_frames { current + 1 } else { loop_start };
+        (current, next, frame.fract() as f32)
+    }
+
+    // None for nodes this animation doesn't have frames for
+    pub fn sample(&self, node: usize, time: f64) -> Option<NodeTransform> {
+        let num_nodes = self.num_nodes();
+        if node >= num_nodes || self.frames.is_empty() {
+            return None;
+        }
+        let (current, next, t) = self.frame_at_time(time);
+        let a = &self.frames[current * num_nodes + node];
+        let b = &self.frames[next * num_nodes + node];
+        Some(a.lerp(b, t))
+    }
+}
+
+struct CompressedChannel<'a> {
+    data: &'a [u8],
+    keyframe_headers_offset: usize,
+    keyframes_offset: usize,
+    defaults_offset: usize,
+    values_offset: usize,
+    value_size: usize,
+}
+
+impl<'a> CompressedChannel<'a> {
+    fn new(data: &'a [u8], offsets: &[u32], value_size: usize) -> Self {
+        CompressedChannel {
+            data,
+            keyframe_headers_offset: offsets[0] as usize,
+            keyframes_offset: offsets[1] as usize,
+            defaults_offset: offsets[2] as usize,
+            values_offset: offsets[3] as usize,
+            value_size,
+        }
+    }
+
+    fn reader_at(&self, offset: usize) -> Result<Cursor<&'a [u8]>> {
+        match self.data.get(offset..) {
+            Some(bytes) => Ok(Cursor::new(bytes)),
+            None => Err(MapReaderError::InvalidTag(format!("compressed animation offset {} out of bounds", offset)).into()),
+        }
+    }
+
+    fn decode<T, R, L>(&self, node: usize, num_frames: usize, read: R, lerp: L) -> Result<Vec<T>>
+        where T: Copy, R: Fn(&mut Cursor<&'a [u8]>) -> Result<T>, L: Fn(&T, &T, f32) -> T
+    {
+        let keyframe_header = self.reader_at(self.keyframe_headers_offset + node * 4)?.read_u32::<LittleEndian>()?;
+        let keyframe_count = (keyframe_header & 0xFFF) as usize;
+        let first_keyframe = (keyframe_header >> 12) as usize;
+        if keyframe_count == 0 {
+            let default = read(&mut self.reader_at(self.defaults_offset + node * self.value_size)?)?;
+            return Ok(vec![default; num_frames]);
+        }
+
+        let mut keyframes = Vec::with_capacity(keyframe_count);
+        let mut keyframe_reader = self.reader_at(self.keyframes_offset + first_keyframe * 2)?;
+        let mut value_reader = self.reader_at(self.values_offset + first_keyframe * self.value_size)?;
+        for _ in 0..keyframe_count {
+            let frame = keyframe_reader.read_u16::<LittleEndian>()? as usize;
+            keyframes.push((frame, read(&mut value_reader)?));
+        }
+
+        // hold the first and last keyframes, and interpolate between the rest
+        let mut result = Vec::with_capacity(num_frames);
+        let mut k = 0;
+        for frame in 0..num_frames {
+            while k + 1 < keyframes.len() && keyframes[k + 1].0 <= frame {
+                k += 1;
+            }
+            let (frame0, value0) = &keyframes[k];
+            let value = match keyframes.get(k + 1) {
+                Some((frame1, value1)) if frame >= *frame0 => {
+                    lerp(value0, value1, (frame - frame0) as f32 / (frame1 - frame0) as f32)
+                },
+                _ => *value0,
+            };
+            result.push(value);
+        }
+        Ok(result)
+    }
+}
+
+// halo stores node rotations inverted, so conjugate them on the way in
+pub(crate) fn make_rotation(i: f32, j: f32, k: f32, w: f32) -> Quat {
+    quat_normalize(&Quat::new(w, -i, -j, -k))
+}
+
+fn read_rotation<R: ReadBytesExt>(reader: &mut R) -> Result<Quat> {
+    let i = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let j = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let k = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let w = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    Ok(make_rotation(i, j, k, w))
+}
+
+// compressed rotations drop w, which is recovered from the unit length
+fn read_compressed_rotation<R: ReadBytesExt>(reader: &mut R) -> Result<Quat> {
+    let i = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let j = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let k = reader.read_i16::<LittleEndian>()? as f32 / i16::MAX as f32;
+    let w = (1.0 - (i * i + j * j + k * k)).max(0.0).sqrt();
+    Ok(make_rotation(i, j, k, w))
+}
+
+fn read_translation<R: ReadBytesExt>(reader: &mut R) -> Result<Vec3> {
+    let x = reader.read_f32::<LittleEndian>()?;
+    let y = reader.read_f32::<LittleEndian>()?;
+    let z = reader.read_f32::<LittleEndian>()?;
+    Ok(vec3(x, y, z))
+}
+
+#[wasm_bindgen(js_name = "HaloAnimationManager")]
+#[derive(Debug, Clone)]
+pub struct AnimationManager {
+    animations: ModelAnimations,
+    bind_pose: Vec<NodeTransform>,
+    animation_index: usize,
+    animation_time: f64,
+}
+
+#[wasm_bindgen(js_class = "HaloAnimationManager")]
+impl AnimationManager {
+    pub fn new(animations: &ModelAnimations, model: &GbxModel) -> Self {
+        AnimationManager {
+            animations: animations.clone(),
+            bind_pose: model.get_bind_pose(),
+            animation_index: 0,
+            animation_time: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f64) {
+        self.animation_time += delta_time;
+    }
+
+    pub fn set_animation(&mut self, animation_index: usize) {
+        self.animation_index = animation_index;
+        self.animation_time = 0.0;
+    }
+
+    pub fn get_animation_index(&self) -> usize {
+        self.animation_index
+    }
+
+    pub fn get_num_nodes(&self) -> usize {
+        self.animations.nodes.items.as_ref().map_or(0, |nodes| nodes.len())
+    }
+
+    // writes a column-major 4x4 matrix per node
+    pub fn update_node_matrices(&self, node_matrices: &mut [f32]) {
+        let matrices = self.animations.evaluate_node_matrices(self.animation_index, self.animation_time, &self.bind_pose);
+        for (matrix, out) in matrices.iter().zip(node_matrices.chunks_exact_mut(16)) {
+            out.copy_from_slice(matrix.as_slice());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_animation(frames: Vec<NodeTransform>, frame_count: i16, loop_frame_index: i16) -> Animation {
+        let empty_data = TagDataOffset { size: 0, external: 0, file_offset: 0, pointer: 0 };
+        Animation {
+            name: b"test".to_vec(),
+            animation_type: AnimationType::Base,
+            frame_count,
+            frame_size: 0,
+            frame_info_type: AnimationFrameInfoType::None,
+            node_list_checksum: 0,
+            node_count: (frames.len() / frame_count as usize) as i16,
+            loop_frame_index,
+            weight: 1.0,
+            key_frame_index: 0,
+            second_key_frame_index: 0,
+            next_animation: -1,
+            flags: 0,
+            sound: -1,
+            sound_frame_index: 0,
+            left_foot_frame_index: 0,
+            right_foot_frame_index: 0,
+            first_permutation_index: 0,
+            chance_to_play: 1.0,
+            frame_info: empty_data.clone(),
+            node_translation_flags: [0, 0],
+            node_rotation_flags: [0, 0],
+            node_scale_flags: [0, 0],
+            offset_to_compressed_data: 0,
+            default_data: empty_data.clone(),
+            frame_data: empty_data,
+            frames,
+            root_motion: Vec::new(),
+        }
+    }
+
+    fn translated(x: f32) -> NodeTransform {
+        NodeTransform { translation: vec3(x, 0.0, 0.0), ..Default::default() }
+    }
+
+    #[test]
+    fn test_decode_uncompressed_frames() {
+        let mut animation = test_animation(Vec::new(), 2, 0);
+        animation.node_count = 2;
+        // node 0 has animated translation, node 1 has animated rotation
+        animation.node_translation_flags = [0b01, 0];
+        animation.node_rotation_flags = [0b10, 0];
+        animation.frame_size = 12 + 8;
+
+        let mut default_data = Vec::new();
+        // node 0: rotation, then scale
+        for v in [0i16, 0, 0, i16::MAX] {
+            default_data.extend_from_slice(&v.to_le_bytes());
+        }
+        default_data.extend_from_slice(&1.0f32.to_le_bytes());
+        // node 1: translation, then scale
+        for v in [0.0f32, 0.0, 5.0, 2.0] {
+            default_data.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let mut frame_data = Vec::new();
+        for x in [1.0f32, 3.0] {
+            for v in [x, 0.0, 0.0] {
+                frame_data.extend_from_slice(&v.to_le_bytes());
+            }
+            for v in [0i16, 0, 0, i16::MAX] {
+                frame_data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        animation.frames = animation.decode_frames(&default_data, &frame_data).unwrap();
+        assert_eq!(animation.frames.len(), 4);
+        assert_eq!(animation.frames[2].translation, vec3(3.0, 0.0, 0.0));
+        assert_eq!(animation.frames[3].translation, vec3(0.0, 0.0, 5.0));
+        assert_eq!(animation.frames[3].scale, 2.0);
+
+        // halfway between the two frames
+        let sample = animation.sample(0, 0.5 / 30.0).unwrap();
+        assert!((sample.translation.x - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_frame_looping() {
+        let animation = test_animation(vec![translated(0.0), translated(1.0), translated(2.0), translated(3.0)], 4, 2);
+        assert_eq!(animation.frame_at_time(1.5 / 30.0).0, 1);
+        // past the end, playback wraps around to the loop frame
+        assert_eq!(animation.frame_at_time(4.5 / 30.0).0, 2);
+        assert_eq!(animation.frame_at_time(5.5 / 30.0).0, 3);
+        assert_eq!(animation.frame_at_time(3.5 / 30.0).1, 2);
+    }
+
+    #[test]
+    fn test_node_hierarchy() {
+        let node = AnimationNode {
+            next_sibling_node_index: -1,
+            first_child_node_index: 1,
+            parent_node_index: -1,
+            node_joint_flags: 0,
+            base_vector: Vector3D { i: 0.0, j: 0.0, k: 0.0 },
+            vector_range: 0.0,
+        };
+        let mut child = node.clone();
+        child.parent_node_index = 0;
+        child.first_child_node_index = -1;
+
+        let animation = test_animation(vec![translated(1.0), translated(2.0)], 1, 0);
+        let animations = ModelAnimations {
+            limp_body_node_radius: 0.0,
+            flags: 0,
+            nodes: Block { count: 2, base_pointer: 0, items: Some(vec![node, child]) },
+            animations: Block { count: 1, base_pointer: 0, items: Some(vec![animation]) },
+        };
+        let matrices = animations.evaluate_node_matrices(0, 0.0, &[]);
+        assert_eq!(matrices[1].column(3)[0], 3.0);
+
+        // without a matching animation, nodes stay in the bind pose
+        let bind_pose = [translated(5.0), translated(7.0)];
+        let matrices = animations.evaluate_node_matrices(1, 0.0, &bind_pose);
+        assert_eq!(matrices[1].column(3)[0], 12.0);
+    }
+}
@@ -1,9 +1,12 @@
 use std::io::{Cursor, Seek};
 
 use deku::prelude::*;
+use noclip_macros::tag_layout;
 use wasm_bindgen::prelude::*;
-use crate::{halo::common::*, unity::types::common::NullTerminatedAsciiString};
+use crate::halo::common::*;
 use crate::halo::bitmap_utils;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::util::trim_tag_string;
 
 #[wasm_bindgen(js_name = "HaloBitmapType")]
 #[derive(Debug, Clone, Copy, DekuRead)]
@@ -66,22 +69,68 @@ pub enum BitmapSpriteUsage {
     DoubleMultiply = 0x2,
 }
 
-#[derive(Debug, Clone, DekuRead)]
+// a rectangle of one of the bitmap's images, in normalized texture coordinates
+#[tag_layout(size = 0x20)]
+#[wasm_bindgen(js_name = "HaloBitmapSprite")]
+#[derive(Debug, Copy, Clone, DekuRead)]
 pub struct Sprite {
-    pub bitmap_index: u16,
-    pub left: f32,
-    pub right: f32,
-    pub top: f32,
-    pub bottom: f32,
-    pub registration_point: Point2D,
+    #[offset(0x00)] pub bitmap_index: u16,
+    #[offset(0x08)] pub left: f32,
+    #[offset(0x0C)] pub right: f32,
+    #[offset(0x10)] pub top: f32,
+    #[offset(0x14)] pub bottom: f32,
+    #[offset(0x18)] pub registration_point: Point2D,
+}
+
+impl Sprite {
+    fn whole_bitmap(bitmap_index: u16) -> Self {
+        Sprite { bitmap_index, left: 0.0, right: 1.0, top: 0.0, bottom: 1.0, registration_point: Point2D { x: 0.5, y: 0.5 } }
+    }
 }
 
+// a sequence is either a run of whole bitmaps (e.g. an animated texture) or,
+// for sprite bitmaps, a list of sprites packed into the bitmaps
+#[tag_layout(size = 0x40)]
+#[wasm_bindgen(js_name = "HaloBitmapSequence")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct BitmapGroup {
-    pub name: NullTerminatedAsciiString,
-    pub first_bitmap_index: u16,
-    pub bitmap_count: u16,
-    pub sprites: Block<Sprite>,
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub first_bitmap_index: u16,
+    #[offset(0x22)] pub bitmap_count: u16,
+    #[offset(0x34)] pub(crate) sprites: Block<Sprite>,
+}
+
+#[wasm_bindgen(js_class = "HaloBitmapSequence")]
+impl BitmapGroup {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+
+    pub fn get_sprites(&self) -> Vec<Sprite> {
+        self.sprites.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        match self.sprites.items.as_deref() {
+            Some(sprites) if !sprites.is_empty() => sprites.len(),
+            _ => self.bitmap_count as usize,
+        }
+    }
+
+    // frames wrap around, so callers can pass an ever increasing frame number
+    pub fn get_frame(&self, frame: usize) -> Option<Sprite> {
+        let frame_count = self.get_frame_count();
+        if frame_count == 0 {
+            return None;
+        }
+        let frame = frame % frame_count;
+        match self.sprites.items.as_deref() {
+            Some(sprites) if !sprites.is_empty() => Some(sprites[frame]),
+            _ => Some(Sprite::whole_bitmap(self.first_bitmap_index + frame as u16)),
+        }
+    }
 }
 
 #[wasm_bindgen(js_name = "HaloBitmapClass")]
@@ -229,24 +278,34 @@ impl BitmapFormat {
     }
 }
 
+impl_tag_layout!(
+    BitmapType => 2,
+    BitmapEncodingFormat => 2,
+    BitmapUsage => 2,
+    BitmapSpriteBudgetSize => 2,
+    BitmapSpriteUsage => 2,
+    BitmapClass => 4,
+    BitmapDataType => 2,
+    BitmapFormat => 2,
+);
+
+#[tag_layout(size = 0x30)]
 #[wasm_bindgen(js_name = "HaloBitmapMetadata")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct BitmapData {
-    pub bitmap_class: BitmapClass,
-    pub width: u16,
-    pub height: u16,
-    pub depth: u16,
-    pub bitmap_type: BitmapDataType,
-    pub format: BitmapFormat,
-    pub flags: u16,
-    pub registration_point: Point2DInt,
-    pub mipmap_count: u16,
-    #[deku(pad_bytes_before = "2")]
-    pub pixel_data_offset: Pointer,
-    pub pixel_data_size: u32,
-    pub bitmap_tag_id: u32,
-    #[deku(pad_bytes_after = "8")]
-    pub pointer: Pointer,
+    #[offset(0x00)] pub bitmap_class: BitmapClass,
+    #[offset(0x04)] pub width: u16,
+    #[offset(0x06)] pub height: u16,
+    #[offset(0x08)] pub depth: u16,
+    #[offset(0x0A)] pub bitmap_type: BitmapDataType,
+    #[offset(0x0C)] pub format: BitmapFormat,
+    #[offset(0x0E)] pub flags: u16,
+    #[offset(0x10)] pub registration_point: Point2DInt,
+    #[offset(0x14)] pub mipmap_count: u16,
+    #[offset(0x18)] pub pixel_data_offset: Pointer,
+    #[offset(0x1C)] pub pixel_data_size: u32,
+    #[offset(0x20)] pub bitmap_tag_id: u32,
+    #[offset(0x24)] pub pointer: Pointer,
 }
 
 #[wasm_bindgen(js_class = "HaloBitmapMetadata")]
@@ -256,30 +315,32 @@ impl BitmapData {
     }
 }
 
+#[tag_layout(size = 0x6C)]
 #[wasm_bindgen(js_name = "HaloBitmap")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct Bitmap {
-    pub bitmap_type: BitmapType,
-    pub encoding_format: BitmapEncodingFormat,
-    pub usage: BitmapUsage,
-    pub flags: u16,
-    pub _detail_fade_factor: f32,
-    pub _sharpen_amount: f32,
-    pub _bump_height: f32,
-    _sprite_budget_size: BitmapSpriteBudgetSize,
-    _sprite_budget_count: u16,
-    pub _color_plate_width: u16, // non-cached
-    pub _color_plate_height: u16, // non-cached
-    _compressed_color_plate_pointer: Pointer, // non-cached
-    _processed_pixel_data: Pointer, // non-cached
-    pub _blur_filter_size: f32,
-    pub _alpha_bias: f32,
-    pub mipmap_count: u16,
-    pub sprite_usage: BitmapSpriteUsage,
-    pub sprite_spacing: u16,
-    #[deku(pad_bytes_before = "34")]
-    pub(crate) bitmap_group_sequence: Block<BitmapGroup>,
-    pub(crate) data: Block<BitmapData>,
+    #[offset(0x00)] pub bitmap_type: BitmapType,
+    #[offset(0x02)] pub encoding_format: BitmapEncodingFormat,
+    #[offset(0x04)] pub usage: BitmapUsage,
+    #[offset(0x06)] pub flags: u16,
+    #[offset(0x08)] pub _detail_fade_factor: f32,
+    #[offset(0x0C)] pub _sharpen_amount: f32,
+    #[offset(0x10)] pub _bump_height: f32,
+    #[offset(0x14)] _sprite_budget_size: BitmapSpriteBudgetSize,
+    #[offset(0x16)] _sprite_budget_count: u16,
+    #[offset(0x18)] pub _color_plate_width: u16, // non-cached
+    #[offset(0x1A)] pub _color_plate_height: u16, // non-cached
+    #[offset(0x1C)] _compressed_color_plate_data: TagDataOffset, // non-cached
+    #[offset(0x30)] _processed_pixel_data: TagDataOffset, // non-cached
+    #[offset(0x44)] pub _blur_filter_size: f32,
+    #[offset(0x48)] pub _alpha_bias: f32,
+    #[offset(0x4C)] pub mipmap_count: u16,
+    #[offset(0x4E)] pub sprite_usage: BitmapSpriteUsage,
+    #[offset(0x50)] pub sprite_spacing: u16,
+    #[offset(0x54)] pub(crate) bitmap_group_sequence: Block<BitmapGroup>,
+    #[offset(0x60)] pub(crate) data: Block<BitmapData>,
+    #[deku(skip)]
+    pub(crate) path: String, // the tag path, for looking up external data in the resource maps
 }
 
 #[wasm_bindgen(js_class = "HaloBitmap")]
@@ -291,15 +352,36 @@ impl Bitmap {
     pub fn get_tag_id(&self) -> u32 {
         self.data.items.as_ref().unwrap()[0].bitmap_tag_id
     }
+
+    pub fn get_sequence_count(&self) -> usize {
+        self.bitmap_group_sequence.items.as_ref().map_or(0, |sequences| sequences.len())
+    }
+
+    pub fn get_sequences(&self) -> Vec<BitmapGroup> {
+        self.bitmap_group_sequence.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_sequence(&self, index: usize) -> Option<BitmapGroup> {
+        self.bitmap_group_sequence.items.as_ref()?.get(index).cloned()
+    }
+
+    // the bitmap index and uv rectangle to draw for a frame of a sequence.
+    // bitmaps without sequences just have the one frame
+    pub fn get_sequence_frame(&self, sequence_index: usize, frame: usize) -> Option<Sprite> {
+        match self.bitmap_group_sequence.items.as_ref() {
+            Some(sequences) if !sequences.is_empty() => sequences.get(sequence_index)?.get_frame(frame),
+            _ => Some(Sprite::whole_bitmap(0)),
+        }
+    }
 }
 
-pub fn get_and_convert_bitmap_data(reader: &mut deku::reader::Reader<Cursor<Vec<u8>>>, bitmap_data: &BitmapData) -> Vec<u8> {
+pub fn get_and_convert_bitmap_data(reader: &mut deku::reader::Reader<Cursor<Vec<u8>>>, bitmap_data: &BitmapData) -> anyhow::Result<Vec<u8>> {
     let offset = bitmap_data.pixel_data_offset as u64;
     let length = bitmap_data.pixel_data_size as usize;
     let mut bytes = vec![0; length];
-    reader.seek(std::io::SeekFrom::Start(offset)).unwrap();
-    reader.read_bytes(length, &mut bytes, deku::ctx::Order::Msb0).unwrap();
-    match bitmap_data.format {
+    reader.seek(std::io::SeekFrom::Start(offset))?;
+    reader.read_bytes(length, &mut bytes, deku::ctx::Order::Msb0)?;
+    Ok(match bitmap_data.format {
         BitmapFormat::P8 | BitmapFormat::P8Bump => bitmap_utils::convert_p8_data(&bytes),
         BitmapFormat::A8r8g8b8 => bitmap_utils::convert_a8r8g8b8_data(&bytes),
         BitmapFormat::X8r8g8b8 => bitmap_utils::convert_x8r8g8b8_data(&bytes),
@@ -308,5 +390,38 @@ pub fn get_and_convert_bitmap_data(reader: &mut deku::reader::Reader<Cursor<Vec<
         BitmapFormat::A8y8 => bitmap_utils::convert_a8y8_data(&bytes),
         BitmapFormat::R5g6b5 => bitmap_utils::convert_r5g6b5_data(&bytes),
         _ => bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    fn sequence(first_bitmap_index: u16, bitmap_count: u16) -> BitmapGroup {
+        read_test_tag(&[
+            (0x00, b"fire"),
+            (0x20, &first_bitmap_index.to_le_bytes()),
+            (0x22, &bitmap_count.to_le_bytes()),
+        ])
+    }
+
+    #[test]
+    fn test_sequence_frames() {
+        let mut animated = sequence(2, 3);
+        assert_eq!(animated.get_name(), "fire");
+        animated.sprites.items = Some(Vec::new());
+        assert_eq!(animated.get_frame_count(), 3);
+        assert_eq!(animated.get_frame(4).unwrap().bitmap_index, 3);
+        assert_eq!(animated.get_frame(0).unwrap().right, 1.0);
+
+        let sprite: Sprite = read_test_tag(&[(0x00, &1u16.to_le_bytes()), (0x0C, &0.5f32.to_le_bytes())]);
+        let mut sprites = sequence(0, 1);
+        sprites.sprites.items = Some(vec![Sprite::whole_bitmap(0), sprite]);
+        assert_eq!(sprites.get_frame_count(), 2);
+        let frame = sprites.get_frame(3).unwrap();
+        assert_eq!((frame.bitmap_index, frame.right), (1, 0.5));
+
+        assert!(sequence(0, 0).get_frame(0).is_none());
     }
 }
@@ -0,0 +1,344 @@
+use deku::prelude::*;
+use nalgebra_glm::{vec3, Vec3};
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+
+// children with the high bit set are leaves, and -1 is solid space outside the bsp
+const BSP3D_LEAF_FLAG: u32 = 0x80000000;
+const BSP3D_NULL_CHILD: u32 = 0xFFFFFFFF;
+
+// how far below a point to look for the ground
+const GROUND_SEARCH_DISTANCE: f32 = 1000.0;
+
+#[tag_layout(size = 0x60)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct CollisionBSP {
+    #[offset(0x00)] pub bsp3d_nodes: Block<BSP3DNode>,
+    #[offset(0x0C)] pub planes: Block<Plane3D>,
+    #[offset(0x18)] pub leaves: Block<CollisionLeaf>,
+    #[offset(0x24)] pub bsp2d_references: Block<BSP2DReference>,
+    #[offset(0x30)] pub bsp2d_nodes: Block<BSP2DNode>,
+    #[offset(0x3C)] pub surfaces: Block<CollisionSurface>,
+    #[offset(0x48)] pub edges: Block<CollisionEdge>,
+    #[offset(0x54)] pub vertices: Block<CollisionVertex>,
+}
+
+#[tag_layout(size = 0x0C)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct BSP3DNode {
+    #[offset(0x00)] pub plane: u32,
+    #[offset(0x04)] pub back_child: u32,
+    #[offset(0x08)] pub front_child: u32,
+}
+
+#[tag_layout(size = 0x08)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct CollisionLeaf {
+    #[offset(0x00)] pub flags: u16,
+    #[offset(0x02)] pub bsp2d_reference_count: i16,
+    #[offset(0x04)] pub first_bsp2d_reference: i32,
+}
+
+#[tag_layout(size = 0x08)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct BSP2DReference {
+    #[offset(0x00)] pub plane: u32,
+    #[offset(0x04)] pub bsp2d_node: u32,
+}
+
+#[tag_layout(size = 0x14)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct BSP2DNode {
+    #[offset(0x00)] pub plane_i: f32,
+    #[offset(0x04)] pub plane_j: f32,
+    #[offset(0x08)] pub plane_d: f32,
+    #[offset(0x0C)] pub left_child: u32,
+    #[offset(0x10)] pub right_child: u32,
+}
+
+#[tag_layout(size = 0x0C)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct CollisionSurface {
+    #[offset(0x00)] pub plane: u32,
+    #[offset(0x04)] pub first_edge: i32,
+    #[offset(0x08)] pub flags: u8,
+    #[offset(0x09)] pub breakable_surface: i8,
+    #[offset(0x0A)] pub material: i16,
+}
+
+#[tag_layout(size = 0x18)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct CollisionEdge {
+    #[offset(0x00)] pub start_vertex: i32,
+    #[offset(0x04)] pub end_vertex: i32,
+    #[offset(0x08)] pub forward_edge: i32,
+    #[offset(0x0C)] pub reverse_edge: i32,
+    #[offset(0x10)] pub left_surface: i32,
+    #[offset(0x14)] pub right_surface: i32,
+}
+
+#[tag_layout(size = 0x10)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct CollisionVertex {
+    #[offset(0x00)] pub point: Point3D,
+    #[offset(0x0C)] pub first_edge: i32,
+}
+
+#[wasm_bindgen(js_name = "HaloCollisionHit")]
+#[derive(Debug, Clone, Copy)]
+pub struct CollisionHit {
+    pub distance: f32,
+    pub position: Point3D,
+    // faces back towards the ray's origin
+    pub normal: Vector3D,
+}
+
+impl CollisionBSP {
+    pub fn read_items(&mut self, data: &mut Reader<std::io::Cursor<Vec<u8>>>, offset: i64) -> anyhow::Result<()> {
+        self.bsp3d_nodes.read_items(data, offset)?;
+        self.planes.read_items(data, offset)?;
+        self.leaves.read_items(data, offset)?;
+        self.bsp2d_references.read_items(data, offset)?;
+        self.bsp2d_nodes.read_items(data, offset)?;
+        self.surfaces.read_items(data, offset)?;
+        self.edges.read_items(data, offset)?;
+        self.vertices.read_items(data, offset)?;
+        Ok(())
+    }
+
+    fn get_plane(&self, node: &BSP3DNode) -> Option<&Plane3D> {
+        self.planes.items.as_ref()?.get((node.plane & !BSP3D_LEAF_FLAG) as usize)
+    }
+
+    pub fn find_leaf(&self, point: &Point3D) -> Option<u32> {
+        let nodes = self.bsp3d_nodes.items.as_ref()?;
+        if nodes.is_empty() {
+            return None;
+        }
+        let mut child = 0;
+        // every step moves down the tree, so this can't take more than nodes.len() steps
+        for _ in 0..nodes.len() {
+            let node = nodes.get(child as usize)?;
+            let plane = self.get_plane(node)?;
+            child = if plane.distance_to(point) >= 0.0 { node.front_child } else { node.back_child };
+            if child == BSP3D_NULL_CHILD {
+                return None;
+            } else if child & BSP3D_LEAF_FLAG != 0 {
+                return Some(child & !BSP3D_LEAF_FLAG);
+            }
+        }
+        None
+    }
+
+    pub fn is_point_in_solid(&self, point: &Point3D) -> bool {
+        self.find_leaf(point).is_none()
+    }
+
+    // finds the first point along the ray where it passes from open space into solid space
+    pub fn ray_cast(&self, origin: &Point3D, direction: &Vector3D, max_distance: f32) -> Option<CollisionHit> {
+        let origin = vec3(origin.x, origin.y, origin.z);
+        let direction = vec3(direction.i, direction.j, direction.k).try_normalize(f32::EPSILON)?;
+        if self.bsp3d_nodes.items.as_ref()?.is_empty() {
+            return None;
+        }
+        let (distance, normal) = self.trace(0, &origin, &direction, 0.0, max_distance, None, 0)?;
+        let position = origin + direction * distance;
+        let normal = normal.unwrap_or(-direction);
+        Some(CollisionHit {
+            distance,
+            position: Point3D { x: position.x, y: position.y, z: position.z },
+            normal: Vector3D { i: normal.x, j: normal.y, k: normal.z },
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn trace(&self, child: u32, origin: &Vec3, direction: &Vec3, start: f32, end: f32, entry_normal: Option<Vec3>, depth: usize) -> Option<(f32, Option<Vec3>)> {
+        if child == BSP3D_NULL_CHILD {
+            return Some((start, entry_normal));
+        } else if child & BSP3D_LEAF_FLAG != 0 {
+            return None;
+        }
+        let nodes = self.bsp3d_nodes.items.as_ref()?;
+        // guard against malformed trees with cycles
+        if depth > nodes.len() {
+            return None;
+        }
+        let node = nodes.get(child as usize)?;
+        let plane = self.get_plane(node)?;
+        let normal = vec3(plane.norm.i, plane.norm.j, plane.norm.k);
+        let start_distance = normal.dot(&(origin + direction * start)) - plane.w;
+        let end_distance = normal.dot(&(origin + direction * end)) - plane.w;
+
+        if start_distance >= 0.0 && end_distance >= 0.0 {
+            return self.trace(node.front_child, origin, direction, start, end, entry_normal, depth + 1);
+        } else if start_distance < 0.0 && end_distance < 0.0 {
+            return self.trace(node.back_child, origin, direction, start, end, entry_normal, depth + 1);
+        }
+
+        // the segment crosses this plane, so check the near side first
+        let split = start + (end - start) * (start_distance / (start_distance - end_distance));
+        let (near, far, far_normal) = if start_distance >= 0.0 {
+            (node.front_child, node.back_child, normal)
+        } else {
+            (node.back_child, node.front_child, -normal)
+        };
+        self.trace(near, origin, direction, start, split, entry_normal, depth + 1)
+            .or_else(|| self.trace(far, origin, direction, split, end, Some(far_normal), depth + 1))
+    }
+
+    pub fn get_ground_height(&self, point: &Point3D) -> Option<f32> {
+        let down = Vector3D { i: 0.0, j: 0.0, k: -1.0 };
+        self.ray_cast(point, &down, GROUND_SEARCH_DISTANCE)
+            .map(|hit| hit.position.z)
+    }
+
+    // walks the edge ring around a surface to get its polygon
+    pub fn get_surface_vertices(&self, surface_index: usize) -> Option<Vec<Point3D>> {
+        let surface = self.surfaces.items.as_ref()?.get(surface_index)?;
+        let edges = self.edges.items.as_ref()?;
+        let vertices = self.vertices.items.as_ref()?;
+        let mut result = Vec::new();
+        let mut edge_index = surface.first_edge;
+        for _ in 0..edges.len() {
+            let edge = edges.get(edge_index as usize)?;
+            if edge.left_surface == surface_index as i32 {
+                result.push(vertices.get(edge.start_vertex as usize)?.point);
+                edge_index = edge.forward_edge;
+            } else {
+                result.push(vertices.get(edge.end_vertex as usize)?.point);
+                edge_index = edge.reverse_edge;
+            }
+            if edge_index == surface.first_edge {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+#[tag_layout(size = 0x298)]
+#[wasm_bindgen(js_name = "HaloModelCollisionGeometry")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ModelCollisionGeometry {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x268)] pub pathfinding_box_x: Bounds,
+    #[offset(0x270)] pub pathfinding_box_y: Bounds,
+    #[offset(0x278)] pub pathfinding_box_z: Bounds,
+    #[offset(0x28C)] pub(crate) nodes: Block<CollisionNode>,
+}
+
+#[tag_layout(size = 0x40)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct CollisionNode {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub region: i16,
+    #[offset(0x22)] pub parent_node: i16,
+    #[offset(0x24)] pub next_sibling_node: i16,
+    #[offset(0x26)] pub first_child_node: i16,
+    #[offset(0x34)] pub bsps: Block<CollisionBSP>,
+}
+
+impl ModelCollisionGeometry {
+    fn bsps(&self) -> impl Iterator<Item = &CollisionBSP> {
+        self.nodes.items.as_deref().unwrap_or_default().iter()
+            .flat_map(|node| node.bsps.items.as_deref().unwrap_or_default().iter())
+    }
+}
+
+// node bsps are tested in model space, so this is only accurate for models
+// whose nodes all sit at the origin, like most scenery
+#[wasm_bindgen(js_class = "HaloModelCollisionGeometry")]
+impl ModelCollisionGeometry {
+    // origin and direction are xyz triples
+    pub fn ray_cast(&self, origin: &[f32], direction: &[f32], max_distance: f32) -> Option<CollisionHit> {
+        assert_eq!(origin.len(), 3);
+        assert_eq!(direction.len(), 3);
+        let origin = Point3D { x: origin[0], y: origin[1], z: origin[2] };
+        let direction = Vector3D { i: direction[0], j: direction[1], k: direction[2] };
+        self.bsps()
+            .filter_map(|bsp| bsp.ray_cast(&origin, &direction, max_distance))
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    pub fn is_point_in_solid(&self, x: f32, y: f32, z: f32) -> bool {
+        let point = Point3D { x, y, z };
+        self.bsps().any(|bsp| bsp.is_point_in_solid(&point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block<T>(items: Vec<T>) -> Block<T> {
+        Block { count: items.len() as u32, base_pointer: 0, items: Some(items) }
+    }
+
+    fn plane(i: f32, j: f32, k: f32, w: f32) -> Plane3D {
+        Plane3D { norm: Vector3D { i, j, k }, w }
+    }
+
+    // splits space at x = 0 and then y = 0, with solid space behind x = 0
+    fn test_bsp() -> CollisionBSP {
+        let nodes = vec![
+            BSP3DNode { plane: 0, back_child: BSP3D_NULL_CHILD, front_child: 1 },
+            BSP3DNode { plane: 1, back_child: BSP3D_LEAF_FLAG, front_child: BSP3D_LEAF_FLAG | 1 },
+        ];
+        let planes = vec![plane(1.0, 0.0, 0.0, 0.0), plane(0.0, 1.0, 0.0, 0.0)];
+        CollisionBSP {
+            bsp3d_nodes: block(nodes),
+            planes: block(planes),
+            leaves: block(Vec::new()),
+            bsp2d_references: block(Vec::new()),
+            bsp2d_nodes: block(Vec::new()),
+            surfaces: block(Vec::new()),
+            edges: block(Vec::new()),
+            vertices: block(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_find_leaf() {
+        let bsp = test_bsp();
+        assert_eq!(bsp.find_leaf(&Point3D { x: 1.0, y: -1.0, z: 0.0 }), Some(0));
+        assert_eq!(bsp.find_leaf(&Point3D { x: 1.0, y: 1.0, z: 0.0 }), Some(1));
+        assert_eq!(bsp.find_leaf(&Point3D { x: -1.0, y: 1.0, z: 0.0 }), None);
+        assert!(bsp.is_point_in_solid(&Point3D { x: -1.0, y: 0.0, z: 0.0 }));
+    }
+
+    #[test]
+    fn test_ray_cast() {
+        let bsp = test_bsp();
+        let origin = Point3D { x: 5.0, y: 1.0, z: 0.0 };
+        let hit = bsp.ray_cast(&origin, &Vector3D { i: -2.0, j: 0.0, k: 0.0 }, 100.0).unwrap();
+        assert!((hit.distance - 5.0).abs() < 1e-5);
+        assert!(hit.position.x.abs() < 1e-5);
+        assert_eq!(hit.normal.i, 1.0);
+
+        // parallel to the wall, and too short to reach it
+        assert!(bsp.ray_cast(&origin, &Vector3D { i: 0.0, j: 1.0, k: 0.0 }, 100.0).is_none());
+        assert!(bsp.ray_cast(&origin, &Vector3D { i: -1.0, j: 0.0, k: 0.0 }, 4.0).is_none());
+    }
+
+    #[test]
+    fn test_surface_vertices() {
+        let mut bsp = test_bsp();
+        let point = |x, y| CollisionVertex { point: Point3D { x, y, z: 0.0 }, first_edge: 0 };
+        bsp.vertices = block(vec![point(0.0, 0.0), point(1.0, 0.0), point(0.0, 1.0)]);
+        // a single triangle, surface 0 on the left of every edge except the last
+        bsp.edges = block(vec![
+            CollisionEdge { start_vertex: 0, end_vertex: 1, forward_edge: 1, reverse_edge: -1, left_surface: 0, right_surface: -1 },
+            CollisionEdge { start_vertex: 1, end_vertex: 2, forward_edge: 2, reverse_edge: -1, left_surface: 0, right_surface: -1 },
+            CollisionEdge { start_vertex: 0, end_vertex: 2, forward_edge: -1, reverse_edge: 0, left_surface: -1, right_surface: 0 },
+        ]);
+        bsp.surfaces = block(vec![CollisionSurface { plane: 0, first_edge: 0, flags: 0, breakable_surface: -1, material: 0 }]);
+        let vertices = bsp.get_surface_vertices(0).unwrap();
+        let xs: Vec<f32> = vertices.iter().map(|v| v.x).collect();
+        assert_eq!(xs, vec![0.0, 1.0, 0.0]);
+        assert_eq!(vertices[2].y, 1.0);
+    }
+}
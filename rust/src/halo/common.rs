@@ -1,6 +1,7 @@
 use std::{error::Error, fmt::Display, io::{Cursor, Seek, SeekFrom}};
 use deku::prelude::*;
 use anyhow::Result;
+use nalgebra_glm::{rotate_x, rotate_y, rotate_z, translation, vec3, Mat4};
 use wasm_bindgen::prelude::*;
 
 pub type Pointer = u32;
@@ -35,9 +36,9 @@ impl<'a, T: DekuContainerRead<'a> + std::fmt::Debug> Block<T> {
         if self.count > 0 {
             let pointer = offset + self.base_pointer as i64;
             if pointer < 0 {
-                panic!("pointer underflow for offset {} and pointer {}", offset, self.base_pointer);
+                return Err(MapReaderError::InvalidTag(format!("pointer underflow for offset {} and pointer {}", offset, self.base_pointer)).into());
             }
-            data.seek(SeekFrom::Start((self.base_pointer as i64 + offset) as u64))?;
+            data.seek(SeekFrom::Start(pointer as u64))?;
             for _ in 0..self.count {
                 items.push(T::from_reader_with_ctx(data, ())?);
             }
@@ -59,8 +60,38 @@ pub struct Vector3D {
 pub struct TagDataOffset {
     pub size: u32,
     pub external: u32,
-    #[deku(pad_bytes_after = "8")]
     pub file_offset: u32,
+    #[deku(pad_bytes_after = "4")]
+    pub pointer: Pointer,
+}
+
+impl TagDataOffset {
+    pub fn is_external(&self) -> bool {
+        self.external & 1 != 0
+    }
+
+    // reads data stored alongside the tag itself, i.e. not in an external resource map
+    pub fn read_data(&self, data: &mut Reader<Cursor<Vec<u8>>>, offset: i64) -> Result<Vec<u8>> {
+        if self.size == 0 {
+            return Ok(Vec::new());
+        }
+        if self.is_external() {
+            return Err(MapReaderError::InvalidTag(format!("{} bytes of data are stored in another file", self.size)).into());
+        }
+        let pointer = offset + self.pointer as i64;
+        if pointer < 0 {
+            return Err(MapReaderError::InvalidTag(format!("pointer underflow for offset {} and pointer {}", offset, self.pointer)).into());
+        }
+        // check the size against the map before allocating, since it may be garbage
+        let map_size = data.seek(SeekFrom::End(0))?;
+        if (pointer as u64).checked_add(self.size as u64).is_none_or(|end| end > map_size) {
+            return Err(MapReaderError::InvalidTag(format!("{} bytes at {:#x} run past the end of the map", self.size, pointer)).into());
+        }
+        let mut buf = vec![0; self.size as usize];
+        data.seek(SeekFrom::Start(pointer as u64))?;
+        data.read_bytes(buf.len(), &mut buf, deku::ctx::Order::Msb0)?;
+        Ok(buf)
+    }
 }
 
 #[wasm_bindgen(js_name = "HaloPlane3D")]
@@ -70,6 +101,12 @@ pub struct Plane3D {
     pub w: f32, // distance from origin (along normal)
 }
 
+impl Plane3D {
+    pub fn distance_to(&self, point: &Point3D) -> f32 {
+        self.norm.i * point.x + self.norm.j * point.y + self.norm.k * point.z - self.w
+    }
+}
+
 #[derive(Debug, Clone, Copy, DekuRead)]
 pub struct Tri {
     pub v0: u16,
@@ -117,6 +154,22 @@ pub struct Point3D {
     pub z: f32,
 }
 
+#[wasm_bindgen(js_name = "HaloQuaternion")]
+#[derive(Debug, Copy, Clone, DekuRead)]
+pub struct Quaternion {
+    pub i: f32,
+    pub j: f32,
+    pub k: f32,
+    pub w: f32,
+}
+
+#[wasm_bindgen(js_name = "HaloBounds")]
+#[derive(Debug, Copy, Clone, DekuRead)]
+pub struct Bounds {
+    pub lower: f32,
+    pub upper: f32,
+}
+
 #[wasm_bindgen(js_name = "HaloEuler3D")]
 #[derive(Debug, Clone, Copy, DekuRead)]
 pub struct Euler3D {
@@ -124,3 +177,31 @@ pub struct Euler3D {
     pub pitch: f32,
     pub roll: f32,
 }
+
+// T * Rz(yaw) * Ry(pitch) * Rx(roll), the same order the scenery renderer uses
+pub fn compute_model_matrix(position: &Point3D, rotation: &Euler3D) -> Mat4 {
+    let mut matrix = translation(&vec3(position.x, position.y, position.z));
+    matrix = rotate_z(&matrix, rotation.yaw);
+    matrix = rotate_y(&matrix, rotation.pitch);
+    rotate_x(&matrix, rotation.roll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_data_offset(size: u32, external: u32, pointer: u32) -> TagDataOffset {
+        TagDataOffset { size, external, file_offset: 0, pointer }
+    }
+
+    #[test]
+    fn test_read_data() {
+        let mut reader = Reader::new(Cursor::new((0..16).collect::<Vec<u8>>()));
+        assert_eq!(tag_data_offset(4, 0, 2).read_data(&mut reader, 8).unwrap(), vec![10, 11, 12, 13]);
+        assert!(tag_data_offset(0, 0, 100).read_data(&mut reader, 0).unwrap().is_empty());
+        // a garbage size is caught before it's allocated
+        assert!(tag_data_offset(u32::MAX, 0, 0).read_data(&mut reader, 0).is_err());
+        assert!(tag_data_offset(4, 0, 14).read_data(&mut reader, 0).is_err());
+        assert!(tag_data_offset(4, 1, 0).read_data(&mut reader, 0).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use deku::prelude::*;
+use nalgebra_glm::{mat3, mat3_to_quat, rotate_x, rotate_y, rotate_z, vec3, Mat3, Mat4};
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::camera_path::CameraKeyframe;
+use crate::halo::common::*;
+use crate::halo::util::trim_tag_string;
+
+const DEFAULT_HORIZONTAL_FOV: f32 = 70.0 * std::f32::consts::PI / 180.0;
+const TICKS_PER_SECOND: f32 = 30.0;
+
+// (x, y, z) -> (x, z, -y), the same as noclipSpaceFromHaloSpace in scenes.ts
+fn noclip_from_halo() -> Mat3 {
+    mat3(
+        1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0,
+        0.0, -1.0, 0.0,
+    )
+}
+
+// noclip camera axes (+x right, +y up, -z forward) to halo's (+x forward, +y left, +z up)
+fn halo_camera_from_noclip_camera() -> Mat3 {
+    mat3(
+        0.0, 0.0, -1.0,
+        -1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+    )
+}
+
+// a named point used by scripts, e.g. to teleport units or aim cameras at
+#[tag_layout(size = 0x5C)]
+#[wasm_bindgen(js_name = "HaloCutsceneFlag")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct CutsceneFlag {
+    #[offset(0x04)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x24)] pub position: Point3D,
+    #[offset(0x30)] pub facing_yaw: f32,
+    #[offset(0x34)] pub facing_pitch: f32,
+}
+
+#[wasm_bindgen(js_class = "HaloCutsceneFlag")]
+impl CutsceneFlag {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+#[tag_layout(size = 0x68)]
+#[wasm_bindgen(js_name = "HaloCutsceneCameraPoint")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct CutsceneCameraPoint {
+    #[offset(0x04)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x28)] pub position: Point3D,
+    #[offset(0x34)] pub orientation: Euler3D,
+    // horizontal, in radians. 0 means the default
+    #[offset(0x40)] pub field_of_view: f32,
+}
+
+#[wasm_bindgen(js_class = "HaloCutsceneCameraPoint")]
+impl CutsceneCameraPoint {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+impl CutsceneCameraPoint {
+    // a keyframe in noclip space, with the fov converted to vertical for the given aspect ratio
+    pub fn to_keyframe(&self, time: f32, aspect: f32) -> CameraKeyframe {
+        let Euler3D { yaw, pitch, roll } = self.orientation;
+        // positive pitch looks up, unlike the object rotations in compute_model_matrix
+        let mut rotation = rotate_z(&Mat4::identity(), yaw);
+        rotation = rotate_y(&rotation, -pitch);
+        rotation = rotate_x(&rotation, roll);
+        let rotation: Mat3 = rotation.fixed_view::<3, 3>(0, 0).into();
+        let orientation = noclip_from_halo() * rotation * halo_camera_from_noclip_camera();
+
+        let horizontal_fov = if self.field_of_view > 0.0 { self.field_of_view } else { DEFAULT_HORIZONTAL_FOV };
+        CameraKeyframe {
+            time,
+            position: noclip_from_halo() * vec3(self.position.x, self.position.y, self.position.z),
+            orientation: mat3_to_quat(&orientation),
+            fov: 2.0 * ((horizontal_fov / 2.0).tan() / aspect).atan(),
+        }
+    }
+}
+
+// recorded unit control data (movement, aiming, firing) that scripts play
+// back on a unit. the event stream itself is left undecoded
+#[tag_layout(size = 0x40)]
+#[wasm_bindgen(js_name = "HaloRecordedAnimation")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct RecordedAnimation {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub version: i8,
+    #[offset(0x21)] pub raw_animation_data: i8,
+    #[offset(0x22)] pub unit_control_data_version: i8,
+    #[offset(0x24)] pub length_of_animation: i16, // in ticks
+    #[offset(0x2C)] pub(crate) event_stream: TagDataOffset,
+    #[deku(skip)]
+    pub(crate) event_data: Vec<u8>,
+}
+
+#[wasm_bindgen(js_class = "HaloRecordedAnimation")]
+impl RecordedAnimation {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+
+    pub fn get_duration(&self) -> f32 {
+        self.length_of_animation as f32 / TICKS_PER_SECOND
+    }
+
+    pub fn get_event_data(&self) -> Vec<u8> {
+        self.event_data.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::{quat_to_mat4, Vec3};
+
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    fn camera_point(yaw: f32, pitch: f32, field_of_view: f32) -> CutsceneCameraPoint {
+        read_test_tag(&[
+            (0x04, b"cam1"),
+            (0x28, &1.0f32.to_le_bytes()),
+            (0x2C, &2.0f32.to_le_bytes()),
+            (0x30, &3.0f32.to_le_bytes()),
+            (0x34, &yaw.to_le_bytes()),
+            (0x38, &pitch.to_le_bytes()),
+            (0x40, &field_of_view.to_le_bytes()),
+        ])
+    }
+
+    fn forward(keyframe: &CameraKeyframe) -> Vec3 {
+        -quat_to_mat4(&keyframe.orientation).column(2).xyz()
+    }
+
+    #[test]
+    fn test_camera_point_keyframe() {
+        use std::f32::consts::FRAC_PI_2;
+
+        let point = camera_point(0.0, 0.0, FRAC_PI_2);
+        assert_eq!(point.get_name(), "cam1");
+        let keyframe = point.to_keyframe(2.0, 1.0);
+        assert_eq!(keyframe.time, 2.0);
+        assert!((keyframe.position - vec3(1.0, 3.0, -2.0)).norm() < 1e-5);
+        assert!((forward(&keyframe) - vec3(1.0, 0.0, 0.0)).norm() < 1e-5);
+        assert!((keyframe.fov - FRAC_PI_2).abs() < 1e-5);
+
+        // looking down halo's +y, i.e. noclip's -z
+        let keyframe = camera_point(FRAC_PI_2, 0.0, 0.0).to_keyframe(0.0, 2.0);
+        assert!((forward(&keyframe) - vec3(0.0, 0.0, -1.0)).norm() < 1e-5);
+        assert!(keyframe.fov < DEFAULT_HORIZONTAL_FOV);
+
+        let keyframe = camera_point(0.0, FRAC_PI_2, 0.0).to_keyframe(0.0, 1.0);
+        assert!((forward(&keyframe) - vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+    }
+}
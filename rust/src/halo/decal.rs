@@ -0,0 +1,101 @@
+use deku::prelude::*;
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::shader::*;
+use crate::halo::tag::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum DecalType {
+    Scratch = 0,
+    Splotch = 1,
+    Burn = 2,
+    PaintedSign = 3,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum DecalLayer {
+    Primary = 0,
+    Secondary = 1,
+    LightAntialiased = 2,
+    AlphaTested = 3,
+    Water = 4,
+}
+
+impl_tag_layout!(DecalType => 2, DecalLayer => 2);
+
+#[tag_layout(size = 0x114)]
+#[wasm_bindgen(js_name = "HaloDecal")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Decal {
+    #[offset(0x00)] pub flags: u16,
+    #[offset(0x02)] pub decal_type: DecalType,
+    #[offset(0x04)] pub layer: DecalLayer,
+    #[offset(0x08)] pub next_decal_in_chain: TagDependency,
+    #[offset(0x18)] pub radius_lower_bound: f32,
+    #[offset(0x1C)] pub radius_upper_bound: f32,
+    #[offset(0x2C)] pub intensity_lower_bound: f32,
+    #[offset(0x30)] pub intensity_upper_bound: f32,
+    #[offset(0x34)] pub color_lower_bound: ColorRGB,
+    #[offset(0x40)] pub color_upper_bound: ColorRGB,
+    #[offset(0x58)] pub animation_loop_frame: i16,
+    #[offset(0x5A)] pub animation_speed: i16,
+    #[offset(0x78)] pub lifetime_lower_bound: f32,
+    #[offset(0x7C)] pub lifetime_upper_bound: f32,
+    #[offset(0x80)] pub decay_time_lower_bound: f32,
+    #[offset(0x84)] pub decay_time_upper_bound: f32,
+    #[offset(0xBE)] pub framebuffer_blend_function: FramebufferBlendFunction,
+    #[offset(0xE8)] pub(crate) map: TagDependency,
+    #[offset(0x10C)] pub maximum_sprite_extent: f32,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum DetailObjectCollectionType {
+    ScreenFacing = 0,
+    ViewerFacing = 1,
+}
+
+impl_tag_layout!(DetailObjectCollectionType => 2);
+
+#[tag_layout(size = 0x70)]
+#[wasm_bindgen(js_name = "HaloDetailObjectCollection")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct DetailObjectCollection {
+    #[offset(0x00)] pub collection_type: DetailObjectCollectionType,
+    #[offset(0x04)] pub global_z_offset: f32,
+    #[offset(0x34)] pub(crate) sprite_plate: TagDependency,
+    #[offset(0x44)] pub(crate) types: Block<DetailObjectType>,
+}
+
+#[wasm_bindgen(js_class = "HaloDetailObjectCollection")]
+impl DetailObjectCollection {
+    pub fn get_types(&self) -> Vec<DetailObjectType> {
+        self.types.items.clone().unwrap_or_default()
+    }
+}
+
+#[tag_layout(size = 0x60)]
+#[wasm_bindgen(js_name = "HaloDetailObjectType")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct DetailObjectType {
+    #[offset(0x20)] pub sequence_index: u8,
+    #[offset(0x21)] pub flags: u8,
+    #[offset(0x24)] pub color_override_factor: f32,
+    #[offset(0x30)] pub near_fade_distance: f32,
+    #[offset(0x34)] pub far_fade_distance: f32,
+    #[offset(0x38)] pub size: f32,
+    #[offset(0x40)] pub minimum_color: ColorRGB,
+    #[offset(0x4C)] pub maximum_color: ColorRGB,
+    #[offset(0x58)] pub ambient_color: u32,
+}
@@ -0,0 +1,518 @@
+use js_sys::Float32Array;
+use nalgebra_glm::{vec3, vec4, Vec3, Vec4};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::particle::{Glow, GlowParticleDistribution, ParticleSystemParticleState, ParticleSystemType, WeatherParticleType};
+use crate::halo::scenario::BSP;
+use crate::halo::shader::FramebufferBlendFunction;
+use crate::halo::tag::TagDependency;
+
+// position + size, color, then rotation and sprite frame
+pub const TEXELS_PER_PARTICLE: usize = 3;
+
+#[derive(Default, Debug, Clone)]
+struct EmitterParticle {
+    age: f32,
+    lifespan: f32,
+    position: Vec3,
+    velocity: Vec3,
+    color: Vec4,
+    // the alpha before any distance fading
+    base_alpha: f32,
+    size: f32,
+    rotation: f32,
+    rotation_rate: f32,
+    frame: f32,
+    animation_rate: f32,
+    // angle and distance around the origin, for glow particles
+    orbit_angle: f32,
+    orbit_distance: f32,
+    // the particle state a particle system particle is in
+    state: usize,
+}
+
+impl EmitterParticle {
+    fn step(&mut self, dt_secs: f32) {
+        self.age += dt_secs;
+        self.position += self.velocity * dt_secs;
+        self.rotation += self.rotation_rate * dt_secs;
+        self.frame += self.animation_rate * dt_secs;
+    }
+}
+
+fn random_in(rng: &mut ThreadRng, bounds: &Bounds) -> f32 {
+    if bounds.upper > bounds.lower {
+        rng.gen_range(bounds.lower..bounds.upper)
+    } else {
+        bounds.lower
+    }
+}
+
+fn lerp_color(a: &ColorARGB, b: &ColorARGB, t: f32) -> Vec4 {
+    vec4(a.r, a.g, a.b, a.a) * (1.0 - t) + vec4(b.r, b.g, b.b, b.a) * t
+}
+
+// 0 before start, 1 after end, linear in between. a zero length ramp never fades
+fn ramp(value: f32, start: f32, end: f32) -> f32 {
+    if end > start {
+        ((value - start) / (end - start)).clamp(0.0, 1.0)
+    } else {
+        1.0
+    }
+}
+
+fn fill_texture(particles: &[EmitterParticle], max_particles: usize, texture: &Float32Array) {
+    let mut data = vec![0.0; max_particles * TEXELS_PER_PARTICLE * 4];
+    for (particle, texels) in particles.iter().zip(data.chunks_exact_mut(TEXELS_PER_PARTICLE * 4)) {
+        texels[0..3].copy_from_slice(particle.position.as_slice());
+        texels[3] = particle.size;
+        texels[4..8].copy_from_slice(particle.color.as_slice());
+        texels[8] = particle.rotation;
+        texels[9] = particle.frame;
+    }
+    texture.copy_from(&data);
+}
+
+// weather particles never die, they wrap around inside a cube centered on the
+// camera, sized so that they've faded out by the time they reach its edge
+#[wasm_bindgen(js_name = "HaloWeatherEmitter")]
+#[derive(Debug, Clone)]
+pub struct WeatherEmitter {
+    particle_type: WeatherParticleType,
+    rng: ThreadRng,
+    particles: Vec<EmitterParticle>,
+    wind: Vec3,
+    camera: Vec3,
+    half_extent: f32,
+    pub max_particles: usize,
+    // scales the particle count between its lower and upper bounds
+    pub intensity: f32,
+}
+
+#[wasm_bindgen(js_class = "HaloWeatherEmitter")]
+impl WeatherEmitter {
+    // wind_direction is an xyz triple
+    pub fn new(particle_type: &WeatherParticleType, wind_direction: &[f32], wind_magnitude: f32) -> Self {
+        assert_eq!(wind_direction.len(), 3);
+        let wind = vec3(wind_direction[0], wind_direction[1], wind_direction[2]) * wind_magnitude;
+        let max_particles = particle_type.particle_count.upper.max(particle_type.particle_count.lower).ceil().max(0.0) as usize;
+        WeatherEmitter {
+            particle_type: particle_type.clone(),
+            rng: thread_rng(),
+            particles: Vec::with_capacity(max_particles),
+            wind,
+            camera: Vec3::zeros(),
+            half_extent: particle_type.fade_out_end_distance.max(1.0),
+            max_particles,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn update(&mut self, dt_ms: f32, camera_pos: &[f32]) {
+        assert_eq!(camera_pos.len(), 3);
+        let dt_secs = dt_ms / 1000.0;
+        self.camera = vec3(camera_pos[0], camera_pos[1], camera_pos[2]);
+
+        let count = &self.particle_type.particle_count;
+        let target = (count.lower + (count.upper - count.lower) * self.intensity.clamp(0.0, 1.0)) as usize;
+        let target = target.min(self.max_particles);
+        self.particles.truncate(target);
+        while self.particles.len() < target {
+            let particle = self.create_particle();
+            self.particles.push(particle);
+        }
+
+        let half_extent = self.half_extent;
+        for particle in self.particles.iter_mut() {
+            particle.step(dt_secs);
+            for axis in 0..3 {
+                let relative = particle.position[axis] - self.camera[axis];
+                if relative > half_extent {
+                    particle.position[axis] -= 2.0 * half_extent;
+                } else if relative < -half_extent {
+                    particle.position[axis] += 2.0 * half_extent;
+                }
+            }
+            let distance = (particle.position - self.camera).magnitude();
+            particle.color[3] = particle.base_alpha * distance_fade(&self.particle_type, distance);
+        }
+    }
+
+    // particles under cover (inside the bsp's weather polyhedra) are hidden
+    pub fn cull_sheltered(&mut self, bsp: &BSP) {
+        for particle in self.particles.iter_mut() {
+            let position = Point3D { x: particle.position[0], y: particle.position[1], z: particle.position[2] };
+            if bsp.is_sheltered_from_weather(&position) {
+                particle.color[3] = 0.0;
+            }
+        }
+    }
+
+    pub fn fill_texture(&self, texture: &Float32Array) {
+        fill_texture(&self.particles, self.max_particles, texture);
+    }
+
+    pub fn get_texels_per_particle() -> usize {
+        TEXELS_PER_PARTICLE
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn get_bitmap(&self) -> TagDependency {
+        self.particle_type.sprite_bitmap
+    }
+
+    pub fn get_framebuffer_blend_function(&self) -> FramebufferBlendFunction {
+        self.particle_type.framebuffer_blend_function
+    }
+}
+
+impl WeatherEmitter {
+    fn create_particle(&mut self) -> EmitterParticle {
+        let particle_type = &self.particle_type;
+        let offset = vec3(
+            self.rng.gen_range(-1.0..1.0),
+            self.rng.gen_range(-1.0..1.0),
+            self.rng.gen_range(-1.0..1.0),
+        ) * self.half_extent;
+        // without point physics to integrate, particles fall at a constant
+        // speed drawn from the acceleration bounds, blown along by the wind
+        let fall_speed = random_in(&mut self.rng, &particle_type.acceleration_magnitude);
+        let t = self.rng.gen_range(0.0..1.0);
+        let color = lerp_color(&particle_type.color_lower_bound, &particle_type.color_upper_bound, t);
+        EmitterParticle {
+            position: self.camera + offset,
+            velocity: self.wind - vec3(0.0, 0.0, fall_speed),
+            color,
+            base_alpha: color[3],
+            size: random_in(&mut self.rng, &particle_type.particle_radius),
+            rotation: self.rng.gen_range(0.0..std::f32::consts::TAU),
+            rotation_rate: random_in(&mut self.rng, &particle_type.rotation_rate),
+            animation_rate: random_in(&mut self.rng, &particle_type.animation_rate),
+            lifespan: f32::INFINITY,
+            ..Default::default()
+        }
+    }
+}
+
+fn distance_fade(particle_type: &WeatherParticleType, distance: f32) -> f32 {
+    let fade_in = ramp(distance, particle_type.fade_in_start_distance, particle_type.fade_in_end_distance);
+    let (out_start, out_end) = (particle_type.fade_out_start_distance, particle_type.fade_out_end_distance);
+    let fade_out = if out_end > out_start { 1.0 - ramp(distance, out_start, out_end) } else { 1.0 };
+    fade_in * fade_out
+}
+
+// glows normally follow a chain of object markers. we don't have those, so
+// the glow's particles circle its origin at the configured distance, while
+// trailing particles stream off of them
+#[wasm_bindgen(js_name = "HaloGlowEmitter")]
+#[derive(Debug, Clone)]
+pub struct GlowEmitter {
+    glow: Glow,
+    rng: ThreadRng,
+    particles: Vec<EmitterParticle>,
+    trailing_particles: Vec<EmitterParticle>,
+    trailing_to_emit: f32,
+    time: f32,
+    pub max_particles: usize,
+}
+
+#[wasm_bindgen(js_class = "HaloGlowEmitter")]
+impl GlowEmitter {
+    pub fn new(glow: &Glow) -> Self {
+        let mut rng = thread_rng();
+        let num_particles = glow.number_of_particles.max(0) as usize;
+        let particles = (0..num_particles)
+            .map(|i| {
+                let orbit = match glow.normal_particle_distribution {
+                    GlowParticleDistribution::DistributedUniformly => i as f32 / num_particles as f32,
+                    GlowParticleDistribution::DistributedRandomly => rng.gen_range(0.0..1.0),
+                };
+                let distance = Bounds { lower: glow.min_distance_particle_to_object, upper: glow.max_distance_particle_to_object };
+                EmitterParticle {
+                    orbit_angle: orbit * std::f32::consts::TAU,
+                    orbit_distance: random_in(&mut rng, &distance),
+                    size: random_in(&mut rng, &glow.particle_size_bounds),
+                    rotation_rate: glow.particle_rotational_velocity,
+                    lifespan: f32::INFINITY,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let max_trailing = (glow.particle_generation_frequency * glow.lifetime_of_trailing_particles).ceil().max(0.0) as usize;
+        GlowEmitter {
+            glow: glow.clone(),
+            rng,
+            particles,
+            trailing_particles: Vec::with_capacity(max_trailing),
+            trailing_to_emit: 0.0,
+            time: 0.0,
+            max_particles: num_particles + max_trailing,
+        }
+    }
+
+    // origin is an xyz triple
+    pub fn update(&mut self, dt_ms: f32, origin: &[f32]) {
+        assert_eq!(origin.len(), 3);
+        let dt_secs = dt_ms / 1000.0;
+        self.time += dt_secs;
+        let origin = vec3(origin[0], origin[1], origin[2]);
+
+        let spin = self.glow.effect_rotational_velocity * self.time;
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            let angle = particle.orbit_angle + spin;
+            particle.position = origin + vec3(angle.cos(), angle.sin(), 0.0) * particle.orbit_distance;
+            particle.rotation += particle.rotation_rate * dt_secs;
+            // offset each particle's color cycle so they don't all pulse together
+            let t = 0.5 + 0.5 * (self.time * self.glow.color_rate_of_change + i as f32).sin();
+            particle.color = lerp_color(&self.glow.color_bound_0, &self.glow.color_bound_1, t);
+        }
+
+        let lifetime = self.glow.lifetime_of_trailing_particles;
+        self.trailing_particles.retain_mut(|particle| {
+            particle.step(dt_secs);
+            particle.color[3] = 1.0 - ramp(particle.age, 0.0, lifetime);
+            particle.age < particle.lifespan
+        });
+        if !self.particles.is_empty() && lifetime > 0.0 {
+            self.trailing_to_emit += self.glow.particle_generation_frequency * dt_secs;
+            while self.trailing_to_emit >= 1.0 {
+                if self.particles.len() + self.trailing_particles.len() < self.max_particles {
+                    let particle = self.create_trailing_particle(&origin);
+                    self.trailing_particles.push(particle);
+                }
+                self.trailing_to_emit -= 1.0;
+            }
+        }
+    }
+
+    pub fn fill_texture(&self, texture: &Float32Array) {
+        let particles: Vec<EmitterParticle> = self.particles.iter().chain(self.trailing_particles.iter()).cloned().collect();
+        fill_texture(&particles, self.max_particles, texture);
+    }
+
+    pub fn get_texels_per_particle() -> usize {
+        TEXELS_PER_PARTICLE
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.particles.len() + self.trailing_particles.len()
+    }
+
+    pub fn get_bitmap(&self) -> TagDependency {
+        self.glow.texture
+    }
+
+    // glows don't pick a blend function, they always add
+    pub fn get_framebuffer_blend_function(&self) -> FramebufferBlendFunction {
+        FramebufferBlendFunction::Add
+    }
+}
+
+impl GlowEmitter {
+    fn create_trailing_particle(&mut self, origin: &Vec3) -> EmitterParticle {
+        let source = &self.particles[self.rng.gen_range(0..self.particles.len())];
+        let outward = source.position - origin;
+        let direction = if outward.magnitude() > 0.0001 { outward.normalize() } else { vec3(0.0, 0.0, 1.0) };
+        EmitterParticle {
+            position: source.position,
+            velocity: direction * self.glow.velocity_of_trailing_particles,
+            color: source.color,
+            size: source.size,
+            lifespan: self.glow.lifetime_of_trailing_particles,
+            ..Default::default()
+        }
+    }
+}
+
+// particle system particles pass through each of their type's particle states
+// in turn, and are replaced by a new particle once they've finished the last.
+// there's no point physics, so they stay where they were created, relative to
+// the emitter's origin
+#[wasm_bindgen(js_name = "HaloParticleSystemEmitter")]
+#[derive(Debug, Clone)]
+pub struct ParticleSystemEmitter {
+    states: Vec<ParticleSystemParticleState>,
+    rng: ThreadRng,
+    particles: Vec<EmitterParticle>,
+    origin: Vec3,
+    radius: f32,
+    pub max_particles: usize,
+}
+
+#[wasm_bindgen(js_class = "HaloParticleSystemEmitter")]
+impl ParticleSystemEmitter {
+    pub fn new(particle_type: &ParticleSystemType) -> Self {
+        let states = particle_type.particle_states.items.clone().unwrap_or_default();
+        let max_particles = if states.is_empty() { 0 } else { particle_type.initial_particle_count.max(0) as usize };
+        ParticleSystemEmitter {
+            states,
+            rng: thread_rng(),
+            particles: Vec::with_capacity(max_particles),
+            origin: Vec3::zeros(),
+            radius: particle_type.radius.max(0.0),
+            max_particles,
+        }
+    }
+
+    // origin is an xyz triple
+    pub fn update(&mut self, dt_ms: f32, origin: &[f32]) {
+        assert_eq!(origin.len(), 3);
+        let dt_secs = dt_ms / 1000.0;
+        let origin = vec3(origin[0], origin[1], origin[2]);
+        let moved = origin - self.origin;
+        self.origin = origin;
+
+        for particle in self.particles.iter_mut() {
+            particle.position += moved;
+            particle.step(dt_secs);
+            while particle.age >= particle.lifespan {
+                let overflow = particle.age - particle.lifespan;
+                if particle.state + 1 < self.states.len() {
+                    enter_state(&mut self.rng, particle, particle.state + 1, &self.states);
+                } else {
+                    *particle = spawn_particle(&mut self.rng, &origin, self.radius, &self.states);
+                }
+                particle.age = overflow;
+            }
+        }
+        while self.particles.len() < self.max_particles {
+            // start partway through, so that the initial particles don't all change state together
+            let mut particle = spawn_particle(&mut self.rng, &origin, self.radius, &self.states);
+            if particle.lifespan.is_finite() {
+                particle.age = self.rng.gen_range(0.0..particle.lifespan);
+            }
+            self.particles.push(particle);
+        }
+    }
+
+    pub fn fill_texture(&self, texture: &Float32Array) {
+        fill_texture(&self.particles, self.max_particles, texture);
+    }
+
+    pub fn get_texels_per_particle() -> usize {
+        TEXELS_PER_PARTICLE
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    // every particle is drawn with the first state's sprite and blending
+    pub fn get_bitmap(&self) -> Option<TagDependency> {
+        Some(self.states.first()?.bitmaps)
+    }
+
+    pub fn get_framebuffer_blend_function(&self) -> FramebufferBlendFunction {
+        self.states.first().map_or(FramebufferBlendFunction::AlphaBlend, |state| state.framebuffer_blend_function)
+    }
+}
+
+fn spawn_particle(rng: &mut ThreadRng, origin: &Vec3, radius: f32, states: &[ParticleSystemParticleState]) -> EmitterParticle {
+    // somewhere in the sphere of the given radius around the origin
+    let offset = loop {
+        let offset = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        if offset.magnitude_squared() <= 1.0 {
+            break offset * radius;
+        }
+    };
+    let mut particle = EmitterParticle {
+        position: origin + offset,
+        rotation: rng.gen_range(0.0..std::f32::consts::TAU),
+        ..Default::default()
+    };
+    enter_state(rng, &mut particle, 0, states);
+    particle
+}
+
+fn enter_state(rng: &mut ThreadRng, particle: &mut EmitterParticle, index: usize, states: &[ParticleSystemParticleState]) {
+    let state = &states[index];
+    let duration = random_in(rng, &state.duration_bounds);
+    let t = rng.gen_range(0.0..1.0);
+    particle.state = index;
+    particle.age = 0.0;
+    // a state without a duration lasts forever
+    particle.lifespan = if duration > 0.0 { duration } else { f32::INFINITY };
+    particle.color = lerp_color(&state.color_1, &state.color_2, t);
+    particle.base_alpha = particle.color[3];
+    particle.size = random_in(rng, &state.scale);
+    particle.rotation_rate = random_in(rng, &state.rotation_rate);
+    particle.animation_rate = random_in(rng, &state.animation_rate);
+    particle.frame = 0.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    #[test]
+    fn test_weather_wraps_around_camera() {
+        let particle_type: WeatherParticleType = read_test_tag(&[
+            (0x2C, &4.0f32.to_le_bytes()), // fade out start
+            (0x30, &5.0f32.to_le_bytes()), // fade out end
+            (0xA4, &10.0f32.to_le_bytes()),
+            (0xA8, &20.0f32.to_le_bytes()),
+            (0xCC, &3.0f32.to_le_bytes()),
+        ]);
+
+        let mut emitter = WeatherEmitter::new(&particle_type, &[1.0, 0.0, 0.0], 0.0);
+        assert_eq!(emitter.max_particles, 20);
+        emitter.intensity = 0.5;
+        emitter.update(16.0, &[100.0, 0.0, 0.0]);
+        assert_eq!(emitter.num_particles(), 15);
+
+        // after a long fall, every particle is still within the camera's cube
+        for _ in 0..100 {
+            emitter.update(100.0, &[100.0, 0.0, 0.0]);
+        }
+        for particle in &emitter.particles {
+            assert!((particle.position - vec3(100.0, 0.0, 0.0)).abs().max() <= 5.0 + 1e-3);
+            assert_eq!(particle.velocity, vec3(0.0, 0.0, -3.0));
+        }
+    }
+
+    fn particle_state(duration: f32, scale: f32) -> ParticleSystemParticleState {
+        read_test_tag(&[
+            (0x20, &duration.to_le_bytes()),
+            (0x24, &duration.to_le_bytes()),
+            (0x48, &scale.to_le_bytes()),
+            (0x4C, &scale.to_le_bytes()),
+        ])
+    }
+
+    #[test]
+    fn test_particle_system_states() {
+        let mut particle_type: ParticleSystemType = read_test_tag(&[(0x24, &4i16.to_le_bytes()), (0x2C, &2.0f32.to_le_bytes())]);
+        assert_eq!(ParticleSystemEmitter::new(&particle_type).max_particles, 0);
+
+        particle_type.particle_states.items = Some(vec![particle_state(1.0, 0.5), particle_state(1.0, 3.0)]);
+        let mut emitter = ParticleSystemEmitter::new(&particle_type);
+        emitter.update(0.0, &[10.0, 0.0, 0.0]);
+        assert_eq!(emitter.num_particles(), 4);
+        for particle in &emitter.particles {
+            assert!((particle.position - vec3(10.0, 0.0, 0.0)).magnitude() <= 2.0 + 1e-3);
+        }
+
+        // each particle is now in the other state, and follows the origin
+        let states: Vec<usize> = emitter.particles.iter().map(|particle| particle.state).collect();
+        emitter.update(1000.0, &[10.0, 5.0, 0.0]);
+        for (particle, state) in emitter.particles.iter().zip(states) {
+            assert_eq!(particle.state, 1 - state);
+            assert_eq!(particle.size, [0.5, 3.0][particle.state]);
+            assert!((particle.position - vec3(10.0, 5.0, 0.0)).magnitude() <= 2.0 + 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_ramp() {
+        assert_eq!(ramp(0.5, 0.0, 1.0), 0.5);
+        assert_eq!(ramp(2.0, 0.0, 1.0), 1.0);
+        assert_eq!(ramp(-1.0, 0.0, 1.0), 0.0);
+        assert_eq!(ramp(-1.0, 0.0, 0.0), 1.0);
+    }
+}
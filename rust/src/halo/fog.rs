@@ -0,0 +1,120 @@
+use deku::prelude::*;
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::model::Sky;
+use crate::halo::tag::*;
+
+// planar fog, i.e. the volume below a bsp fog plane
+#[tag_layout(size = 0x18C)]
+#[wasm_bindgen(js_name = "HaloFog")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Fog {
+    #[offset(0x000)] pub flags: u32,
+    #[offset(0x058)] pub maximum_density: f32,
+    #[offset(0x060)] pub opaque_distance: f32,
+    #[offset(0x068)] pub opaque_depth: f32,
+    #[offset(0x074)] pub distance_to_water_plane: f32,
+    #[offset(0x078)] pub color: ColorRGB,
+    #[offset(0x084)] pub screen_layer_flags: u16,
+    #[offset(0x086)] pub screen_layer_count: u16,
+    #[offset(0x088)] pub screen_layer_distance_gradient: Bounds,
+    #[offset(0x090)] pub screen_layer_density_gradient: Bounds,
+    #[offset(0x098)] pub screen_layer_start_distance_from_fog_plane: f32,
+    #[offset(0x0A0)] pub screen_layer_color: u32,
+    #[offset(0x0A4)] pub screen_layer_rotation_multiplier: f32,
+    #[offset(0x0A8)] pub screen_layer_strafing_multiplier: f32,
+    #[offset(0x0AC)] pub screen_layer_zoom_multiplier: f32,
+    #[offset(0x0B8)] pub screen_layer_map_scale: f32,
+    #[offset(0x0BC)] pub screen_layer_map: TagDependency,
+    #[offset(0x0CC)] pub screen_layer_animation_period: f32,
+    #[offset(0x0D4)] pub wind_velocity: Bounds,
+    #[offset(0x0DC)] pub wind_period: Bounds,
+    #[offset(0x0E4)] pub wind_acceleration_weight: f32,
+    #[offset(0x0E8)] pub wind_perpendicular_weight: f32,
+    #[offset(0x0F4)] pub background_sound: TagDependency,
+    #[offset(0x104)] pub sound_environment: TagDependency,
+}
+
+#[wasm_bindgen(js_class = "HaloFog")]
+impl Fog {
+    pub fn is_water(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    // the sky's atmospheric fog wins where the two overlap
+    pub fn is_atmosphere_dominant(&self) -> bool {
+        self.flags & 2 != 0
+    }
+
+    pub fn is_fog_screen_only(&self) -> bool {
+        self.flags & 4 != 0
+    }
+}
+
+// the fog visible from within a bsp cluster: atmospheric fog from the
+// cluster's sky (outdoor fog, or indoor fog if the cluster has no sky), plus
+// the planar fog the cluster sits in, if any
+#[wasm_bindgen(js_name = "HaloClusterFog")]
+#[derive(Debug, Clone)]
+pub struct ClusterFog {
+    pub is_outdoor: bool,
+    pub atmospheric_color: ColorRGB,
+    pub atmospheric_max_density: f32,
+    pub atmospheric_start_distance: f32,
+    pub atmospheric_opaque_distance: f32,
+    pub(crate) planar_fog: Option<Fog>,
+}
+
+#[wasm_bindgen(js_class = "HaloClusterFog")]
+impl ClusterFog {
+    pub fn get_planar_fog(&self) -> Option<Fog> {
+        self.planar_fog.clone()
+    }
+}
+
+impl ClusterFog {
+    pub fn new(sky: &Sky, is_outdoor: bool, planar_fog: Option<Fog>) -> Self {
+        if is_outdoor {
+            ClusterFog {
+                is_outdoor,
+                atmospheric_color: sky.outdoor_fog_color,
+                atmospheric_max_density: sky.outdoor_fog_max_density,
+                atmospheric_start_distance: sky.outdoor_fog_start_distance,
+                atmospheric_opaque_distance: sky.outdoor_fog_opaque_distance,
+                planar_fog,
+            }
+        } else {
+            ClusterFog {
+                is_outdoor,
+                atmospheric_color: sky.indoor_fog_color,
+                atmospheric_max_density: sky.indoor_fog_max_density,
+                atmospheric_start_distance: sky.indoor_fog_start_distance,
+                atmospheric_opaque_distance: sky.indoor_fog_opaque_distance,
+                planar_fog,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    #[test]
+    fn test_cluster_fog() {
+        let sky: Sky = read_test_tag(&[(0x6C, &0.5f32.to_le_bytes()), (0x8C, &0.25f32.to_le_bytes())]);
+        let fog: Fog = read_test_tag(&[(0x00, &3u32.to_le_bytes()), (0x68, &8.0f32.to_le_bytes())]);
+        assert!(fog.is_water() && fog.is_atmosphere_dominant() && !fog.is_fog_screen_only());
+        assert_eq!(fog.opaque_depth, 8.0);
+
+        let outdoor = ClusterFog::new(&sky, true, Some(fog));
+        assert_eq!(outdoor.atmospheric_max_density, 0.5);
+        assert!(outdoor.get_planar_fog().is_some());
+        let indoor = ClusterFog::new(&sky, false, None);
+        assert_eq!(indoor.atmospheric_max_density, 0.25);
+        assert!(indoor.get_planar_fog().is_none());
+    }
+}
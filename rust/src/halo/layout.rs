@@ -0,0 +1,200 @@
+use crate::halo::common::*;
+use crate::halo::tag::*;
+use crate::halo::util::trim_tag_string;
+
+// a field's on-disk size plus how to show it in a tag dump. structs declared
+// with #[tag_layout] implement this themselves, anything they contain has to
+// as well (see impl_tag_layout! for the simple cases)
+pub trait TagLayout {
+    const SIZE: usize;
+
+    fn describe(&self) -> String;
+
+    fn fields(&self) -> Vec<TagField> {
+        Vec::new()
+    }
+}
+
+// a zeroed T with the given bytes written at their offsets, for tests that
+// only care about a few fields
+#[cfg(test)]
+pub(crate) fn read_test_tag<T>(fields: &[(usize, &[u8])]) -> T
+    where T: TagLayout + for<'a> deku::DekuContainerRead<'a>
+{
+    let mut data = vec![0u8; T::SIZE];
+    for (offset, bytes) in fields {
+        data[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+    }
+    let ((rest, _), tag) = T::from_bytes((&data, 0)).unwrap();
+    assert!(rest.is_empty());
+    tag
+}
+
+#[derive(Debug, Clone)]
+pub struct TagField {
+    pub name: String,
+    pub offset: usize,
+    pub type_name: &'static str,
+    pub value: String,
+    pub fields: Vec<TagField>,
+}
+
+// implements TagLayout for fixed size types that are dumped via their Debug impl
+macro_rules! impl_tag_layout {
+    ($($ty:ty => $size:expr),* $(,)?) => {
+        $(
+            impl crate::halo::layout::TagLayout for $ty {
+                const SIZE: usize = $size;
+
+                fn describe(&self) -> String {
+                    format!("{:?}", self)
+                }
+            }
+        )*
+    };
+}
+pub(crate) use impl_tag_layout;
+
+impl_tag_layout!(
+    u8 => 1, i8 => 1,
+    u16 => 2, i16 => 2,
+    u32 => 4, i32 => 4, f32 => 4,
+    Vector3D => 12,
+    Plane3D => 16,
+    Point2D => 8,
+    Point2DInt => 4,
+    Point3D => 12,
+    Quaternion => 16,
+    Bounds => 8,
+    Euler3D => 12,
+    ColorRGB => 12,
+    ColorARGB => 16,
+    TagDataOffset => 20,
+    TagDependency => 16,
+    Tri => 6,
+);
+
+impl<T: TagLayout, const N: usize> TagLayout for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn describe(&self) -> String {
+        let items: Vec<String> = self.iter().map(|item| item.describe()).collect();
+        format!("[{}]", items.join(", "))
+    }
+}
+
+impl<T: TagLayout> TagLayout for Block<T> {
+    const SIZE: usize = 12;
+
+    fn describe(&self) -> String {
+        format!("{} items @ {:#x}", self.count, self.base_pointer)
+    }
+
+    fn fields(&self) -> Vec<TagField> {
+        let Some(items) = self.items.as_ref() else {
+            return Vec::new();
+        };
+        items.iter().enumerate().map(|(i, item)| TagField {
+            name: format!("[{}]", i),
+            offset: i * T::SIZE,
+            type_name: std::any::type_name::<T>().rsplit("::").next().unwrap(),
+            value: item.describe(),
+            fields: item.fields(),
+        }).collect()
+    }
+}
+
+// fixed length strings are shown as text, anything else as hex
+pub fn describe_bytes(bytes: &[u8]) -> String {
+    let text = trim_tag_string(bytes);
+    if bytes.iter().take_while(|b| **b != 0).all(|b| b.is_ascii_graphic() || *b == b' ') {
+        format!("{:?}", text)
+    } else {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+// renders fields one per line, nesting blocks and structs by indentation
+pub fn format_fields(fields: &[TagField]) -> String {
+    let mut result = String::new();
+    write_fields(&mut result, fields, 0);
+    result
+}
+
+fn write_fields(result: &mut String, fields: &[TagField], depth: usize) {
+    for field in fields {
+        result.push_str(&format!("{}{:#06x} {}: {} = {}\n", "  ".repeat(depth), field.offset, field.name, field.type_name, field.value));
+        write_fields(result, &field.fields, depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::sound::*;
+
+    #[test]
+    fn test_sound_layout() {
+        assert_eq!(Sound::SIZE, 0xA4);
+        assert_eq!(SoundPermutation::SIZE, 0x7C);
+
+        let sound: Sound = read_test_tag(&[
+            (0x06, &1u16.to_le_bytes()),
+            (0x44, &2.5f32.to_le_bytes()),
+            (0x56, &1u16.to_le_bytes()),
+            (0x68, &3u16.to_le_bytes()),
+            (0x98, &7u32.to_le_bytes()),
+        ]);
+        assert_eq!(sound.sample_rate, SoundSampleRate::Rate44kHz);
+        assert_eq!(sound.pitch_modifier, 2.5);
+        assert_eq!(sound.compression, SoundCompression::XboxAdpcm);
+        assert_eq!(sound.promotion_count, 3);
+        assert_eq!(sound.pitch_ranges.count, 7);
+
+        let fields = sound.fields();
+        let field = fields.iter().find(|field| field.name == "pitch_ranges").unwrap();
+        assert_eq!(field.offset, 0x98);
+        assert_eq!(field.type_name, "Block<SoundPitchRange>");
+        assert_eq!(field.value, "7 items @ 0x0");
+        let dump = format_fields(&fields);
+        assert!(dump.contains("0x0056 compression: SoundCompression = XboxAdpcm\n"));
+    }
+
+    #[test]
+    fn test_skipped_fields() {
+        use crate::halo::bitmap::Bitmap;
+        use crate::halo::scenario::Scenario;
+
+        assert_eq!(Scenario::SIZE, 0x5B0);
+        assert_eq!(Bitmap::SIZE, 0x6C);
+
+        let bitmap: Bitmap = read_test_tag(&[(0x4C, &4u16.to_le_bytes()), (0x60, &2u32.to_le_bytes())]);
+        assert_eq!(bitmap.mipmap_count, 4);
+        assert_eq!(bitmap.data.count, 2);
+        assert!(bitmap.path.is_empty());
+        assert!(bitmap.fields().iter().all(|field| field.name != "path"));
+    }
+
+    #[test]
+    fn test_block_element_sizes() {
+        use crate::halo::animation::Animation;
+        use crate::halo::collision::CollisionBSP;
+        use crate::halo::model::{GbxModelNode, GbxModelPart};
+        use crate::halo::scenario::{BSPCluster, BSPMaterial};
+
+        // blocks are read as count * SIZE contiguous elements
+        assert_eq!(Animation::SIZE, 0xB4);
+        assert_eq!(CollisionBSP::SIZE, 0x60);
+        assert_eq!(GbxModelNode::SIZE, 0x9C);
+        assert_eq!(GbxModelPart::SIZE, 0x84);
+        assert_eq!(BSPCluster::SIZE, 0x68);
+        assert_eq!(BSPMaterial::SIZE, 0x100);
+        assert_eq!(<[i16; 5]>::SIZE, 10);
+    }
+
+    #[test]
+    fn test_describe_bytes() {
+        assert_eq!(describe_bytes(b"loop\0\0\0\0"), "\"loop\"");
+        assert_eq!(describe_bytes(&[0x01, 0xFF]), "01ff");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{convert::TryInto, io::{Cursor, Seek, SeekFrom}};
+use std::{collections::HashMap, convert::{TryFrom, TryInto}, io::{Cursor, Seek, SeekFrom}, rc::Rc};
 use deku::prelude::*;
 use anyhow::Result;
 use wasm_bindgen::prelude::*;
@@ -9,40 +9,126 @@ use crate::halo::bitmap::*;
 use crate::halo::model::*;
 use crate::halo::scenario::*;
 use crate::halo::shader::*;
-
-const BASE_MEMORY_ADDRESS: Pointer = 0x50000000;
+use crate::halo::decal::*;
+use crate::halo::animation::*;
+use crate::halo::collision::*;
+use crate::halo::sound::*;
+use crate::halo::fog::*;
+use crate::halo::particle::*;
+use crate::halo::netgame::*;
+use crate::halo::vertex_utils::*;
 
 pub struct MapManager {
     pub reader: MapReader,
     pub header: Header,
     pub tag_index_header: TagIndexHeader,
     pub tag_headers: Vec<TagHeader>,
+    // index into tag_headers for each tag_id
+    tag_indices: HashMap<u32, usize>,
+    // every tag that's been decoded so far, by tag_id
+    tag_cache: HashMap<u32, Rc<Tag>>,
 }
 
 impl MapManager {
     pub fn new(map: Vec<u8>) -> Result<Self> {
         let mut reader = MapReader::new(map);
         let header = reader.read_header()?;
+        if header.version.is_compressed() {
+            reader = MapReader::new(decompress_map(reader.data.into_inner().into_inner(), &header)?);
+        }
 
         let tag_index_header = reader.read_tag_index_header(&header)?;
         let tag_headers = reader.read_tag_headers(&header, &tag_index_header)?;
+        let tag_indices = tag_headers.iter().enumerate()
+            .map(|(i, header)| (header.tag_id, i))
+            .collect();
 
         Ok(MapManager {
             reader,
             header,
             tag_index_header,
             tag_headers,
+            tag_indices,
+            tag_cache: HashMap::new(),
         })
     }
 
     fn get_tag_data_offset(&self) -> i64 {
-        self.header.tag_data_offset as i64 - BASE_MEMORY_ADDRESS as i64
+        self.header.tag_data_offset as i64 - self.header.version.base_memory_address() as i64
     }
 
+    // on xbox, model vertices and indices are loaded along with the tag data, so
+    // parts point into memory rather than into the model data section
+    pub fn get_model_vertex_data_offset(&self, part: &GbxModelPart) -> u64 {
+        match self.header.version {
+            MapVersion::Xbox => (self.get_tag_data_offset() + part.vert_offset as i64) as u64,
+            _ => (self.tag_index_header.model_data_file_offset + part.vert_offset) as u64,
+        }
+    }
+
+    pub fn get_model_index_data_offset(&self, part: &GbxModelPart) -> u64 {
+        match self.header.version {
+            MapVersion::Xbox => (self.get_tag_data_offset() + part.tri_offset as i64) as u64,
+            _ => (self.tag_index_header.model_data_file_offset + self.tag_index_header.vertex_data_size + part.tri_offset) as u64,
+        }
+    }
+
+    // always in the uncompressed (pc) layout, whatever the map version
+    pub fn read_model_vertices(&mut self, part: &GbxModelPart) -> Result<Vec<u8>> {
+        let offset = self.get_model_vertex_data_offset(part);
+        let count = part.vert_count as usize;
+        if self.header.version.has_compressed_vertices() {
+            let data = self.read_map_bytes(offset, count * COMPRESSED_MODEL_VERTEX_SIZE)?;
+            Ok(decompress_model_vertices(&data))
+        } else {
+            self.read_map_bytes(offset, count * MODEL_VERTEX_SIZE)
+        }
+    }
+
+    pub fn read_model_indices(&mut self, part: &GbxModelPart) -> Result<Vec<u16>> {
+        let offset = self.get_model_index_data_offset(part);
+        self.read_map_u16s(offset, part.tri_count() as usize)
+    }
+
+    // always decodes the tag from scratch, see get_tag for the cached version
     pub fn read_tag(&mut self, tag_header: &TagHeader) -> Result<Tag> {
         self.read_tag_at_offset(tag_header, self.get_tag_data_offset())
     }
 
+    pub fn get_tag(&mut self, tag_header: &TagHeader) -> Result<Rc<Tag>> {
+        if let Some(tag) = self.tag_cache.get(&tag_header.tag_id) {
+            return Ok(tag.clone());
+        }
+        // bsps live outside the tag data, so they're read through the scenario
+        if tag_header.primary_class == TagClass::ScenarioStructureBsp {
+            let scenario = self.get_scenario()?;
+            return self.get_scenario_bsps(&scenario)?.into_iter()
+                .find(|tag| tag.header.tag_id == tag_header.tag_id)
+                .ok_or_else(|| MapReaderError::InvalidTag(format!("bsp {} isn't referenced by the scenario", tag_header.path)).into());
+        }
+        let tag = Rc::new(self.read_tag(tag_header)?);
+        self.tag_cache.insert(tag_header.tag_id, tag.clone());
+        Ok(tag)
+    }
+
+    pub fn get_tag_header(&self, tag_id: u32) -> Option<&TagHeader> {
+        self.tag_headers.get(*self.tag_indices.get(&tag_id)?)
+    }
+
+    // resolves and decodes a dependency, checking that it's the expected type
+    pub fn get_dependency_data<T: Clone>(&mut self, dependency: &TagDependency) -> Result<T>
+        where for<'a> &'a T: TryFrom<&'a TagData, Error = String>
+    {
+        let Some(tag_header) = self.resolve_dependency(dependency) else {
+            return Err(MapReaderError::InvalidTag(format!("couldn't resolve dependency {:?}", dependency)).into());
+        };
+        let tag = self.get_tag(&tag_header)
+            .map_err(|err| MapReaderError::InvalidTag(format!("failed to read {}: {}", tag_header, err)))?;
+        let data: &T = (&tag.data).try_into()
+            .map_err(|err| MapReaderError::InvalidTag(format!("{}: {}", tag_header, err)))?;
+        Ok(data.clone())
+    }
+
     pub fn read_map_bytes(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
         self.reader.data.seek(SeekFrom::Start(offset))?;
         let mut buf = vec![0; size];
@@ -62,14 +148,18 @@ impl MapManager {
     fn read_tag_at_offset(&mut self, tag_header: &TagHeader, offset: i64) -> Result<Tag> {
         let tag_pointer = offset + tag_header.tag_data as i64;
         if tag_pointer < 0 {
-            panic!("invalid tag pointer {} for header {:?}", tag_pointer, tag_header)
+            return Err(MapReaderError::InvalidTag(format!("invalid tag pointer {} for {}", tag_pointer, tag_header)).into());
         }
         self.reader.data.seek(SeekFrom::Start(tag_pointer as u64))?;
         let data = match tag_header.primary_class {
             TagClass::Bitmap => {
                 let mut bitmap = Bitmap::from_reader_with_ctx(&mut self.reader.data, ())?;
                 bitmap.bitmap_group_sequence.read_items(&mut self.reader.data, offset)?;
+                for sequence in bitmap.bitmap_group_sequence.items.as_mut().unwrap() {
+                    sequence.sprites.read_items(&mut self.reader.data, offset)?;
+                }
                 bitmap.data.read_items(&mut self.reader.data, offset)?;
+                bitmap.path = tag_header.path.clone();
                 TagData::Bitmap(bitmap)
             },
             TagClass::Scenario => {
@@ -77,6 +167,38 @@ impl MapManager {
                 scenario.skies.read_items(&mut self.reader.data, offset)?;
                 scenario.scenery.read_items(&mut self.reader.data, offset)?;
                 scenario.scenery_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.bipeds.read_items(&mut self.reader.data, offset)?;
+                scenario.biped_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.vehicles.read_items(&mut self.reader.data, offset)?;
+                scenario.vehicle_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.equipment.read_items(&mut self.reader.data, offset)?;
+                scenario.equipment_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.weapons.read_items(&mut self.reader.data, offset)?;
+                scenario.weapon_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.machines.read_items(&mut self.reader.data, offset)?;
+                scenario.machine_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.controls.read_items(&mut self.reader.data, offset)?;
+                scenario.control_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.light_fixtures.read_items(&mut self.reader.data, offset)?;
+                scenario.light_fixture_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.sound_scenery.read_items(&mut self.reader.data, offset)?;
+                scenario.sound_scenery_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.player_starting_locations.read_items(&mut self.reader.data, offset)?;
+                scenario.recorded_animations.read_items(&mut self.reader.data, offset)?;
+                for animation in scenario.recorded_animations.items.as_mut().unwrap() {
+                    animation.event_data = animation.event_stream.read_data(&mut self.reader.data, offset)?;
+                }
+                scenario.netgame_flags.read_items(&mut self.reader.data, offset)?;
+                scenario.netgame_equipment.read_items(&mut self.reader.data, offset)?;
+                scenario.decals.read_items(&mut self.reader.data, offset)?;
+                scenario.decal_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.detail_object_collection_palette.read_items(&mut self.reader.data, offset)?;
+                scenario.script_syntax_bytes = scenario.script_syntax_data.read_data(&mut self.reader.data, offset)?;
+                scenario.script_string_bytes = scenario.script_string_data.read_data(&mut self.reader.data, offset)?;
+                scenario.scripts.read_items(&mut self.reader.data, offset)?;
+                scenario.globals.read_items(&mut self.reader.data, offset)?;
+                scenario.cutscene_flags.read_items(&mut self.reader.data, offset)?;
+                scenario.cutscene_camera_points.read_items(&mut self.reader.data, offset)?;
                 scenario.structure_bsp_references.read_items(&mut self.reader.data, offset)?;
                 dbg!(&scenario);
                 TagData::Scenario(scenario)
@@ -88,6 +210,38 @@ impl MapManager {
                 for lightmap in bsp.lightmaps.items.as_mut().unwrap() {
                     lightmap.materials.read_items(&mut self.reader.data, offset)?;
                 }
+                bsp.collision_bsp.read_items(&mut self.reader.data, offset)?;
+                for collision_bsp in bsp.collision_bsp.items.as_mut().unwrap() {
+                    collision_bsp.read_items(&mut self.reader.data, offset)?;
+                }
+                bsp.leaves.read_items(&mut self.reader.data, offset)?;
+                bsp.clusters.read_items(&mut self.reader.data, offset)?;
+                for cluster in bsp.clusters.items.as_mut().unwrap() {
+                    cluster.subclusters.read_items(&mut self.reader.data, offset)?;
+                    for subcluster in cluster.subclusters.items.as_mut().unwrap() {
+                        subcluster.surface_indices.read_items(&mut self.reader.data, offset)?;
+                    }
+                    cluster.surface_indices.read_items(&mut self.reader.data, offset)?;
+                    cluster.portals.read_items(&mut self.reader.data, offset)?;
+                }
+                let cluster_data = bsp.cluster_data.read_data(&mut self.reader.data, offset)?;
+                bsp.read_pvs(&cluster_data);
+                bsp.cluster_portals.read_items(&mut self.reader.data, offset)?;
+                for portal in bsp.cluster_portals.items.as_mut().unwrap() {
+                    portal.vertices.read_items(&mut self.reader.data, offset)?;
+                }
+                bsp.fog_planes.read_items(&mut self.reader.data, offset)?;
+                for fog_plane in bsp.fog_planes.items.as_mut().unwrap() {
+                    fog_plane.vertices.read_items(&mut self.reader.data, offset)?;
+                }
+                bsp.fog_regions.read_items(&mut self.reader.data, offset)?;
+                bsp.fog_palette.read_items(&mut self.reader.data, offset)?;
+                bsp.weather_palette.read_items(&mut self.reader.data, offset)?;
+                bsp.weather_polyhedra.read_items(&mut self.reader.data, offset)?;
+                for polyhedron in bsp.weather_polyhedra.items.as_mut().unwrap() {
+                    polyhedron.planes.read_items(&mut self.reader.data, offset)?;
+                }
+                bsp.build_cluster_materials();
                 TagData::BSP(bsp)
             },
             TagClass::ShaderEnvironment => {
@@ -104,6 +258,25 @@ impl MapManager {
                 shader.bitmaps.read_items(&mut self.reader.data, offset)?;
                 TagData::ShaderTransparentChicago(shader)
             },
+            TagClass::ShaderTransparentChicagoExtended => {
+                let mut shader = ShaderTransparentChicagoExtended::from_reader_with_ctx(&mut self.reader.data, ())?;
+                shader.extra_layers.read_items(&mut self.reader.data, offset)?;
+                shader.four_stage_bitmaps.read_items(&mut self.reader.data, offset)?;
+                shader.two_stage_bitmaps.read_items(&mut self.reader.data, offset)?;
+                TagData::ShaderTransparentChicagoExtended(shader)
+            },
+            TagClass::ShaderTransparentGlass => {
+                let shader = ShaderTransparentGlass::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::ShaderTransparentGlass(shader)
+            },
+            TagClass::ShaderTransparentPlasma => {
+                let shader = ShaderTransparentPlasma::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::ShaderTransparentPlasma(shader)
+            },
+            TagClass::ShaderTransparentMeter => {
+                let shader = ShaderTransparentMeter::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::ShaderTransparentMeter(shader)
+            },
             TagClass::ShaderTransparentGeneric => {
                 let mut shader = ShaderTransparentGeneric::from_reader_with_ctx(&mut self.reader.data, ())?;
                 shader.extra_layers.read_items(&mut self.reader.data, offset)?;
@@ -149,22 +322,118 @@ impl MapManager {
                 let scenery = Scenery::from_reader_with_ctx(&mut self.reader.data, ())?;
                 TagData::Scenery(scenery)
             },
+            TagClass::Biped | TagClass::Vehicle | TagClass::Weapon | TagClass::Equipment |
+            TagClass::DeviceMachine | TagClass::DeviceControl | TagClass::SoundScenery => {
+                let mut object = GameObject::from_reader_with_ctx(&mut self.reader.data, ())?;
+                object.attachments.read_items(&mut self.reader.data, offset)?;
+                TagData::Object(object)
+            },
+            TagClass::ModelAnimations => {
+                let mut animations = ModelAnimations::from_reader_with_ctx(&mut self.reader.data, ())?;
+                animations.nodes.read_items(&mut self.reader.data, offset)?;
+                animations.animations.read_items(&mut self.reader.data, offset)?;
+                for animation in animations.animations.items.as_mut().unwrap() {
+                    animation.read_data(&mut self.reader.data, offset)?;
+                }
+                TagData::ModelAnimations(animations)
+            },
+            TagClass::Sound => {
+                let mut sound = Sound::from_reader_with_ctx(&mut self.reader.data, ())?;
+                sound.pitch_ranges.read_items(&mut self.reader.data, offset)?;
+                for pitch_range in sound.pitch_ranges.items.as_mut().unwrap() {
+                    pitch_range.permutations.read_items(&mut self.reader.data, offset)?;
+                }
+                TagData::Sound(sound)
+            },
+            TagClass::SoundLooping => {
+                let mut sound_looping = SoundLooping::from_reader_with_ctx(&mut self.reader.data, ())?;
+                sound_looping.tracks.read_items(&mut self.reader.data, offset)?;
+                sound_looping.detail_sounds.read_items(&mut self.reader.data, offset)?;
+                TagData::SoundLooping(sound_looping)
+            },
+            TagClass::ModelCollisionGeometry => {
+                let mut collision = ModelCollisionGeometry::from_reader_with_ctx(&mut self.reader.data, ())?;
+                collision.nodes.read_items(&mut self.reader.data, offset)?;
+                for node in collision.nodes.items.as_mut().unwrap() {
+                    node.bsps.read_items(&mut self.reader.data, offset)?;
+                    for bsp in node.bsps.items.as_mut().unwrap() {
+                        bsp.read_items(&mut self.reader.data, offset)?;
+                    }
+                }
+                TagData::ModelCollisionGeometry(collision)
+            },
+            TagClass::DeviceLightFixture => {
+                let light_fixture = DeviceLightFixture::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::DeviceLightFixture(light_fixture)
+            },
+            TagClass::Decal => {
+                let decal = Decal::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::Decal(decal)
+            },
+            TagClass::DetailObjectCollection => {
+                let mut collection = DetailObjectCollection::from_reader_with_ctx(&mut self.reader.data, ())?;
+                collection.types.read_items(&mut self.reader.data, offset)?;
+                TagData::DetailObjectCollection(collection)
+            },
             TagClass::Sky => {
-                let sky = Sky::from_reader_with_ctx(&mut self.reader.data, ())?;
+                let mut sky = Sky::from_reader_with_ctx(&mut self.reader.data, ())?;
+                sky.lights.read_items(&mut self.reader.data, offset)?;
                 TagData::Sky(sky)
             },
+            TagClass::Fog => {
+                let fog = Fog::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::Fog(fog)
+            },
+            TagClass::Particle => {
+                let particle = Particle::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::Particle(particle)
+            },
+            TagClass::ParticleSystem => {
+                let mut particle_system = ParticleSystem::from_reader_with_ctx(&mut self.reader.data, ())?;
+                particle_system.particle_types.read_items(&mut self.reader.data, offset)?;
+                for particle_type in particle_system.particle_types.items.as_mut().unwrap() {
+                    particle_type.particle_states.read_items(&mut self.reader.data, offset)?;
+                }
+                TagData::ParticleSystem(particle_system)
+            },
+            TagClass::WeatherParticleSystem => {
+                let mut weather = WeatherParticleSystem::from_reader_with_ctx(&mut self.reader.data, ())?;
+                weather.particle_types.read_items(&mut self.reader.data, offset)?;
+                TagData::WeatherParticleSystem(weather)
+            },
+            TagClass::LensFlare => {
+                let mut lens_flare = LensFlare::from_reader_with_ctx(&mut self.reader.data, ())?;
+                lens_flare.reflections.read_items(&mut self.reader.data, offset)?;
+                TagData::LensFlare(lens_flare)
+            },
+            TagClass::ItemCollection => {
+                let mut collection = ItemCollection::from_reader_with_ctx(&mut self.reader.data, ())?;
+                collection.permutations.read_items(&mut self.reader.data, offset)?;
+                TagData::ItemCollection(collection)
+            },
+            TagClass::Glow => {
+                let glow = Glow::from_reader_with_ctx(&mut self.reader.data, ())?;
+                TagData::Glow(glow)
+            },
             TagClass::GbxModel => {
                 let mut model = GbxModel::from_reader_with_ctx(&mut self.reader.data, ())?;
                 model.geometries.read_items(&mut self.reader.data, offset)?;
-                match &mut model.geometries.items {
-                    Some(geometries) => {
-                        for geometry in geometries {
-                            geometry.parts.read_items(&mut self.reader.data, offset)?;
-                        }
-                    },
-                    None => panic!("failed to load geometries for {:?}", model),
+                for geometry in model.geometries.items.iter_mut().flatten() {
+                    geometry.parts.read_items(&mut self.reader.data, offset)?;
                 }
                 model.shaders.read_items(&mut self.reader.data, offset)?;
+                model.markers.read_items(&mut self.reader.data, offset)?;
+                for marker in model.markers.items.as_mut().unwrap() {
+                    marker.instances.read_items(&mut self.reader.data, offset)?;
+                }
+                model.nodes.read_items(&mut self.reader.data, offset)?;
+                model.regions.read_items(&mut self.reader.data, offset)?;
+                for region in model.regions.items.as_mut().unwrap() {
+                    region.permutations.read_items(&mut self.reader.data, offset)?;
+                    for permutation in region.permutations.items.as_mut().unwrap() {
+                        permutation.markers.read_items(&mut self.reader.data, offset)?;
+                    }
+                }
                 TagData::GbxModel(model)
             },
             _ => return Err(MapReaderError::UnimplementedTag(format!("can't yet read {:?}", tag_header)).into()),
@@ -172,64 +441,258 @@ impl MapManager {
         Ok(Tag { header: tag_header.clone(), data })
     }
 
-    pub fn get_scenario(&mut self) -> Result<Tag> {
-        let header = self.tag_headers.iter()
-            .find(|header| matches!(header.primary_class, TagClass::Scenario))
-            .unwrap().clone();
-        self.read_tag(&header)
+    // every object tag starts with the same header, so this works for scenery
+    // and light fixtures too, which otherwise decode to their own types
+    pub fn read_object(&mut self, tag_header: &TagHeader) -> Result<GameObject> {
+        let offset = self.get_tag_data_offset();
+        let tag_pointer = offset + tag_header.tag_data as i64;
+        self.reader.data.seek(SeekFrom::Start(tag_pointer as u64))?;
+        let mut object = GameObject::from_reader_with_ctx(&mut self.reader.data, ())?;
+        object.attachments.read_items(&mut self.reader.data, offset)?;
+        Ok(object)
     }
 
-    pub fn resolve_dependency(&self, dependency: &TagDependency) -> Option<TagHeader> {
+    // tags whose primary, secondary or tertiary class matches (so that e.g.
+    // Object finds every kind of object), and whose path starts with path_prefix
+    pub fn find_tags(&self, class: Option<TagClass>, path_prefix: Option<&str>) -> Vec<&TagHeader> {
+        let path_prefix = path_prefix.map(|prefix| prefix.to_lowercase().replace('/', "\\"));
         self.tag_headers.iter()
-            .find(|header| header.tag_id == dependency.tag_id)
-            .cloned()
+            .filter(|header| class.is_none_or(|class| [header.primary_class, header.secondary_class, header.tertiary_class].contains(&class)))
+            .filter(|header| path_prefix.as_ref().is_none_or(|prefix| header.path.to_lowercase().starts_with(prefix)))
+            .collect()
+    }
+
+    // a heuristic: rather than walking the tag's known dependency fields, this
+    // scans its data for anything shaped like a dependency (a class followed,
+    // 12 bytes later, by the id of a tag of that class), so it also works for
+    // tags we can't decode yet. unaligned dependencies are missed, and any other
+    // data that happens to look like one is reported too
+    pub fn get_tag_dependencies(&mut self, tag_header: &TagHeader) -> Result<Vec<TagHeader>> {
+        let (start, end) = self.get_tag_data_range(tag_header)?;
+        let data = self.read_map_bytes(start, (end - start) as usize)?;
+        let mut result: Vec<TagHeader> = Vec::new();
+        // dependencies are 16 bytes: class, path pointer, global id and tag id
+        for i in (0..data.len().saturating_sub(15)).step_by(4) {
+            let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+            let Some(dependency) = self.get_tag_header(read_u32(i + 12)) else {
+                continue;
+            };
+            if dependency.primary_class as u32 == read_u32(i) && !result.iter().any(|header| header.tag_id == dependency.tag_id) {
+                result.push(dependency.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    // tags and their blocks are laid out one after another, so a tag's data
+    // runs up to wherever the next tag's starts
+    fn get_tag_data_range(&mut self, tag_header: &TagHeader) -> Result<(u64, u64)> {
+        if tag_header.primary_class == TagClass::ScenarioStructureBsp {
+            let scenario = self.get_scenario()?;
+            let scenario: &Scenario = (&scenario.data).try_into().map_err(MapReaderError::InvalidTag)?;
+            let bsp_ref = scenario.structure_bsp_references.items.as_deref().unwrap_or_default().iter()
+                .find(|bsp_ref| bsp_ref.structure_bsp.tag_id == tag_header.tag_id)
+                .ok_or_else(|| MapReaderError::InvalidTag(format!("bsp {} isn't referenced by the scenario", tag_header)))?;
+            return Ok((bsp_ref.start as u64, bsp_ref.start as u64 + bsp_ref.size as u64));
+        }
+        let base_address = self.header.version.base_memory_address();
+        let tag_data_end = base_address + self.header.tag_data_size;
+        if tag_header.tag_data < base_address || tag_header.tag_data >= tag_data_end {
+            return Err(MapReaderError::InvalidTag(format!("{} has no data in this map", tag_header)).into());
+        }
+        let next_tag_data = self.tag_headers.iter()
+            .filter(|header| header.primary_class != TagClass::ScenarioStructureBsp)
+            .map(|header| header.tag_data)
+            .filter(|&tag_data| tag_data > tag_header.tag_data && tag_data < tag_data_end)
+            .min()
+            .unwrap_or(tag_data_end);
+        let offset = self.get_tag_data_offset();
+        Ok(((offset + tag_header.tag_data as i64) as u64, (offset + next_tag_data as i64) as u64))
+    }
+
+    pub fn get_scenario(&mut self) -> Result<Rc<Tag>> {
+        let Some(header) = self.tag_headers.iter().find(|header| header.primary_class == TagClass::Scenario) else {
+            return Err(MapReaderError::InvalidTag("map has no scenario tag".to_string()).into());
+        };
+        self.get_tag(&header.clone())
     }
 
-    pub fn get_scenario_bsps(&mut self, tag: &Tag) -> Result<Vec<Tag>> {
+    pub fn resolve_dependency(&self, dependency: &TagDependency) -> Option<TagHeader> {
+        self.get_tag_header(dependency.tag_id).cloned()
+    }
+
+    pub fn get_scenario_bsps(&mut self, tag: &Tag) -> Result<Vec<Rc<Tag>>> {
         let TagData::Scenario(scenario) = &tag.data else {
             return Err(MapReaderError::InvalidTag(format!("expected scenario tag, got {:?}", tag.header.primary_class)).into());
         };
         let Some(bsp_references) = &scenario.structure_bsp_references.items else {
             return Err(MapReaderError::InvalidTag("scenario has no bsp references".to_string()).into());
         };
-        let bsp_refs_and_headers: Vec<(&ScenarioStructureBSPReference, TagHeader)> = bsp_references.iter()
-            .map(|bsp_ref| (bsp_ref, self.resolve_dependency(&bsp_ref.structure_bsp).unwrap()))
-            .collect();
+        let bsp_refs_and_headers = bsp_references.iter()
+            .map(|bsp_ref| match self.resolve_dependency(&bsp_ref.structure_bsp) {
+                Some(header) => Ok((bsp_ref, header)),
+                None => Err(MapReaderError::InvalidTag(format!("couldn't resolve bsp dependency {:?}", bsp_ref.structure_bsp))),
+            })
+            .collect::<std::result::Result<Vec<(&ScenarioStructureBSPReference, TagHeader)>, _>>()?;
         let mut result = Vec::new();
         for (bsp_ref, mut header) in bsp_refs_and_headers {
+            if let Some(tag) = self.tag_cache.get(&header.tag_id) {
+                result.push(tag.clone());
+                continue;
+            }
             self.reader.data.seek(SeekFrom::Start(bsp_ref.start as u64))?;
             let bsp_header = BSPHeader::from_reader_with_ctx(&mut self.reader.data, ())?;
             let offset = bsp_ref.start as i64 - bsp_ref.address as i64;
             header.tag_data = bsp_header.bsp_offset;
-            let mut tag = self.read_tag_at_offset(&header, offset).unwrap();
+            let mut tag = self.read_tag_at_offset(&header, offset)
+                .map_err(|err| MapReaderError::InvalidTag(format!("failed to read {}: {}", header, err)))?;
             if let TagData::BSP(bsp) = &mut tag.data {
                 bsp.header = Some(bsp_header);
             }
+            let tag = Rc::new(tag);
+            self.tag_cache.insert(header.tag_id, tag.clone());
             result.push(tag);
         }
         return Ok(result);
     }
 }
 
+// reads bitmaps.map, sounds.map and loc.map, which store external tag data
+// shared between maps
 #[wasm_bindgen(js_name = "HaloBitmapReader")]
 pub struct ResourceMapReader {
     data: deku::reader::Reader<Cursor<Vec<u8>>>,
+    header: Option<ResourcesHeader>,
+    resources: Vec<ResourceHeader>,
 }
 
 #[wasm_bindgen(js_class = "HaloBitmapReader")]
 impl ResourceMapReader {
     pub fn new(data: Vec<u8>) -> ResourceMapReader {
-        ResourceMapReader { data: deku::reader::Reader::new(Cursor::new(data)) }
+        let mut reader = ResourceMapReader { data: deku::reader::Reader::new(Cursor::new(data)), header: None, resources: Vec::new() };
+        // older callers only use raw offsets, so a missing index isn't fatal
+        if let Ok((header, resources)) = reader.read_index() {
+            reader.header = Some(header);
+            reader.resources = resources;
+        }
+        reader
+    }
+
+    pub fn get_resource_type(&self) -> Option<ResourceType> {
+        self.header.as_ref().map(|header| header.resource_type)
+    }
+
+    pub fn get_num_resources(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn get_resource_path(&self, index: usize) -> Option<String> {
+        self.resources.get(index)?.path.clone()
+    }
+
+    pub fn find_resource_index(&self, path: &str) -> Option<usize> {
+        let path = normalize_resource_path(path);
+        self.resources.iter()
+            .position(|resource| resource.path.as_deref().map(normalize_resource_path).as_deref() == Some(path.as_str()))
     }
 
-    pub fn get_and_convert_bitmap_data(&mut self, bitmap: &Bitmap, submap: usize) -> Vec<u8> {
-        let bitmap_data = &bitmap.data.items.as_ref().unwrap()[submap];
+    pub fn get_resource_data(&mut self, index: usize) -> Option<Vec<u8>> {
+        let resource = self.resources.get(index)?;
+        let (offset, size) = (resource.data_offset as u64, resource.size as usize);
+        self.read_bytes(offset, size).ok()
+    }
+
+    pub fn get_and_convert_bitmap_data(&mut self, bitmap: &Bitmap, submap: usize) -> Result<Vec<u8>, String> {
+        let Some(bitmap_data) = bitmap.data.items.as_ref().and_then(|data| data.get(submap)) else {
+            return Err(format!("bitmap has no submap {}", submap));
+        };
         get_and_convert_bitmap_data(&mut self.data, bitmap_data)
+            .map_err(|err| format!("failed to read external bitmap data at 0x{:x}: {}", bitmap_data.pixel_data_offset, err))
+    }
+
+    pub fn get_sound_samples(&mut self, sound: &Sound, permutation: &SoundPermutation) -> Result<Vec<f32>, String> {
+        let data = self.read_bytes(permutation.samples.file_offset as u64, permutation.samples.size as usize)
+            .map_err(|err| format!("failed to read permutation {}: {}", permutation.get_name(), err))?;
+        sound.decode_samples(permutation, &data)
+            .map_err(|err| format!("failed to decode permutation {}: {}", permutation.get_name(), err))
+    }
+
+    // for when the bitmap's pixel data is stored as its own resource. the
+    // submap's pixel data offset is then relative to the start of the resource
+    pub fn get_and_convert_bitmap_data_by_index(&mut self, bitmap: &Bitmap, submap: usize, index: usize) -> Result<Vec<u8>, String> {
+        let Some(mut bitmap_data) = bitmap.data.items.as_ref().and_then(|data| data.get(submap)).cloned() else {
+            return Err(format!("bitmap has no submap {}", submap));
+        };
+        let Some(resource) = self.resources.get(index) else {
+            return Err(format!("no resource {}", index));
+        };
+        let end = bitmap_data.pixel_data_offset.checked_add(bitmap_data.pixel_data_size);
+        if end.is_none_or(|end| end > resource.size) {
+            return Err(format!("submap {} (0x{:x}, {} bytes) doesn't fit in resource {} ({} bytes)",
+                submap, bitmap_data.pixel_data_offset, bitmap_data.pixel_data_size, index, resource.size));
+        }
+        // can't overflow if the resource itself is in bounds, but the index isn't trusted
+        bitmap_data.pixel_data_offset = resource.data_offset.checked_add(bitmap_data.pixel_data_offset)
+            .ok_or_else(|| format!("resource {} has an invalid data offset 0x{:x}", index, resource.data_offset))?;
+        get_and_convert_bitmap_data(&mut self.data, &bitmap_data)
+            .map_err(|err| format!("failed to read resource {} at 0x{:x}: {}", index, bitmap_data.pixel_data_offset, err))
+    }
+
+    pub fn get_and_convert_bitmap_data_by_path(&mut self, bitmap: &Bitmap, submap: usize, path: &str) -> Result<Vec<u8>, String> {
+        let index = self.find_resource_index(path)
+            .ok_or_else(|| format!("no resource named {}", path))?;
+        self.get_and_convert_bitmap_data_by_index(bitmap, submap, index)
+    }
+
+    // resolves the pixel data of an external bitmap through the resource
+    // index by the bitmap's tag path, only falling back to the raw offset
+    // when the resource map has no entry for it
+    pub fn get_and_convert_external_bitmap_data(&mut self, bitmap: &Bitmap, submap: usize) -> Result<Vec<u8>, String> {
+        match self.find_resource_index(&bitmap.path) {
+            Some(index) => self.get_and_convert_bitmap_data_by_index(bitmap, submap, index),
+            None => self.get_and_convert_bitmap_data(bitmap, submap),
+        }
     }
 
     pub fn destroy(self) {}
 }
 
+impl ResourceMapReader {
+    fn read_index(&mut self) -> Result<(ResourcesHeader, Vec<ResourceHeader>)> {
+        self.data.seek(SeekFrom::Start(0))?;
+        let header = ResourcesHeader::from_reader_with_ctx(&mut self.data, ())?;
+        let mut resources = Vec::with_capacity(header.resource_count as usize);
+        for i in 0..header.resource_count {
+            self.data.seek(SeekFrom::Start(header.resources_offset as u64 + i as u64 * 12))?;
+            resources.push(ResourceHeader::from_reader_with_ctx(&mut self.data, ())?);
+        }
+        for resource in resources.iter_mut() {
+            self.data.seek(SeekFrom::Start(header.paths_offset as u64 + resource.path_offset as u64))?;
+            let path = NullTerminatedAsciiString::from_reader_with_ctx(&mut self.data, ())?;
+            resource.path = Some(path.into());
+        }
+        Ok((header, resources))
+    }
+
+    fn read_bytes(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        self.data.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; size];
+        self.data.read_bytes(size, &mut buf, deku::ctx::Order::Msb0)?;
+        Ok(buf)
+    }
+
+    // the resource whose data contains the given offset, for checking raw offsets
+    pub fn find_resource_at_offset(&self, offset: Pointer) -> Option<&ResourceHeader> {
+        self.resources.iter()
+            .find(|resource| offset >= resource.data_offset && resource.data_offset.checked_add(resource.size).is_some_and(|end| offset < end))
+    }
+}
+
+// tag paths are case insensitive, and may use either slash
+fn normalize_resource_path(path: &str) -> String {
+    path.to_lowercase().replace('/', "\\")
+}
+
 pub struct MapReader {
     pub data: deku::reader::Reader<Cursor<Vec<u8>>>,
 }
@@ -246,16 +709,17 @@ impl MapReader {
 
     fn read_tag_index_header(&mut self, header: &Header) -> Result<TagIndexHeader> {
         self.data.seek(SeekFrom::Start(header.tag_data_offset as u64))?;
-        Ok(TagIndexHeader::from_reader_with_ctx(&mut self.data, ())?)
+        Ok(TagIndexHeader::from_reader_with_ctx(&mut self.data, header.version)?)
     }
 
     fn read_tag_headers(&mut self, header: &Header, tag_index_header: &TagIndexHeader) -> Result<Vec<TagHeader>> {
+        let base_address = header.version.base_memory_address();
         let mut result = Vec::with_capacity(tag_index_header.tag_count as usize);
         for i in 0..tag_index_header.tag_count {
-            let data_offset = header.tag_data_offset + 40 + (i * 32);
+            let data_offset = header.tag_data_offset + tag_index_header.tag_array_pointer - base_address + (i * 32);
             self.data.seek(SeekFrom::Start(data_offset as u64))?;
             let mut tag_header = TagHeader::from_reader_with_ctx(&mut self.data, ())?;
-            let path_offset = header.tag_data_offset + tag_header.tag_path - BASE_MEMORY_ADDRESS;
+            let path_offset = header.tag_data_offset + tag_header.tag_path - base_address;
             self.data.seek(SeekFrom::Start(path_offset as u64))?;
             let path = NullTerminatedAsciiString::from_reader_with_ctx(&mut self.data, ())?;
             tag_header.path = path.try_into()?;
@@ -265,7 +729,8 @@ impl MapReader {
     }
 }
 
-#[derive(Debug, Copy, Clone, DekuRead)]
+#[wasm_bindgen(js_name = "HaloResourceType")]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
 #[deku(id_type = "u32")]
 #[repr(u32)]
 pub enum ResourceType {
@@ -291,6 +756,52 @@ pub struct ResourceHeader {
     pub path: Option<String>,
 }
 
+// everything after the header of an xbox map is a single zlib stream
+const MAP_HEADER_SIZE: usize = 2048;
+
+fn decompress_map(map: Vec<u8>, header: &Header) -> Result<Vec<u8>> {
+    if map.len() < MAP_HEADER_SIZE {
+        return Err(MapReaderError::IO(format!("map is too small to be compressed: {} bytes", map.len())).into());
+    }
+    let body = inflate::inflate_bytes_zlib(&map[MAP_HEADER_SIZE..])
+        .map_err(|err| MapReaderError::IO(format!("failed to decompress map: {}", err)))?;
+    let mut result = Vec::with_capacity(header.uncompressed_file_size as usize);
+    result.extend_from_slice(&map[..MAP_HEADER_SIZE]);
+    result.extend_from_slice(&body);
+    Ok(result)
+}
+
+#[wasm_bindgen(js_name = "HaloMapVersion")]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u32")]
+#[repr(u32)]
+pub enum MapVersion {
+    Xbox = 5,
+    Retail = 7,
+    MCC = 13,
+    CustomEdition = 609,
+}
+
+impl MapVersion {
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, MapVersion::Xbox)
+    }
+
+    // where each version's tag data is loaded in memory, which all tag pointers are relative to
+    pub fn base_memory_address(&self) -> Pointer {
+        match self {
+            MapVersion::Xbox => 0x803A6000,
+            MapVersion::Retail | MapVersion::CustomEdition => 0x40440000,
+            MapVersion::MCC => 0x50000000,
+        }
+    }
+
+    // xbox model and bsp vertices are compressed, see vertex_utils
+    pub fn has_compressed_vertices(&self) -> bool {
+        matches!(self, MapVersion::Xbox)
+    }
+}
+
 #[derive(Debug, Copy, Clone, DekuRead)]
 #[deku(id_type = "u16")]
 #[repr(u16)]
@@ -303,8 +814,7 @@ pub enum ScenarioType {
 #[derive(Debug, DekuRead)]
 #[deku(magic = b"daeh")]
 pub struct Header {
-    #[deku(assert_eq = "0xD")]
-    pub mcc: u32,
+    pub version: MapVersion,
     pub uncompressed_file_size: u32,
     pub _padding_length: u32,
     pub tag_data_offset: Pointer,
@@ -322,15 +832,18 @@ pub struct Header {
 }
 
 #[derive(Debug, Clone, DekuRead)]
+#[deku(ctx = "version: MapVersion")]
 pub struct TagIndexHeader {
     pub tag_array_pointer: Pointer,
     pub _checksum: u32,
     pub scenario_tag_id: u32,
     pub tag_count: u32,
     pub model_part_count: u32,
+    // on xbox, these are memory addresses of the vertex and index data
     pub model_data_file_offset: Pointer,
     pub _model_part_count_pc: u32,
     pub vertex_data_size: u32,
+    #[deku(cond = "version != MapVersion::Xbox", default = "0")]
     pub model_data_size: u32,
     // footer == "sgat"
     #[deku(assert_eq = "1952540531")]
@@ -385,7 +898,7 @@ mod tests {
                 _ => unreachable!(),
             }).collect();
         assert!(bsps.len() > 0);
-        let scenario_data = match scenario_tag.data { TagData::Scenario(s) => s, _ => unreachable!(), };
+        let scenario_data = match &scenario_tag.data { TagData::Scenario(s) => s, _ => unreachable!(), };
         for dependency in scenario_data.skies.items.as_ref().unwrap() {
             dbg!(dependency);
             let sky_header = mgr.resolve_dependency(dependency).unwrap();
@@ -407,6 +920,228 @@ mod tests {
         assert!(false);
     }
 
+    #[test]
+    fn test_scenario_decals_and_light_fixtures() {
+        let mut mgr = MapManager::new(read_map("a10.map")).unwrap();
+        let scenario_tag = mgr.get_scenario().unwrap();
+        let TagData::Scenario(scenario) = &scenario_tag.data else {
+            unreachable!();
+        };
+        let decals = scenario.decals.items.as_ref().unwrap();
+        let decal_palette = scenario.decal_palette.items.as_ref().unwrap();
+        assert!(!decals.is_empty());
+        for decal in decals {
+            assert!((decal.decal_type as usize) < decal_palette.len());
+        }
+        for dependency in decal_palette {
+            let hdr = mgr.resolve_dependency(dependency).unwrap();
+            assert!(matches!(mgr.read_tag(&hdr).unwrap().data, TagData::Decal(_)));
+        }
+        for palette_entry in scenario.light_fixture_palette.items.as_ref().unwrap() {
+            let hdr = mgr.resolve_dependency(&palette_entry.obj).unwrap();
+            assert!(matches!(mgr.read_tag(&hdr).unwrap().data, TagData::DeviceLightFixture(_)));
+        }
+    }
+
+    #[test]
+    fn test_object_palettes() {
+        let mut mgr = MapManager::new(read_map("bloodgulch.map")).unwrap();
+        let scenario_tag = mgr.get_scenario().unwrap();
+        let TagData::Scenario(scenario) = &scenario_tag.data else {
+            unreachable!();
+        };
+        let object_types = [
+            ObjectType::Biped, ObjectType::Vehicle, ObjectType::Weapon, ObjectType::Equipment,
+            ObjectType::Scenery, ObjectType::DeviceMachine, ObjectType::DeviceControl,
+            ObjectType::DeviceLightFixture, ObjectType::SoundScenery,
+        ];
+        for object_type in object_types {
+            let palette = scenario.get_object_palette(object_type).unwrap();
+            for instance in scenario.get_object_instances(object_type).unwrap() {
+                assert!((instance.palette_index as usize) < palette.len());
+            }
+            for palette_entry in palette {
+                let Some(hdr) = mgr.resolve_dependency(&palette_entry.obj) else { continue };
+                assert_eq!(mgr.read_object(&hdr).unwrap().object_type, object_type);
+            }
+        }
+    }
+
+    // wraps data in a zlib stream of uncompressed blocks
+    fn zlib_store(data: &[u8]) -> Vec<u8> {
+        let mut result = vec![0x78, 0x01];
+        let chunks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            result.push((i == chunks.len() - 1) as u8);
+            result.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            result.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+            result.extend_from_slice(chunk);
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in data {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        result.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        result
+    }
+
+    // a compressed xbox map holding a single tag with the given data
+    fn xbox_map(tag_class: TagClass, tag_body: &[u8]) -> Vec<u8> {
+        let base = MapVersion::Xbox.base_memory_address();
+        let mut tag_data = Vec::new();
+        // a 36 byte tag index header, one tag header, its path, then its data
+        for x in [base + 36, 0, 0xE1FF0000, 1, 0, 0, 0, 0, 1952540531] {
+            tag_data.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in [tag_class as u32, 0, 0, 0xE1FF0000, base + 68, base + 80, 0, 0] {
+            tag_data.extend_from_slice(&x.to_le_bytes());
+        }
+        tag_data.extend_from_slice(b"levels\\test\0");
+        tag_data.extend_from_slice(tag_body);
+
+        let mut map = Vec::new();
+        map.extend_from_slice(b"daeh");
+        let file_size = (MAP_HEADER_SIZE + tag_data.len()) as u32;
+        for x in [MapVersion::Xbox as u32, file_size, 0, MAP_HEADER_SIZE as u32, tag_data.len() as u32] {
+            map.extend_from_slice(&x.to_le_bytes());
+        }
+        map.resize(MAP_HEADER_SIZE - 4, 0);
+        map.extend_from_slice(&1718579060u32.to_le_bytes());
+        map.extend_from_slice(&zlib_store(&tag_data));
+        map
+    }
+
+    #[test]
+    fn test_compressed_xbox_map() {
+        let mgr = MapManager::new(xbox_map(TagClass::Scenario, &[])).unwrap();
+        assert_eq!(mgr.header.version, MapVersion::Xbox);
+        assert_eq!(mgr.tag_index_header.tag_count, 1);
+        assert_eq!(mgr.tag_headers[0].primary_class, TagClass::Scenario);
+        assert_eq!(mgr.tag_headers[0].path, "levels\\test");
+    }
+
+    #[test]
+    fn test_xbox_model_data_placement() {
+        // one compressed vertex, then a three index strip, in the tag data
+        let mut body = Vec::new();
+        for x in [1.0f32, 2.0, 3.0] {
+            body.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in [511u32 << 22, 1023, 1023 << 11] {
+            body.extend_from_slice(&x.to_le_bytes());
+        }
+        body.extend_from_slice(&[0; 8]);
+        for x in [0u16, 1, 2] {
+            body.extend_from_slice(&x.to_le_bytes());
+        }
+        let mut mgr = MapManager::new(xbox_map(TagClass::Scenario, &body)).unwrap();
+        let body_address = MapVersion::Xbox.base_memory_address() + 80;
+        let part = GbxModelPart {
+            shader_index: 0,
+            centroid: Point3D { x: 0.0, y: 0.0, z: 0.0 },
+            off_by_two_tri_count: 1,
+            tri_offset: body_address + 32,
+            vert_count: 1,
+            vert_offset: body_address,
+        };
+
+        let vertices = mgr.read_model_vertices(&part).unwrap();
+        assert_eq!(vertices.len(), MODEL_VERTEX_SIZE);
+        assert_eq!(&vertices[0..4], &1.0f32.to_le_bytes());
+        // the normal, expanded to three floats
+        assert_eq!(&vertices[20..24], &1.0f32.to_le_bytes());
+        assert_eq!(mgr.read_model_indices(&part).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_tag_cache() {
+        let mut mgr = MapManager::new(xbox_map(TagClass::Sound, &[0; 164])).unwrap();
+        assert!(mgr.get_tag_header(0).is_none());
+        let tag_header = mgr.get_tag_header(0xE1FF0000).unwrap().clone();
+        let tag = mgr.get_tag(&tag_header).unwrap();
+        assert!(Rc::ptr_eq(&tag, &mgr.get_tag(&tag_header).unwrap()));
+
+        let dependency = TagDependency { tag_class: TagClass::Sound, path_pointer: 0, global_id: 0, tag_id: 0xE1FF0000 };
+        let sound: Sound = mgr.get_dependency_data(&dependency).unwrap();
+        assert_eq!(sound.get_num_pitch_ranges(), 0);
+        let err = mgr.get_dependency_data::<Bitmap>(&dependency).unwrap_err().to_string();
+        assert!(err.contains("test (Sound @ 0x"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_tags_and_dependencies() {
+        // a sound whose promotion sound is itself
+        let mut sound = vec![0; 164];
+        sound[0x58..0x5C].copy_from_slice(&(TagClass::Sound as u32).to_le_bytes());
+        sound[0x64..0x68].copy_from_slice(&0xE1FF0000u32.to_le_bytes());
+        let mut mgr = MapManager::new(xbox_map(TagClass::Sound, &sound)).unwrap();
+
+        assert_eq!(mgr.find_tags(Some(TagClass::Sound), None).len(), 1);
+        assert_eq!(mgr.find_tags(None, Some("LEVELS/")).len(), 1);
+        assert!(mgr.find_tags(Some(TagClass::Bitmap), None).is_empty());
+        assert!(mgr.find_tags(None, Some("sound\\")).is_empty());
+
+        let tag_header = mgr.tag_headers[0].clone();
+        let dependencies = mgr.get_tag_dependencies(&tag_header).unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies[0].tag_id, 0xE1FF0000);
+    }
+
+    #[test]
+    fn test_resource_map_index() {
+        let mut data = Vec::new();
+        // header, then two 12 byte resource entries, then paths, then data
+        for x in [2u32, 40, 16, 2, 0, 1, 46, 4, 2, 47] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(b"a\\b\0c\0");
+        data.extend_from_slice(&[1, 2, 3]);
+        let mut reader = ResourceMapReader::new(data);
+        assert_eq!(reader.get_resource_type(), Some(ResourceType::Sounds));
+        assert_eq!(reader.get_num_resources(), 2);
+        assert_eq!(reader.get_resource_path(0).as_deref(), Some("a\\b"));
+        assert_eq!(reader.find_resource_index("A/B"), Some(0));
+        assert_eq!(reader.find_resource_index("c"), Some(1));
+        assert_eq!(reader.get_resource_data(0), Some(vec![1]));
+        assert_eq!(reader.get_resource_data(1), Some(vec![2, 3]));
+        assert_eq!(reader.find_resource_at_offset(48).unwrap().size, 2);
+        assert!(reader.find_resource_at_offset(49).is_none());
+    }
+
+    #[test]
+    fn test_external_bitmap_data() {
+        let mut data = Vec::new();
+        // one bitmap resource at offset 30, whose second byte is the submap's data
+        for x in [1u32, 28, 16, 1, 0, 2, 30] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(b"a\0");
+        data.extend_from_slice(&[1, 2]);
+        let mut reader = ResourceMapReader::new(data);
+
+        let (_, mut bitmap) = Bitmap::from_bytes((&[0; 0x6C], 0)).unwrap();
+        let (_, mut submap) = BitmapData::from_bytes((&[0; 0x30], 0)).unwrap();
+        submap.format = BitmapFormat::A8;
+        submap.pixel_data_offset = 1;
+        submap.pixel_data_size = 1;
+        bitmap.data.items = Some(vec![submap]);
+        // not in the index, so the offset is read as a raw one into the header
+        bitmap.path = "b".into();
+        assert_eq!(reader.get_and_convert_external_bitmap_data(&bitmap, 0).unwrap(), vec![0xFF, 0xFF, 0xFF, 0]);
+        bitmap.path = "A".into();
+        assert_eq!(reader.get_and_convert_external_bitmap_data(&bitmap, 0).unwrap(), vec![0xFF, 0xFF, 0xFF, 2]);
+
+        // past the end of the resource
+        bitmap.data.items.as_mut().unwrap()[0].pixel_data_size = 2;
+        assert!(reader.get_and_convert_bitmap_data_by_index(&bitmap, 0, 0).is_err());
+
+        // a resource running past the end of the address space contains nothing
+        reader.resources[0].data_offset = u32::MAX;
+        assert!(reader.find_resource_at_offset(u32::MAX).is_none());
+        assert!(reader.get_and_convert_bitmap_data_by_index(&bitmap, 0, 0).is_err());
+    }
+
     #[test]
     fn test_shader_counts() {
         use std::collections::HashMap;
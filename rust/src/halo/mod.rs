@@ -6,10 +6,22 @@ pub mod util;
 pub mod tag;
 pub mod bitmap;
 pub mod scenario;
+pub mod collision;
 pub mod model;
+pub mod animation;
 pub mod shader;
+pub mod decal;
+pub mod sound;
+pub mod fog;
+pub mod particle;
+pub mod emitter;
+pub mod netgame;
+pub mod cutscene;
+pub mod script;
+pub mod layout;
 pub mod wasm;
 pub mod bitmap_utils;
+pub mod vertex_utils;
 
 #[wasm_bindgen]
 pub fn init_panic_hook() {
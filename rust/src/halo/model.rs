@@ -1,6 +1,11 @@
 use deku::prelude::*;
+use noclip_macros::tag_layout;
 use crate::halo::common::*;
 use crate::halo::tag::*;
+use crate::halo::scenario::ObjectType;
+use crate::halo::animation::{make_rotation, NodeTransform};
+use crate::halo::util::trim_tag_string;
+use nalgebra_glm::{vec3, Mat4};
 use wasm_bindgen::prelude::*;
 
 #[derive(Debug, Clone, DekuRead)]
@@ -9,60 +14,303 @@ pub struct SkyAnimations {
     pub period: f32,
 }
 
+#[tag_layout(size = 0xD0)]
 #[wasm_bindgen(js_name = "HaloSky")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct Sky {
-    pub model: TagDependency,
-    pub animation_graph: TagDependency,
-    #[deku(pad_bytes_before = "24")]
-    pub indoor_ambient_radiosity_color: ColorRGB,
-    pub indoor_ambient_radiosity_power: f32,
-    pub outdoor_ambient_radiosity_color: ColorRGB,
-    pub outdoor_ambient_radiosity_power: f32,
-    pub outdoor_fog_color: ColorRGB,
-    #[deku(pad_bytes_before = "8")]
-    pub outdoor_fog_max_density: f32,
-    pub outdoor_fog_start_distance: f32,
-    pub outdoor_fog_opaque_distance: f32,
-    pub indoor_fog_color: ColorRGB,
-    #[deku(pad_bytes_before = "8")]
-    pub indoor_fog_max_density: f32,
-    pub indoor_fog_start_distance: f32,
-    #[deku(pad_bytes_after = "56")]
-    pub indoor_fog_opaque_distance: f32,
+    #[offset(0x00)] pub model: TagDependency,
+    #[offset(0x10)] pub animation_graph: TagDependency,
+    #[offset(0x38)] pub indoor_ambient_radiosity_color: ColorRGB,
+    #[offset(0x44)] pub indoor_ambient_radiosity_power: f32,
+    #[offset(0x48)] pub outdoor_ambient_radiosity_color: ColorRGB,
+    #[offset(0x54)] pub outdoor_ambient_radiosity_power: f32,
+    #[offset(0x58)] pub outdoor_fog_color: ColorRGB,
+    #[offset(0x6C)] pub outdoor_fog_max_density: f32,
+    #[offset(0x70)] pub outdoor_fog_start_distance: f32,
+    #[offset(0x74)] pub outdoor_fog_opaque_distance: f32,
+    #[offset(0x78)] pub indoor_fog_color: ColorRGB,
+    #[offset(0x8C)] pub indoor_fog_max_density: f32,
+    #[offset(0x90)] pub indoor_fog_start_distance: f32,
+    #[offset(0x94)] pub indoor_fog_opaque_distance: f32,
+    #[offset(0x98)] pub indoor_fog_screen: TagDependency,
+    #[offset(0xC4)] pub(crate) lights: Block<SkyLight>,
 }
 
+#[wasm_bindgen(js_class = "HaloSky")]
+impl Sky {
+    pub fn get_lights(&self) -> Vec<SkyLight> {
+        self.lights.items.clone().unwrap_or_default()
+    }
+}
+
+// a distant light, e.g. the sun, along with the lens flare drawn where it sits in the sky
+#[tag_layout(size = 0x74)]
+#[wasm_bindgen(js_name = "HaloSkyLight")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SkyLight {
+    #[offset(0x00)] pub lens_flare: TagDependency,
+    #[offset(0x10)]
+    #[deku(count = "32")]
+    pub(crate) lens_flare_marker_name: Vec<u8>,
+    #[offset(0x4C)] pub flags: u32,
+    #[offset(0x50)] pub color: ColorRGB,
+    #[offset(0x5C)] pub power: f32,
+    #[offset(0x60)] pub test_distance: f32,
+    #[offset(0x68)] pub direction_yaw: f32,
+    #[offset(0x6C)] pub direction_pitch: f32,
+    #[offset(0x70)] pub diameter: f32,
+}
+
+#[wasm_bindgen(js_class = "HaloSkyLight")]
+impl SkyLight {
+    pub fn get_lens_flare_marker_name(&self) -> String {
+        trim_tag_string(&self.lens_flare_marker_name)
+    }
+
+    pub fn affects_exteriors(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    pub fn affects_interiors(&self) -> bool {
+        self.flags & 2 != 0
+    }
+}
+
+#[tag_layout(size = 0xE8)]
 #[wasm_bindgen(js_name = "HaloModel")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct GbxModel {
-    #[deku(pad_bytes_before = "48")]
-    pub base_bitmap_u_scale: f32,
-    pub base_bitmap_v_scale: f32,
-    #[deku(pad_bytes_before = "152")]
-    pub(crate) geometries: Block<GbxModelGeometry>,
-    pub(crate) shaders: Block<GbxModelShader>,
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub node_list_checksum: i32,
+    #[offset(0x08)] pub super_high_detail_cutoff: f32,
+    #[offset(0x0C)] pub high_detail_cutoff: f32,
+    #[offset(0x10)] pub medium_detail_cutoff: f32,
+    #[offset(0x14)] pub low_detail_cutoff: f32,
+    #[offset(0x18)] pub super_low_detail_cutoff: f32,
+    #[offset(0x30)] pub base_bitmap_u_scale: f32,
+    #[offset(0x34)] pub base_bitmap_v_scale: f32,
+    #[offset(0xAC)] pub(crate) markers: Block<GbxModelMarker>,
+    #[offset(0xB8)] pub(crate) nodes: Block<GbxModelNode>,
+    #[offset(0xC4)] pub(crate) regions: Block<GbxModelRegion>,
+    #[offset(0xD0)] pub(crate) geometries: Block<GbxModelGeometry>,
+    #[offset(0xDC)] pub(crate) shaders: Block<GbxModelShader>,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GbxModelDetailLevel {
+    SuperHigh = 0,
+    High = 1,
+    Medium = 2,
+    Low = 3,
+    SuperLow = 4,
+}
+
+#[wasm_bindgen(js_class = "HaloModel")]
+impl GbxModel {
+    // cutoffs are the on-screen size of the model in pixels
+    pub fn get_detail_level(&self, screen_size: f32) -> GbxModelDetailLevel {
+        if screen_size >= self.super_high_detail_cutoff {
+            GbxModelDetailLevel::SuperHigh
+        } else if screen_size >= self.high_detail_cutoff {
+            GbxModelDetailLevel::High
+        } else if screen_size >= self.medium_detail_cutoff {
+            GbxModelDetailLevel::Medium
+        } else if screen_size >= self.low_detail_cutoff {
+            GbxModelDetailLevel::Low
+        } else {
+            GbxModelDetailLevel::SuperLow
+        }
+    }
+
+    pub fn get_num_nodes(&self) -> usize {
+        self.nodes.items.as_ref().map_or(0, |nodes| nodes.len())
+    }
+
+    pub fn get_node_names(&self) -> Vec<String> {
+        self.nodes.items.as_ref().unwrap().iter()
+            .map(|node| trim_tag_string(&node.name))
+            .collect()
+    }
+
+    pub fn get_node_parents(&self) -> Vec<i16> {
+        self.nodes.items.as_ref().unwrap().iter()
+            .map(|node| node.parent_node_index)
+            .collect()
+    }
+
+    // model space bind pose for every node, as column-major 4x4 matrices
+    pub fn get_node_matrices(&self) -> Vec<f32> {
+        self.compute_node_matrices().iter()
+            .flat_map(|matrix| matrix.as_slice().to_vec())
+            .collect()
+    }
+
+    pub fn get_region_names(&self) -> Vec<String> {
+        self.regions.items.as_ref().unwrap().iter()
+            .map(|region| trim_tag_string(&region.name))
+            .collect()
+    }
+
+    pub fn get_permutation_names(&self, region_index: usize) -> Vec<String> {
+        self.regions.items.as_ref().unwrap().get(region_index)
+            .and_then(|region| region.permutations.items.as_ref())
+            .map(|permutations| permutations.iter().map(|permutation| trim_tag_string(&permutation.name)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get_marker_names(&self) -> Vec<String> {
+        self.markers.items.as_ref().unwrap().iter()
+            .map(|marker| trim_tag_string(&marker.name))
+            .collect()
+    }
+
+    pub fn get_marker_instances(&self, marker_index: usize) -> Vec<GbxModelMarkerInstance> {
+        self.markers.items.as_ref().unwrap().get(marker_index)
+            .and_then(|marker| marker.instances.items.clone())
+            .unwrap_or_default()
+    }
 }
 
+impl GbxModel {
+    // each node's default transform relative to its parent
+    pub fn get_bind_pose(&self) -> Vec<NodeTransform> {
+        self.nodes.items.as_deref().unwrap_or_default().iter()
+            .map(GbxModelNode::get_default_transform)
+            .collect()
+    }
+
+    pub fn compute_node_matrices(&self) -> Vec<Mat4> {
+        let nodes = self.nodes.items.as_ref().unwrap();
+        let mut result: Vec<Mat4> = Vec::with_capacity(nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            let local = node.get_default_transform().to_mat4();
+            let world = match node.parent_node_index {
+                parent if parent >= 0 && (parent as usize) < i => result[parent as usize] * local,
+                _ => local,
+            };
+            result.push(world);
+        }
+        result
+    }
+
+    // permutations missing from a region, and detail levels missing from a
+    // permutation, fall back to the base permutation and the next highest
+    // detail level respectively
+    pub fn get_geometry_index(&self, region_index: usize, permutation_index: usize, detail_level: GbxModelDetailLevel) -> Option<usize> {
+        let region = self.regions.items.as_ref()?.get(region_index)?;
+        let permutations = region.permutations.items.as_ref()?;
+        let permutation = permutations.get(permutation_index).or_else(|| permutations.first())?;
+        let geometry_count = self.geometries.items.as_ref()?.len();
+        (0..=detail_level as usize).rev()
+            .map(|level| permutation.geometry_indices[level])
+            .find(|&index| index >= 0 && (index as usize) < geometry_count)
+            .map(|index| index as usize)
+    }
+
+    pub fn get_parts(&self, permutation_index: usize, detail_level: GbxModelDetailLevel) -> Vec<GbxModelPart> {
+        let Some(geometries) = self.geometries.items.as_ref() else {
+            return Vec::new();
+        };
+        let num_regions = self.regions.items.as_ref().map_or(0, |regions| regions.len());
+        let mut result = Vec::new();
+        for region_index in 0..num_regions {
+            let Some(geometry_index) = self.get_geometry_index(region_index, permutation_index, detail_level) else {
+                continue;
+            };
+            if let Some(parts) = geometries[geometry_index].parts.items.as_ref() {
+                result.extend_from_slice(parts);
+            }
+        }
+        result
+    }
+}
+
+#[tag_layout(size = 0x40)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GbxModelMarker {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub magic_identifier: i16,
+    #[offset(0x34)] pub instances: Block<GbxModelMarkerInstance>,
+}
+
+#[tag_layout(size = 0x20)]
+#[wasm_bindgen(js_name = "HaloModelMarkerInstance")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GbxModelMarkerInstance {
+    #[offset(0x00)] pub region_index: i8,
+    #[offset(0x01)] pub permutation_index: i8,
+    #[offset(0x02)] pub node_index: i8,
+    #[offset(0x04)] pub translation: Point3D,
+    #[offset(0x10)] pub rotation: Quaternion,
+}
+
+#[tag_layout(size = 0x9C)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GbxModelNode {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub next_sibling_node_index: i16,
+    #[offset(0x22)] pub first_child_node_index: i16,
+    #[offset(0x24)] pub parent_node_index: i16,
+    #[offset(0x28)] pub default_translation: Point3D,
+    #[offset(0x34)] pub default_rotation: Quaternion,
+    #[offset(0x44)] pub node_distance_from_parent: f32,
+}
+
+impl GbxModelNode {
+    pub fn get_default_transform(&self) -> NodeTransform {
+        let Quaternion { i, j, k, w } = self.default_rotation;
+        NodeTransform {
+            rotation: make_rotation(i, j, k, w),
+            translation: vec3(self.default_translation.x, self.default_translation.y, self.default_translation.z),
+            scale: 1.0,
+        }
+    }
+}
+
+#[tag_layout(size = 0x4C)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GbxModelRegion {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x40)] pub permutations: Block<GbxModelPermutation>,
+}
+
+#[tag_layout(size = 0x58)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GbxModelPermutation {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub flags: u32,
+    // stored from super low to super high, reversed so GbxModelDetailLevel can index it
+    #[offset(0x40)]
+    #[deku(map = "|levels: [i16; 5]| -> Result<_, DekuError> { let mut levels = levels; levels.reverse(); Ok(levels) }")]
+    pub geometry_indices: [i16; 5],
+    #[offset(0x4C)] pub markers: Block<GbxModelMarkerInstance>,
+}
+
+#[tag_layout(size = 0x30)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct GbxModelGeometry {
-    #[deku(pad_bytes_before = "36")]
-    pub parts: Block<GbxModelPart>,
+    #[offset(0x24)] pub parts: Block<GbxModelPart>,
 }
 
+#[tag_layout(size = 0x84)]
 #[wasm_bindgen(js_name = "HaloModelPart")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct GbxModelPart {
-    #[deku(pad_bytes_before = "4")]
-    pub shader_index: u16,
-    #[deku(pad_bytes_before = "14")]
-    pub centroid: Point3D,
-    #[deku(pad_bytes_before = "40")]
-    pub off_by_two_tri_count: u32, // always off by 2
-    pub tri_offset: u32,
-    #[deku(pad_bytes_before = "8")]
-    pub vert_count: u32,
-    #[deku(pad_bytes_before = "8", pad_bytes_after = "28")]
-    pub vert_offset: u32,
+    #[offset(0x04)] pub shader_index: u16,
+    #[offset(0x14)] pub centroid: Point3D,
+    #[offset(0x48)] pub off_by_two_tri_count: u32, // always off by 2
+    #[offset(0x4C)] pub tri_offset: u32,
+    #[offset(0x58)] pub vert_count: u32,
+    #[offset(0x64)] pub vert_offset: u32,
 }
 
 #[wasm_bindgen(js_class = "HaloModelPart")]
@@ -72,23 +320,128 @@ impl GbxModelPart {
     }
 }
 
+#[tag_layout(size = 0x20)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct GbxModelShader {
-    pub shader: TagDependency,
-    #[deku(pad_bytes_after = "14")]
-    pub permutation: u16,
+    #[offset(0x00)] pub shader: TagDependency,
+    #[offset(0x10)] pub permutation: u16,
 }
 
+#[tag_layout(size = 0xA0)]
 #[wasm_bindgen(js_name = "HaloScenery")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct Scenery {
-    #[deku(pad_bytes_before = "2")]
-    pub flags: u16,
-    pub bounding_radius: f32,
-    pub bounding_offset: Point3D,
-    pub origin_offset: Point3D,
-    #[deku(assert = "model.tag_class == TagClass::GbxModel", pad_bytes_before = "8")]
+    #[offset(0x02)] pub flags: u16,
+    #[offset(0x04)] pub bounding_radius: f32,
+    #[offset(0x08)] pub bounding_offset: Point3D,
+    #[offset(0x14)] pub origin_offset: Point3D,
+    #[offset(0x28)]
+    #[deku(assert = "model.tag_class == TagClass::GbxModel")]
     pub(crate) model: TagDependency,
-    #[deku(assert = "modifier_shader.tag_class == TagClass::Shader", pad_bytes_before = "88")]
+    #[offset(0x90)]
+    #[deku(assert = "modifier_shader.tag_class == TagClass::Shader")]
     pub modifier_shader: TagDependency,
 }
+
+#[tag_layout(size = 0x38)]
+#[wasm_bindgen(js_name = "HaloLightFixture")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct DeviceLightFixture {
+    #[offset(0x02)] pub flags: u16,
+    #[offset(0x04)] pub bounding_radius: f32,
+    #[offset(0x08)] pub bounding_offset: Point3D,
+    #[offset(0x14)] pub origin_offset: Point3D,
+    #[offset(0x28)]
+    #[deku(assert = "model.tag_class == TagClass::GbxModel")]
+    pub(crate) model: TagDependency,
+}
+
+// the shared header of every object tag (bipeds, vehicles, weapons, devices...)
+#[tag_layout(size = 0x14C)]
+#[wasm_bindgen(js_name = "HaloObject")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct GameObject {
+    #[offset(0x00)] pub object_type: ObjectType,
+    #[offset(0x02)] pub flags: u16,
+    #[offset(0x04)] pub bounding_radius: f32,
+    #[offset(0x08)] pub bounding_offset: Point3D,
+    #[offset(0x14)] pub origin_offset: Point3D,
+    #[offset(0x20)] pub acceleration_scale: f32,
+    #[offset(0x28)] pub(crate) model: TagDependency,
+    #[offset(0x38)] pub animation_graph: TagDependency,
+    #[offset(0x70)] pub(crate) collision_model: TagDependency,
+    #[offset(0x140)] pub(crate) attachments: Block<ObjectAttachment>,
+}
+
+// lights, looping sounds and effects attached to an object's markers
+#[tag_layout(size = 0x48)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ObjectAttachment {
+    #[offset(0x00)] pub attachment_type: TagDependency,
+    #[offset(0x10)]
+    #[deku(count = "32")]
+    pub marker: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block<T>(items: Vec<T>) -> Block<T> {
+        Block { count: items.len() as u32, base_pointer: 0, items: Some(items) }
+    }
+
+    fn permutation(geometry_indices: [i16; 5]) -> GbxModelPermutation {
+        GbxModelPermutation {
+            name: Vec::new(),
+            flags: 0,
+            geometry_indices,
+            markers: block(Vec::new()),
+        }
+    }
+
+    fn test_model() -> GbxModel {
+        let regions = vec![
+            GbxModelRegion { name: Vec::new(), permutations: block(vec![permutation([0, 1, -1, -1, -1]), permutation([2, 2, 2, 2, 2])]) },
+            GbxModelRegion { name: Vec::new(), permutations: block(vec![permutation([3, 3, 3, 3, 3])]) },
+        ];
+        let geometries = (0..4).map(|_| GbxModelGeometry { parts: block(Vec::new()) }).collect();
+        GbxModel {
+            flags: 0,
+            node_list_checksum: 0,
+            super_high_detail_cutoff: 300.0,
+            high_detail_cutoff: 200.0,
+            medium_detail_cutoff: 100.0,
+            low_detail_cutoff: 50.0,
+            super_low_detail_cutoff: 0.0,
+            base_bitmap_u_scale: 1.0,
+            base_bitmap_v_scale: 1.0,
+            markers: block(Vec::new()),
+            nodes: block(Vec::new()),
+            regions: block(regions),
+            geometries: block(geometries),
+            shaders: block(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_geometry_index_fallback() {
+        let model = test_model();
+        assert_eq!(model.get_geometry_index(0, 0, GbxModelDetailLevel::SuperHigh), Some(0));
+        assert_eq!(model.get_geometry_index(0, 0, GbxModelDetailLevel::High), Some(1));
+        // missing detail levels use the next highest one
+        assert_eq!(model.get_geometry_index(0, 0, GbxModelDetailLevel::SuperLow), Some(1));
+        assert_eq!(model.get_geometry_index(0, 1, GbxModelDetailLevel::Medium), Some(2));
+        // regions without the permutation use their base permutation
+        assert_eq!(model.get_geometry_index(1, 1, GbxModelDetailLevel::Medium), Some(3));
+        assert_eq!(model.get_geometry_index(2, 0, GbxModelDetailLevel::Medium), None);
+    }
+
+    #[test]
+    fn test_detail_level() {
+        let model = test_model();
+        assert_eq!(model.get_detail_level(1000.0), GbxModelDetailLevel::SuperHigh);
+        assert_eq!(model.get_detail_level(120.0), GbxModelDetailLevel::Medium);
+        assert_eq!(model.get_detail_level(10.0), GbxModelDetailLevel::SuperLow);
+    }
+}
@@ -0,0 +1,180 @@
+use deku::prelude::*;
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::scenario::*;
+use crate::halo::tag::*;
+
+#[wasm_bindgen(js_name = "HaloNetgameFlagType")]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum NetgameFlagType {
+    CtfFlag = 0,
+    CtfVehicle = 1,
+    OddballBallSpawn = 2,
+    RaceTrack = 3,
+    RaceVehicle = 4,
+    VegasBank = 5,
+    TeleportFrom = 6,
+    TeleportTo = 7,
+    HillFlag = 8,
+}
+
+impl_tag_layout!(NetgameFlagType => 2);
+
+#[wasm_bindgen(js_name = "HaloNetgameGameType")]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u16)]
+pub enum NetgameGameType {
+    CaptureTheFlag = 1,
+    Slayer = 2,
+    Oddball = 3,
+    KingOfTheHill = 4,
+    Race = 5,
+    Terminator = 6,
+    Stub = 7,
+    AllGames = 12,
+    AllExceptCaptureTheFlag = 13,
+    AllExceptRaceAndCaptureTheFlag = 14,
+}
+
+impl NetgameGameType {
+    // spawns list up to four game types, unused slots are 0 (none)
+    pub fn from_slots(slots: &[u16]) -> Vec<NetgameGameType> {
+        use NetgameGameType::*;
+        slots.iter().filter_map(|slot| Some(match slot {
+            1 => CaptureTheFlag,
+            2 => Slayer,
+            3 => Oddball,
+            4 => KingOfTheHill,
+            5 => Race,
+            6 => Terminator,
+            7 => Stub,
+            12 => AllGames,
+            13 => AllExceptCaptureTheFlag,
+            14 => AllExceptRaceAndCaptureTheFlag,
+            _ => return None,
+        })).collect()
+    }
+}
+
+#[wasm_bindgen(js_name = "HaloNetgameMarkerType")]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NetgameMarkerType {
+    PlayerSpawn,
+    Flag,
+    Equipment,
+}
+
+// a spawn point, flag or weapon spawn of a multiplayer map, flattened so it
+// can be drawn as an overlay
+#[wasm_bindgen(js_name = "HaloNetgameMarker")]
+#[derive(Debug, Clone)]
+pub struct NetgameMarker {
+    pub marker_type: NetgameMarkerType,
+    // only set for flags
+    pub flag_type: Option<NetgameFlagType>,
+    pub position: Point3D,
+    // radians around the z axis
+    pub facing: f32,
+    // for flags, this is the team for ctf and the hill/track index otherwise
+    pub team_index: u16,
+    pub(crate) game_types: Vec<NetgameGameType>,
+    pub item_collection: Option<TagDependency>,
+}
+
+#[wasm_bindgen(js_class = "HaloNetgameMarker")]
+impl NetgameMarker {
+    pub fn get_game_types(&self) -> Vec<NetgameGameType> {
+        self.game_types.clone()
+    }
+}
+
+impl NetgameMarker {
+    pub fn from_starting_location(location: &ScenarioPlayerStartingLocation) -> Self {
+        NetgameMarker {
+            marker_type: NetgameMarkerType::PlayerSpawn,
+            flag_type: None,
+            position: location.position,
+            facing: location.facing,
+            team_index: location.team_index,
+            game_types: NetgameGameType::from_slots(&location.game_types()),
+            item_collection: None,
+        }
+    }
+
+    pub fn from_flag(flag: &ScenarioNetgameFlag) -> Self {
+        NetgameMarker {
+            marker_type: NetgameMarkerType::Flag,
+            flag_type: Some(flag.flag_type),
+            position: flag.position,
+            facing: flag.facing,
+            team_index: flag.team_index,
+            game_types: Vec::new(),
+            item_collection: None,
+        }
+    }
+
+    pub fn from_equipment(equipment: &ScenarioNetgameEquipment) -> Self {
+        NetgameMarker {
+            marker_type: NetgameMarkerType::Equipment,
+            flag_type: None,
+            position: equipment.position,
+            facing: equipment.facing,
+            team_index: equipment.team_index,
+            game_types: NetgameGameType::from_slots(&equipment.game_types()),
+            item_collection: Some(equipment.item_collection),
+        }
+    }
+}
+
+// a weighted list of items (weapons, powerups) to pick from at a spawn
+#[tag_layout(size = 0x5C)]
+#[wasm_bindgen(js_name = "HaloItemCollection")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ItemCollection {
+    #[offset(0x00)] pub(crate) permutations: Block<ItemCollectionPermutation>,
+    #[offset(0x0C)] pub default_spawn_time: i16,
+}
+
+#[wasm_bindgen(js_class = "HaloItemCollection")]
+impl ItemCollection {
+    pub fn get_permutations(&self) -> Vec<ItemCollectionPermutation> {
+        self.permutations.items.clone().unwrap_or_default()
+    }
+}
+
+#[tag_layout(size = 0x54)]
+#[wasm_bindgen(js_name = "HaloItemCollectionPermutation")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ItemCollectionPermutation {
+    #[offset(0x20)] pub weight: f32,
+    #[offset(0x24)] pub item: TagDependency,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    #[test]
+    fn test_equipment_marker() {
+        let equipment: ScenarioNetgameEquipment = read_test_tag(&[
+            (0x04, &2u16.to_le_bytes()),
+            (0x06, &9u16.to_le_bytes()),
+            (0x08, &12u16.to_le_bytes()),
+            (0x0C, &1u16.to_le_bytes()),
+            (0x48, &5.0f32.to_le_bytes()),
+            (0x4C, &1.5f32.to_le_bytes()),
+        ]);
+
+        let marker = NetgameMarker::from_equipment(&equipment);
+        assert_eq!(marker.marker_type, NetgameMarkerType::Equipment);
+        assert_eq!(marker.get_game_types(), vec![NetgameGameType::Slayer, NetgameGameType::AllGames]);
+        assert_eq!((marker.position.z, marker.facing, marker.team_index), (5.0, 1.5, 1));
+        assert!(marker.item_collection.is_some());
+    }
+}
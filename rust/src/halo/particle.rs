@@ -0,0 +1,291 @@
+use deku::prelude::*;
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::shader::{FramebufferBlendFunction, FramebufferFadeMode, FunctionSource};
+use crate::halo::tag::*;
+use crate::halo::util::trim_tag_string;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum ParticleOrientation {
+    ScreenFacing = 0,
+    ParallelToDirection = 1,
+    PerpendicularToDirection = 2,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum WeatherRenderDirectionSource {
+    FromVelocity = 0,
+    FromAcceleration = 1,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum GlowBoundaryEffect {
+    Bounce = 0,
+    Wrap = 1,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum GlowParticleDistribution {
+    DistributedRandomly = 0,
+    DistributedUniformly = 1,
+}
+
+impl_tag_layout!(
+    ParticleOrientation => 2,
+    WeatherRenderDirectionSource => 2,
+    GlowBoundaryEffect => 2,
+    GlowParticleDistribution => 2,
+);
+
+#[tag_layout(size = 0x164)]
+#[wasm_bindgen(js_name = "HaloParticle")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Particle {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub bitmap: TagDependency,
+    #[offset(0x14)] pub physics: TagDependency,
+    #[offset(0x38)] pub lifespan: Bounds,
+    #[offset(0x40)] pub fade_in_time: f32,
+    #[offset(0x44)] pub fade_out_time: f32,
+    #[offset(0x48)] pub collision_effect: TagDependency,
+    #[offset(0x58)] pub death_effect: TagDependency,
+    #[offset(0x68)] pub minimum_size: f32,
+    #[offset(0x74)] pub radius_animation: Bounds,
+    #[offset(0x80)] pub animation_rate: Bounds,
+    #[offset(0x88)] pub contact_deterioration: f32,
+    #[offset(0x8C)] pub fade_start_size: f32,
+    #[offset(0x90)] pub fade_end_size: f32,
+    #[offset(0x98)] pub first_sequence_index: i16,
+    #[offset(0x9A)] pub initial_sequence_count: i16,
+    #[offset(0x9C)] pub looping_sequence_count: i16,
+    #[offset(0x9E)] pub final_sequence_count: i16,
+    #[offset(0xAC)] pub orientation: ParticleOrientation,
+    #[offset(0xB0)] pub shader_flags: u16,
+    #[offset(0xB2)] pub framebuffer_blend_function: FramebufferBlendFunction,
+    #[offset(0xB4)] pub framebuffer_fade_mode: FramebufferFadeMode,
+}
+
+#[tag_layout(size = 0x68)]
+#[wasm_bindgen(js_name = "HaloParticleSystem")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ParticleSystem {
+    #[offset(0x38)] pub point_physics: TagDependency,
+    #[offset(0x4C)] pub physics_flags: u32,
+    #[offset(0x5C)] pub(crate) particle_types: Block<ParticleSystemType>,
+}
+
+#[wasm_bindgen(js_class = "HaloParticleSystem")]
+impl ParticleSystem {
+    pub fn get_particle_types(&self) -> Vec<ParticleSystemType> {
+        self.particle_types.items.clone().unwrap_or_default()
+    }
+}
+
+#[tag_layout(size = 0x80)]
+#[wasm_bindgen(js_name = "HaloParticleSystemType")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ParticleSystemType {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub flags: u32,
+    #[offset(0x24)] pub initial_particle_count: i16,
+    #[offset(0x28)] pub complex_sprite_render_modes: u16,
+    #[offset(0x2C)] pub radius: f32,
+    #[offset(0x58)] pub physics_flags: u32,
+    #[offset(0x74)] pub(crate) particle_states: Block<ParticleSystemParticleState>,
+}
+
+#[wasm_bindgen(js_class = "HaloParticleSystemType")]
+impl ParticleSystemType {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+
+    pub fn get_particle_states(&self) -> Vec<ParticleSystemParticleState> {
+        self.particle_states.items.clone().unwrap_or_default()
+    }
+}
+
+// one stage of a particle system particle's life, with its own sprite and colors
+#[tag_layout(size = 0x178)]
+#[wasm_bindgen(js_name = "HaloParticleSystemParticleState")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ParticleSystemParticleState {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub duration_bounds: Bounds,
+    #[offset(0x28)] pub transition_time_bounds: Bounds,
+    #[offset(0x30)] pub bitmaps: TagDependency,
+    #[offset(0x40)] pub sequence_index: i16,
+    #[offset(0x48)] pub scale: Bounds,
+    #[offset(0x50)] pub animation_rate: Bounds,
+    #[offset(0x58)] pub rotation_rate: Bounds,
+    #[offset(0x60)] pub color_1: ColorARGB,
+    #[offset(0x70)] pub color_2: ColorARGB,
+    #[offset(0x80)] pub radius_multiplier: f32,
+    #[offset(0x84)] pub point_physics: TagDependency,
+    #[offset(0xB8)] pub shader_flags: u16,
+    #[offset(0xBA)] pub framebuffer_blend_function: FramebufferBlendFunction,
+    #[offset(0xBC)] pub framebuffer_fade_mode: FramebufferFadeMode,
+}
+
+#[wasm_bindgen(js_class = "HaloParticleSystemParticleState")]
+impl ParticleSystemParticleState {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+#[tag_layout(size = 0x30)]
+#[wasm_bindgen(js_name = "HaloWeatherParticleSystem")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct WeatherParticleSystem {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x24)] pub(crate) particle_types: Block<WeatherParticleType>,
+}
+
+#[wasm_bindgen(js_class = "HaloWeatherParticleSystem")]
+impl WeatherParticleSystem {
+    pub fn get_particle_types(&self) -> Vec<WeatherParticleType> {
+        self.particle_types.items.clone().unwrap_or_default()
+    }
+}
+
+// e.g. the rain or snow of a weather system. the particles live in a volume
+// around the camera, fading in and out with distance and height
+#[tag_layout(size = 0x25C)]
+#[wasm_bindgen(js_name = "HaloWeatherParticleType")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct WeatherParticleType {
+    #[offset(0x000)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x020)] pub flags: u32,
+    #[offset(0x024)] pub fade_in_start_distance: f32,
+    #[offset(0x028)] pub fade_in_end_distance: f32,
+    #[offset(0x02C)] pub fade_out_start_distance: f32,
+    #[offset(0x030)] pub fade_out_end_distance: f32,
+    #[offset(0x034)] pub fade_in_start_height: f32,
+    #[offset(0x038)] pub fade_in_end_height: f32,
+    #[offset(0x03C)] pub fade_out_start_height: f32,
+    #[offset(0x040)] pub fade_out_end_height: f32,
+    #[offset(0x0A4)] pub particle_count: Bounds,
+    #[offset(0x0AC)] pub physics: TagDependency,
+    #[offset(0x0CC)] pub acceleration_magnitude: Bounds,
+    #[offset(0x0D4)] pub acceleration_turning_rate: f32,
+    #[offset(0x0D8)] pub acceleration_change_rate: f32,
+    #[offset(0x0FC)] pub particle_radius: Bounds,
+    #[offset(0x104)] pub animation_rate: Bounds,
+    #[offset(0x10C)] pub rotation_rate: Bounds,
+    #[offset(0x134)] pub color_lower_bound: ColorARGB,
+    #[offset(0x144)] pub color_upper_bound: ColorARGB,
+    #[offset(0x194)] pub sprite_bitmap: TagDependency,
+    #[offset(0x1A4)] pub render_mode: ParticleOrientation,
+    #[offset(0x1A6)] pub render_direction_source: WeatherRenderDirectionSource,
+    #[offset(0x1D0)] pub shader_flags: u16,
+    #[offset(0x1D2)] pub framebuffer_blend_function: FramebufferBlendFunction,
+    #[offset(0x1D4)] pub framebuffer_fade_mode: FramebufferFadeMode,
+}
+
+#[wasm_bindgen(js_class = "HaloWeatherParticleType")]
+impl WeatherParticleType {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+#[tag_layout(size = 0xF0)]
+#[wasm_bindgen(js_name = "HaloLensFlare")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct LensFlare {
+    #[offset(0x00)] pub falloff_angle: f32,
+    #[offset(0x04)] pub cutoff_angle: f32,
+    #[offset(0x10)] pub occlusion_radius: f32,
+    #[offset(0x18)] pub near_fade_distance: f32,
+    #[offset(0x1C)] pub far_fade_distance: f32,
+    #[offset(0x20)] pub bitmap: TagDependency,
+    #[offset(0x30)] pub flags: u16,
+    #[offset(0x80)] pub rotation_function: u16,
+    #[offset(0x84)] pub rotation_function_scale: f32,
+    #[offset(0xA0)] pub horizontal_scale: f32,
+    #[offset(0xA4)] pub vertical_scale: f32,
+    #[offset(0xC4)] pub(crate) reflections: Block<LensFlareReflection>,
+}
+
+#[wasm_bindgen(js_class = "HaloLensFlare")]
+impl LensFlare {
+    pub fn get_reflections(&self) -> Vec<LensFlareReflection> {
+        self.reflections.items.clone().unwrap_or_default()
+    }
+}
+
+// one sprite of a lens flare, placed along the line from the light through the screen center
+#[tag_layout(size = 0x80)]
+#[wasm_bindgen(js_name = "HaloLensFlareReflection")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct LensFlareReflection {
+    #[offset(0x00)] pub flags: u16,
+    #[offset(0x04)] pub bitmap_index: i16,
+    #[offset(0x1C)] pub position: f32,
+    #[offset(0x20)] pub rotation_offset: f32,
+    #[offset(0x28)] pub radius: Bounds,
+    #[offset(0x34)] pub brightness: Bounds,
+    #[offset(0x40)] pub tint_color: ColorARGB,
+    #[offset(0x50)] pub color_lower_bound: ColorARGB,
+    #[offset(0x60)] pub color_upper_bound: ColorARGB,
+}
+
+#[tag_layout(size = 0x154)]
+#[wasm_bindgen(js_name = "HaloGlow")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Glow {
+    #[offset(0x000)]
+    #[deku(count = "32")]
+    pub(crate) attachment_marker: Vec<u8>,
+    #[offset(0x020)] pub number_of_particles: i16,
+    #[offset(0x022)] pub boundary_effect: GlowBoundaryEffect,
+    #[offset(0x024)] pub normal_particle_distribution: GlowParticleDistribution,
+    #[offset(0x026)] pub trailing_particle_distribution: GlowParticleDistribution,
+    #[offset(0x028)] pub flags: u32,
+    #[offset(0x050)] pub particle_rotational_velocity_source: FunctionSource,
+    #[offset(0x054)] pub particle_rotational_velocity: f32,
+    #[offset(0x064)] pub effect_rotational_velocity: f32,
+    #[offset(0x074)] pub effect_translational_velocity: f32,
+    #[offset(0x084)] pub min_distance_particle_to_object: f32,
+    #[offset(0x088)] pub max_distance_particle_to_object: f32,
+    #[offset(0x094)] pub particle_size_bounds: Bounds,
+    #[offset(0x0A8)] pub color_bound_0: ColorARGB,
+    #[offset(0x0B8)] pub color_bound_1: ColorARGB,
+    #[offset(0x0E8)] pub color_rate_of_change: f32,
+    #[offset(0x0EC)] pub fading_percentage_of_glow: f32,
+    #[offset(0x0F0)] pub particle_generation_frequency: f32,
+    #[offset(0x0F4)] pub lifetime_of_trailing_particles: f32,
+    #[offset(0x0F8)] pub velocity_of_trailing_particles: f32,
+    #[offset(0x0FC)] pub trailing_particle_minimum_t: f32,
+    #[offset(0x100)] pub trailing_particle_maximum_t: f32,
+    #[offset(0x138)] pub texture: TagDependency,
+}
+
+#[wasm_bindgen(js_class = "HaloGlow")]
+impl Glow {
+    pub fn get_attachment_marker(&self) -> String {
+        trim_tag_string(&self.attachment_marker)
+    }
+}
@@ -1,10 +1,17 @@
 use deku::prelude::*;
+use noclip_macros::tag_layout;
 use wasm_bindgen::prelude::*;
 
 use crate::{halo::common::*, unity::types::common::NullTerminatedAsciiString};
+use crate::halo::layout::impl_tag_layout;
 use crate::halo::tag::*;
+use crate::halo::collision::*;
+use crate::halo::netgame::*;
+use crate::halo::cutscene::*;
+use crate::halo::script::*;
 
-#[derive(Debug, Copy, Clone, DekuRead)]
+#[wasm_bindgen(js_name = "HaloObjectType")]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
 #[deku(id_type = "u16")]
 #[repr(u16)]
 pub enum ObjectType {
@@ -22,6 +29,8 @@ pub enum ObjectType {
     SoundScenery = 0xB,
 }
 
+impl_tag_layout!(ObjectType => 2);
+
 #[derive(Debug, DekuRead)]
 pub struct ObjectName {
     pub name: NullTerminatedAsciiString,
@@ -29,49 +38,336 @@ pub struct ObjectName {
     pub index: u16,
 }
 
+#[tag_layout(size = 0x48)]
 #[wasm_bindgen(js_name = "HaloSceneryInstance")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ScenarioScenery {
-    pub scenery_type: u16,
-    pub name_index: u16,
-    pub not_placed: u32,
-    pub position: Point3D,
-    pub rotation: Euler3D,
-    #[deku(pad_bytes_after = "38")]
-    pub _appearance_player_index: u16,
+    #[offset(0x00)] pub scenery_type: u16,
+    #[offset(0x02)] pub name_index: u16,
+    #[offset(0x04)] pub not_placed: u16,
+    #[offset(0x06)] pub desired_permutation: u16,
+    #[offset(0x08)] pub position: Point3D,
+    #[offset(0x14)] pub rotation: Euler3D,
+    #[offset(0x20)] pub _appearance_player_index: u16,
+}
+
+#[wasm_bindgen(js_class = "HaloSceneryInstance")]
+impl ScenarioScenery {
+    pub fn get_model_matrix(&self) -> Vec<f32> {
+        compute_model_matrix(&self.position, &self.rotation).as_slice().to_vec()
+    }
 }
 
+#[tag_layout(size = 0x30)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ObjectSwatch {
-    #[deku(pad_bytes_after = "32")]
-    pub obj: TagDependency,
+    #[offset(0x00)] pub obj: TagDependency,
 }
 
+#[tag_layout(size = 0x5B0)]
+#[wasm_bindgen(js_name = "HaloScenario")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct Scenario {
-    #[deku(pad_bytes_before = "48")]
-    pub skies: Block<TagDependency>,
-    #[deku(pad_bytes_before = "468")]
-    pub scenery: Block<ScenarioScenery>,
-    pub scenery_palette: Block<ObjectSwatch>,
-    #[deku(pad_bytes_before = "156")]
-    pub light_fixtures: Block<ScenarioLightFixture>,
-    pub light_fixture_palette: Block<ObjectSwatch>,
-    #[deku(pad_bytes_before = "204")]
-    pub decals: Block<ScenarioDecal>,
-    pub decal_palette: Block<ObjectSwatch>,
-    pub detail_object_collection_palette: Block<ObjectSwatch>,
-    #[deku(pad_bytes_before = "472")]
-    pub structure_bsp_references: Block<ScenarioStructureBSPReference>,
+    #[offset(0x030)] pub(crate) skies: Block<TagDependency>,
+    #[offset(0x210)] pub(crate) scenery: Block<ScenarioScenery>,
+    #[offset(0x21C)] pub(crate) scenery_palette: Block<ObjectSwatch>,
+    #[offset(0x228)] pub(crate) bipeds: Block<ScenarioBiped>,
+    #[offset(0x234)] pub(crate) biped_palette: Block<ObjectSwatch>,
+    #[offset(0x240)] pub(crate) vehicles: Block<ScenarioVehicle>,
+    #[offset(0x24C)] pub(crate) vehicle_palette: Block<ObjectSwatch>,
+    #[offset(0x258)] pub(crate) equipment: Block<ScenarioEquipment>,
+    #[offset(0x264)] pub(crate) equipment_palette: Block<ObjectSwatch>,
+    #[offset(0x270)] pub(crate) weapons: Block<ScenarioWeapon>,
+    #[offset(0x27C)] pub(crate) weapon_palette: Block<ObjectSwatch>,
+    // device groups
+    #[offset(0x294)] pub(crate) machines: Block<ScenarioMachine>,
+    #[offset(0x2A0)] pub(crate) machine_palette: Block<ObjectSwatch>,
+    #[offset(0x2AC)] pub(crate) controls: Block<ScenarioControl>,
+    #[offset(0x2B8)] pub(crate) control_palette: Block<ObjectSwatch>,
+    #[offset(0x2C4)] pub(crate) light_fixtures: Block<ScenarioLightFixture>,
+    #[offset(0x2D0)] pub(crate) light_fixture_palette: Block<ObjectSwatch>,
+    #[offset(0x2DC)] pub(crate) sound_scenery: Block<ScenarioSoundScenery>,
+    #[offset(0x2E8)] pub(crate) sound_scenery_palette: Block<ObjectSwatch>,
+    // player starting profile
+    #[offset(0x354)] pub(crate) player_starting_locations: Block<ScenarioPlayerStartingLocation>,
+    // trigger volumes
+    #[offset(0x36C)] pub(crate) recorded_animations: Block<RecordedAnimation>,
+    #[offset(0x378)] pub(crate) netgame_flags: Block<ScenarioNetgameFlag>,
+    #[offset(0x384)] pub(crate) netgame_equipment: Block<ScenarioNetgameEquipment>,
+    // starting equipment, bsp switch trigger volumes
+    #[offset(0x3A8)] pub(crate) decals: Block<ScenarioDecal>,
+    #[offset(0x3B4)] pub(crate) decal_palette: Block<TagDependency>,
+    #[offset(0x3C0)] pub(crate) detail_object_collection_palette: Block<ObjectSwatch>,
+    // ai
+    #[offset(0x474)] pub(crate) script_syntax_data: TagDataOffset,
+    #[offset(0x488)] pub(crate) script_string_data: TagDataOffset,
+    #[offset(0x49C)] pub(crate) scripts: Block<ScenarioScript>,
+    #[offset(0x4A8)] pub(crate) globals: Block<ScenarioGlobal>,
+    // references, source files
+    #[offset(0x4E4)] pub(crate) cutscene_flags: Block<CutsceneFlag>,
+    #[offset(0x4F0)] pub(crate) cutscene_camera_points: Block<CutsceneCameraPoint>,
+    // cutscene titles, custom object names, help text, hud messages
+    #[offset(0x5A4)] pub(crate) structure_bsp_references: Block<ScenarioStructureBSPReference>,
+    // the raw script_syntax_data and script_string_data, see get_script_syntax()
+    #[deku(skip)]
+    pub(crate) script_syntax_bytes: Vec<u8>,
+    #[deku(skip)]
+    pub(crate) script_string_bytes: Vec<u8>,
+}
+
+#[wasm_bindgen(js_class = "HaloScenario")]
+impl Scenario {
+    pub fn get_skies(&self) -> Vec<TagDependency> {
+        self.skies.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_decal_palette(&self) -> Vec<TagDependency> {
+        self.decal_palette.items.clone().unwrap_or_default()
+    }
+}
+
+impl Scenario {
+    pub fn get_object_palette(&self, object_type: ObjectType) -> Option<&[ObjectSwatch]> {
+        let palette = match object_type {
+            ObjectType::Biped => &self.biped_palette,
+            ObjectType::Vehicle => &self.vehicle_palette,
+            ObjectType::Weapon => &self.weapon_palette,
+            ObjectType::Equipment => &self.equipment_palette,
+            ObjectType::Scenery => &self.scenery_palette,
+            ObjectType::DeviceMachine => &self.machine_palette,
+            ObjectType::DeviceControl => &self.control_palette,
+            ObjectType::DeviceLightFixture => &self.light_fixture_palette,
+            ObjectType::SoundScenery => &self.sound_scenery_palette,
+            _ => return None,
+        };
+        palette.items.as_deref()
+    }
+
+    pub fn get_object_instances(&self, object_type: ObjectType) -> Option<Vec<ScenarioObjectInstance>> {
+        fn collect<T>(block: &Block<T>, object_type: ObjectType, base: impl Fn(&T) -> &ScenarioObject) -> Option<Vec<ScenarioObjectInstance>> {
+            let items = block.items.as_ref()?;
+            Some(items.iter().map(|item| ScenarioObjectInstance::new(object_type, base(item))).collect())
+        }
+        match object_type {
+            ObjectType::Biped => collect(&self.bipeds, object_type, |x| &x.object),
+            ObjectType::Vehicle => collect(&self.vehicles, object_type, |x| &x.object),
+            ObjectType::Weapon => collect(&self.weapons, object_type, |x| &x.object),
+            ObjectType::Equipment => collect(&self.equipment, object_type, |x| &x.object),
+            ObjectType::DeviceMachine => collect(&self.machines, object_type, |x| &x.object),
+            ObjectType::DeviceControl => collect(&self.controls, object_type, |x| &x.object),
+            ObjectType::SoundScenery => collect(&self.sound_scenery, object_type, |x| &x.object),
+            ObjectType::Scenery => Some(self.scenery.items.as_ref()?.iter()
+                .map(|x| ScenarioObjectInstance {
+                    object_type,
+                    palette_index: x.scenery_type,
+                    name_index: x.name_index,
+                    not_placed: x.not_placed,
+                    desired_permutation: x.desired_permutation,
+                    position: x.position,
+                    rotation: x.rotation,
+                }).collect()),
+            ObjectType::DeviceLightFixture => Some(self.light_fixtures.items.as_ref()?.iter()
+                .map(|x| ScenarioObjectInstance {
+                    object_type,
+                    palette_index: x.light_type,
+                    name_index: x.name,
+                    not_placed: x.not_placed,
+                    desired_permutation: x.desired_permutation,
+                    position: x.position,
+                    rotation: x.rotation,
+                }).collect()),
+            _ => None,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn get_script_syntax(&self) -> anyhow::Result<ScriptSyntax> {
+        ScriptSyntax::new(&self.script_syntax_bytes, &self.script_string_bytes)
+    }
+
+    pub fn get_netgame_markers(&self) -> Vec<NetgameMarker> {
+        let locations = self.player_starting_locations.items.as_deref().unwrap_or_default().iter()
+            .map(NetgameMarker::from_starting_location);
+        let flags = self.netgame_flags.items.as_deref().unwrap_or_default().iter()
+            .map(NetgameMarker::from_flag);
+        let equipment = self.netgame_equipment.items.as_deref().unwrap_or_default().iter()
+            .map(NetgameMarker::from_equipment);
+        locations.chain(flags).chain(equipment).collect()
+    }
+}
+
+#[tag_layout(size = 0x34)]
+#[wasm_bindgen(js_name = "HaloScenarioPlayerStartingLocation")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioPlayerStartingLocation {
+    #[offset(0x00)] pub position: Point3D,
+    #[offset(0x0C)] pub facing: f32,
+    #[offset(0x10)] pub team_index: u16,
+    #[offset(0x12)] pub bsp_index: u16,
+    #[offset(0x14)] pub game_type_0: u16,
+    #[offset(0x16)] pub game_type_1: u16,
+    #[offset(0x18)] pub game_type_2: u16,
+    #[offset(0x1A)] pub game_type_3: u16,
+}
+
+impl ScenarioPlayerStartingLocation {
+    pub fn game_types(&self) -> [u16; 4] {
+        [self.game_type_0, self.game_type_1, self.game_type_2, self.game_type_3]
+    }
+}
+
+#[tag_layout(size = 0x94)]
+#[wasm_bindgen(js_name = "HaloScenarioNetgameFlag")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioNetgameFlag {
+    #[offset(0x00)] pub position: Point3D,
+    #[offset(0x0C)] pub facing: f32,
+    #[offset(0x10)] pub flag_type: NetgameFlagType,
+    #[offset(0x12)] pub team_index: u16,
+    #[offset(0x14)] pub weapon_group: TagDependency,
+}
+
+#[tag_layout(size = 0x90)]
+#[wasm_bindgen(js_name = "HaloScenarioNetgameEquipment")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioNetgameEquipment {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub game_type_0: u16,
+    #[offset(0x06)] pub game_type_1: u16,
+    #[offset(0x08)] pub game_type_2: u16,
+    #[offset(0x0A)] pub game_type_3: u16,
+    #[offset(0x0C)] pub team_index: u16,
+    #[offset(0x0E)] pub spawn_time: i16,
+    #[offset(0x40)] pub position: Point3D,
+    #[offset(0x4C)] pub facing: f32,
+    #[offset(0x50)] pub item_collection: TagDependency,
+}
+
+impl ScenarioNetgameEquipment {
+    pub fn game_types(&self) -> [u16; 4] {
+        [self.game_type_0, self.game_type_1, self.game_type_2, self.game_type_3]
+    }
+
+    pub fn is_levitating(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+// the fields every placed object starts with
+#[tag_layout(size = 0x20)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioObject {
+    #[offset(0x00)] pub palette_index: u16,
+    #[offset(0x02)] pub name_index: u16,
+    #[offset(0x04)] pub not_placed: u16,
+    #[offset(0x06)] pub desired_permutation: u16,
+    #[offset(0x08)] pub position: Point3D,
+    #[offset(0x14)] pub rotation: Euler3D,
+}
+
+#[tag_layout(size = 0x78)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioBiped {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x48)] pub body_vitality: f32,
+    #[offset(0x4C)] pub flags: u32,
+}
+
+#[tag_layout(size = 0x78)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioVehicle {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x48)] pub body_vitality: f32,
+    #[offset(0x4C)] pub flags: u32,
+    #[offset(0x58)] pub multiplayer_team_index: i8,
+    #[offset(0x5A)] pub multiplayer_spawn_flags: u16,
+}
+
+#[tag_layout(size = 0x28)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioEquipment {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x22)] pub misc_flags: u16,
+}
+
+#[tag_layout(size = 0x5C)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioWeapon {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x48)] pub rounds_left: i16,
+    #[offset(0x4A)] pub rounds_loaded: i16,
+    #[offset(0x4C)] pub flags: u16,
 }
 
+#[tag_layout(size = 0x40)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioMachine {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x28)] pub power_group: u16,
+    #[offset(0x2A)] pub position_group: u16,
+    #[offset(0x2C)] pub device_flags: u32,
+    #[offset(0x30)] pub machine_flags: u32,
+}
+
+#[tag_layout(size = 0x40)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioControl {
+    #[offset(0x00)] pub object: ScenarioObject,
+    #[offset(0x28)] pub power_group: u16,
+    #[offset(0x2A)] pub position_group: u16,
+    #[offset(0x2C)] pub device_flags: u32,
+    #[offset(0x30)] pub control_flags: u32,
+    #[offset(0x34)] pub custom: i16,
+}
+
+#[tag_layout(size = 0x28)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioSoundScenery {
+    #[offset(0x00)] pub object: ScenarioObject,
+}
+
+// a placed object of any type, flattened for JS
+#[wasm_bindgen(js_name = "HaloObjectInstance")]
+#[derive(Debug, Clone)]
+pub struct ScenarioObjectInstance {
+    pub object_type: ObjectType,
+    pub palette_index: u16,
+    pub name_index: u16,
+    pub not_placed: u16,
+    pub desired_permutation: u16,
+    pub position: Point3D,
+    pub rotation: Euler3D,
+}
+
+impl ScenarioObjectInstance {
+    fn new(object_type: ObjectType, object: &ScenarioObject) -> Self {
+        ScenarioObjectInstance {
+            object_type,
+            palette_index: object.palette_index,
+            name_index: object.name_index,
+            not_placed: object.not_placed,
+            desired_permutation: object.desired_permutation,
+            position: object.position,
+            rotation: object.rotation,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "HaloObjectInstance")]
+impl ScenarioObjectInstance {
+    pub fn get_model_matrix(&self) -> Vec<f32> {
+        compute_model_matrix(&self.position, &self.rotation).as_slice().to_vec()
+    }
+}
+
+#[tag_layout(size = 0x20)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ScenarioStructureBSPReference {
-    pub start: u32,
-    pub size: u32,
-    pub address: u32,
-    #[deku(pad_bytes_before = "4")]
-    pub structure_bsp: TagDependency,
+    #[offset(0x00)] pub start: u32,
+    #[offset(0x04)] pub size: u32,
+    #[offset(0x08)] pub address: u32,
+    #[offset(0x10)] pub structure_bsp: TagDependency,
 }
 
 #[derive(Debug, Clone, DekuRead)]
@@ -85,67 +381,374 @@ pub struct BSPHeader {
     pub _class: TagClass,
 }
 
+#[tag_layout(size = 0x27C)]
 #[wasm_bindgen(js_name = "HaloBSP")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct BSP {
-    pub lightmaps_bitmap: TagDependency,
-    #[deku(pad_bytes_before = "28")]
-    pub default_ambient_color: ColorRGB,
-    #[deku(pad_bytes_before = "4")]
-    pub default_distant_light0_color: ColorRGB,
-    pub default_distant_light0_direction: Vector3D,
-    pub default_distant_light1_color: ColorRGB,
-    pub default_distant_light1_direction: Vector3D,
-    #[deku(pad_bytes_before = "12")]
-    pub default_reflection_tint: ColorARGB,
-    pub default_shadow_vector: Vector3D,
-    pub default_shadow_color: ColorRGB,
-    #[deku(pad_bytes_before = "88")]
-    pub(crate) surfaces: Block<Tri>,
-    #[deku(pad_bytes_after = "364")]
-    pub(crate) lightmaps: Block<BSPLightmap>,
+    #[offset(0x00)] pub lightmaps_bitmap: TagDependency,
+    #[offset(0x2C)] pub default_ambient_color: ColorRGB,
+    #[offset(0x3C)] pub default_distant_light0_color: ColorRGB,
+    #[offset(0x48)] pub default_distant_light0_direction: Vector3D,
+    #[offset(0x54)] pub default_distant_light1_color: ColorRGB,
+    #[offset(0x60)] pub default_distant_light1_direction: Vector3D,
+    #[offset(0x78)] pub default_reflection_tint: ColorARGB,
+    #[offset(0x88)] pub default_shadow_vector: Vector3D,
+    #[offset(0x94)] pub default_shadow_color: ColorRGB,
+    #[offset(0xB0)] pub(crate) collision_bsp: Block<CollisionBSP>,
+    #[offset(0xC8)] pub world_bounds_x: Bounds,
+    #[offset(0xD0)] pub world_bounds_y: Bounds,
+    #[offset(0xD8)] pub world_bounds_z: Bounds,
+    #[offset(0xE0)] pub(crate) leaves: Block<BSPLeaf>,
+    #[offset(0xF8)] pub(crate) surfaces: Block<Tri>,
+    #[offset(0x104)] pub(crate) lightmaps: Block<BSPLightmap>,
+    #[offset(0x134)] pub(crate) clusters: Block<BSPCluster>,
+    #[offset(0x140)] pub(crate) cluster_data: TagDataOffset,
+    #[offset(0x154)] pub(crate) cluster_portals: Block<BSPClusterPortal>,
+    #[offset(0x178)] pub(crate) fog_planes: Block<BSPFogPlane>,
+    #[offset(0x184)] pub(crate) fog_regions: Block<BSPFogRegion>,
+    #[offset(0x190)] pub(crate) fog_palette: Block<BSPFogPaletteEntry>,
+    #[offset(0x1B4)] pub(crate) weather_palette: Block<BSPWeatherPaletteEntry>,
+    #[offset(0x1C0)] pub(crate) weather_polyhedra: Block<BSPWeatherPolyhedron>,
     #[deku(skip)]
     pub(crate) header: Option<BSPHeader>,
+    // one row of cluster_count bits per cluster, unpacked from cluster_data
+    #[deku(skip)]
+    pub(crate) pvs: Vec<u32>,
+    // indices of the lightmap materials that have surfaces in each cluster,
+    // counting through every lightmap's materials in order
+    #[deku(skip)]
+    pub(crate) cluster_materials: Vec<Vec<u32>>,
+}
+
+#[wasm_bindgen(js_class = "HaloBSP")]
+impl BSP {
+    pub fn get_num_clusters(&self) -> usize {
+        self.clusters.items.as_ref().map_or(0, |clusters| clusters.len())
+    }
+
+    pub fn find_cluster(&self, x: f32, y: f32, z: f32) -> Option<u32> {
+        let leaf_index = self.collision_bsp.items.as_ref()?.first()?.find_leaf(&Point3D { x, y, z })?;
+        let leaf = self.leaves.items.as_ref()?.get(leaf_index as usize)?;
+        if leaf.cluster < 0 { None } else { Some(leaf.cluster as u32) }
+    }
+
+    // when the camera is outside of every cluster, everything is visible
+    pub fn get_visible_clusters(&self, x: f32, y: f32, z: f32) -> Vec<u32> {
+        let num_clusters = self.get_num_clusters();
+        let Some(cluster) = self.find_cluster(x, y, z) else {
+            return (0..num_clusters as u32).collect();
+        };
+        (0..num_clusters as u32)
+            .filter(|&other| other == cluster || self.is_cluster_visible(cluster as usize, other as usize))
+            .collect()
+    }
+
+    pub fn get_visible_materials(&self, x: f32, y: f32, z: f32) -> Vec<u32> {
+        let mut visible = vec![false; self.cluster_materials.iter().flatten().max().map_or(0, |max| *max as usize + 1)];
+        for cluster in self.get_visible_clusters(x, y, z) {
+            if let Some(materials) = self.cluster_materials.get(cluster as usize) {
+                for &material in materials {
+                    visible[material as usize] = true;
+                }
+            }
+        }
+        (0..visible.len() as u32).filter(|&material| visible[material as usize]).collect()
+    }
+
+    pub fn is_point_in_solid(&self, x: f32, y: f32, z: f32) -> bool {
+        match self.collision_bsp.items.as_ref().and_then(|bsps| bsps.first()) {
+            Some(collision_bsp) => collision_bsp.is_point_in_solid(&Point3D { x, y, z }),
+            None => false,
+        }
+    }
+
+    // origin and direction are xyz triples
+    pub fn ray_cast(&self, origin: &[f32], direction: &[f32], max_distance: f32) -> Option<CollisionHit> {
+        assert_eq!(origin.len(), 3);
+        assert_eq!(direction.len(), 3);
+        let origin = Point3D { x: origin[0], y: origin[1], z: origin[2] };
+        let direction = Vector3D { i: direction[0], j: direction[1], k: direction[2] };
+        self.collision_bsp.items.as_ref()?.first()?.ray_cast(&origin, &direction, max_distance)
+    }
+
+    pub fn get_ground_height(&self, x: f32, y: f32, z: f32) -> Option<f32> {
+        self.collision_bsp.items.as_ref()?.first()?.get_ground_height(&Point3D { x, y, z })
+    }
+
+    pub fn get_cluster_portals(&self) -> Vec<BSPClusterPortal> {
+        self.cluster_portals.items.as_ref().cloned().unwrap_or_default()
+    }
+
+    pub fn get_fog_planes(&self) -> Vec<BSPFogPlane> {
+        self.fog_planes.items.as_ref().cloned().unwrap_or_default()
+    }
+
+    // index into the scenario's skies, or None for indoor clusters
+    pub fn get_cluster_sky(&self, cluster: usize) -> Option<u32> {
+        let sky = self.clusters.items.as_ref()?.get(cluster)?.sky;
+        if sky < 0 { None } else { Some(sky as u32) }
+    }
+}
+
+impl BSP {
+    fn get_fog_palette_dependency(&self, index: i16) -> Option<&TagDependency> {
+        if index < 0 {
+            return None;
+        }
+        Some(&self.fog_palette.items.as_ref()?.get(index as usize)?.fog)
+    }
+
+    pub fn get_cluster_fog_dependency(&self, cluster: usize) -> Option<&TagDependency> {
+        self.get_fog_palette_dependency(self.clusters.items.as_ref()?.get(cluster)?.fog)
+    }
+
+    pub fn get_cluster_weather(&self, cluster: usize) -> Option<&BSPWeatherPaletteEntry> {
+        let weather = self.clusters.items.as_ref()?.get(cluster)?.weather;
+        if weather < 0 {
+            return None;
+        }
+        self.weather_palette.items.as_ref()?.get(weather as usize)
+    }
+
+    pub fn is_sheltered_from_weather(&self, point: &Point3D) -> bool {
+        self.weather_polyhedra.items.as_deref().unwrap_or_default().iter()
+            .any(|polyhedron| polyhedron.contains(point))
+    }
+
+    // the fog below a fog plane is given by the region in front of it
+    pub fn get_fog_plane_fog_dependency(&self, fog_plane: usize) -> Option<&TagDependency> {
+        let region = self.fog_planes.items.as_ref()?.get(fog_plane)?.front_region;
+        if region < 0 {
+            return None;
+        }
+        self.get_fog_palette_dependency(self.fog_regions.items.as_ref()?.get(region as usize)?.fog_palette_index)
+    }
+
+    fn pvs_row_length(&self) -> usize {
+        self.get_num_clusters().div_ceil(32)
+    }
+
+    pub fn is_cluster_visible(&self, from: usize, to: usize) -> bool {
+        let word = from * self.pvs_row_length() + to / 32;
+        self.pvs.get(word).is_some_and(|bits| bits & (1 << (to % 32)) != 0)
+    }
+
+    pub(crate) fn read_pvs(&mut self, cluster_data: &[u8]) {
+        self.pvs = cluster_data.chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+    }
+
+    pub(crate) fn build_cluster_materials(&mut self) {
+        // (first surface, surface count, material index) for every material
+        let mut material_ranges = Vec::new();
+        for lightmap in self.lightmaps.items.as_deref().unwrap_or_default() {
+            for material in lightmap.materials.items.as_deref().unwrap_or_default() {
+                let index = material_ranges.len() as u32;
+                material_ranges.push((material.surfaces, material.surface_count, index));
+            }
+        }
+        material_ranges.sort_by_key(|(first, _, _)| *first);
+
+        let find_material = |surface: i32| -> Option<u32> {
+            let i = material_ranges.partition_point(|(first, _, _)| *first <= surface).checked_sub(1)?;
+            let (first, count, index) = material_ranges[i];
+            if surface < first + count { Some(index) } else { None }
+        };
+
+        self.cluster_materials = self.clusters.items.as_deref().unwrap_or_default().iter()
+            .map(|cluster| {
+                let mut materials: Vec<u32> = cluster.surface_indices()
+                    .filter_map(&find_material)
+                    .collect();
+                materials.sort_unstable();
+                materials.dedup();
+                materials
+            })
+            .collect();
+    }
+}
+
+#[tag_layout(size = 0x10)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPLeaf {
+    #[offset(0x08)] pub cluster: i16,
+    #[offset(0x0A)] pub surface_reference_count: i16,
+    #[offset(0x0C)] pub first_surface_reference_index: i32,
+}
+
+#[tag_layout(size = 0x68)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPCluster {
+    #[offset(0x00)] pub sky: i16,
+    #[offset(0x02)] pub fog: i16,
+    #[offset(0x04)] pub background_sound: i16,
+    #[offset(0x06)] pub sound_environment: i16,
+    #[offset(0x08)] pub weather: i16,
+    #[offset(0x0A)] pub transition_structure_bsp: i16,
+    #[offset(0x0C)] pub first_decal_index: i16,
+    #[offset(0x0E)] pub decal_count: i16,
+    #[offset(0x34)] pub subclusters: Block<BSPSubcluster>,
+    #[offset(0x40)] pub first_lens_flare_marker_index: i16,
+    #[offset(0x42)] pub lens_flare_marker_count: i16,
+    #[offset(0x44)] pub surface_indices: Block<BSPSurfaceIndex>,
+    #[offset(0x5C)] pub portals: Block<BSPClusterPortalIndex>,
+}
+
+#[tag_layout(size = 0x04)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct BSPSurfaceIndex {
+    #[offset(0x00)] pub index: i32,
+}
+
+#[tag_layout(size = 0x02)]
+#[derive(Debug, Clone, Copy, DekuRead)]
+pub struct BSPClusterPortalIndex {
+    #[offset(0x00)] pub portal: i16,
 }
 
+impl BSPCluster {
+    pub fn surface_indices(&self) -> impl Iterator<Item = i32> + '_ {
+        let subcluster_surfaces = self.subclusters.items.as_deref().unwrap_or_default().iter()
+            .flat_map(|subcluster| subcluster.surface_indices.items.as_deref().unwrap_or_default().iter());
+        self.surface_indices.items.as_deref().unwrap_or_default().iter()
+            .chain(subcluster_surfaces)
+            .map(|surface| surface.index)
+    }
+}
+
+#[tag_layout(size = 0x24)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPSubcluster {
+    #[offset(0x00)] pub world_bounds_x: Bounds,
+    #[offset(0x08)] pub world_bounds_y: Bounds,
+    #[offset(0x10)] pub world_bounds_z: Bounds,
+    #[offset(0x18)] pub surface_indices: Block<BSPSurfaceIndex>,
+}
+
+#[tag_layout(size = 0x40)]
+#[wasm_bindgen(js_name = "HaloClusterPortal")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPClusterPortal {
+    #[offset(0x00)] pub front_cluster: i16,
+    #[offset(0x02)] pub back_cluster: i16,
+    #[offset(0x04)] pub plane_index: i32,
+    #[offset(0x08)] pub centroid: Point3D,
+    #[offset(0x14)] pub bounding_radius: f32,
+    #[offset(0x18)] pub flags: u32,
+    #[offset(0x34)] pub(crate) vertices: Block<Point3D>,
+}
+
+#[wasm_bindgen(js_class = "HaloClusterPortal")]
+impl BSPClusterPortal {
+    pub fn get_vertices(&self) -> Vec<f32> {
+        self.vertices.items.as_deref().unwrap_or_default().iter()
+            .flat_map(|v| [v.x, v.y, v.z])
+            .collect()
+    }
+}
+
+#[tag_layout(size = 0x20)]
+#[wasm_bindgen(js_name = "HaloFogPlane")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPFogPlane {
+    #[offset(0x00)] pub front_region: i16,
+    #[offset(0x04)] pub plane: Plane3D,
+    #[offset(0x14)] pub(crate) vertices: Block<Point3D>,
+}
+
+#[wasm_bindgen(js_class = "HaloFogPlane")]
+impl BSPFogPlane {
+    pub fn get_vertices(&self) -> Vec<f32> {
+        self.vertices.items.as_deref().unwrap_or_default().iter()
+            .flat_map(|v| [v.x, v.y, v.z])
+            .collect()
+    }
+}
+
+#[tag_layout(size = 0x28)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPFogRegion {
+    #[offset(0x24)] pub fog_palette_index: i16,
+    #[offset(0x26)] pub weather_palette_index: i16,
+}
+
+#[tag_layout(size = 0x88)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPFogPaletteEntry {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub fog: TagDependency,
+    #[offset(0x34)]
+    #[deku(count = "32")]
+    pub fog_scale_function: Vec<u8>,
+}
+
+#[tag_layout(size = 0xF0)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPWeatherPaletteEntry {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub particle_system: TagDependency,
+    #[offset(0x80)] pub wind: TagDependency,
+    #[offset(0x90)] pub wind_direction: Vector3D,
+    #[offset(0x9C)] pub wind_magnitude: f32,
+}
+
+// weather doesn't fall inside these convex volumes, e.g. under an overhang
+#[tag_layout(size = 0x20)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct BSPWeatherPolyhedron {
+    #[offset(0x00)] pub bounding_sphere_center: Point3D,
+    #[offset(0x0C)] pub bounding_sphere_radius: f32,
+    #[offset(0x14)] pub planes: Block<Plane3D>,
+}
+
+impl BSPWeatherPolyhedron {
+    pub fn contains(&self, point: &Point3D) -> bool {
+        self.planes.items.as_deref().unwrap_or_default().iter()
+            .all(|plane| plane.distance_to(point) <= 0.0)
+    }
+}
+
+#[tag_layout(size = 0x20)]
 #[wasm_bindgen(js_name = "HaloLightmap")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct BSPLightmap {
-    pub bitmap_index: u16,
-    #[deku(pad_bytes_before = "18")]
-    pub(crate) materials: Block<BSPMaterial>,
+    #[offset(0x00)] pub bitmap_index: u16,
+    #[offset(0x14)] pub(crate) materials: Block<BSPMaterial>,
 }
 
+#[tag_layout(size = 0x100)]
 #[wasm_bindgen(js_name = "HaloMaterial")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct BSPMaterial {
-    pub(crate) shader: TagDependency,
-    pub shader_permutation: u16,
-    pub flags: u16,
-    pub surfaces: i32,
-    pub surface_count: i32,
-    pub centroid: Point3D,
-    pub ambient_color: ColorRGB,
-    pub distant_light_count: u16,
-    #[deku(pad_bytes_before = "2")]
-    pub distant_light0_color: ColorRGB,
-    pub distant_light0_direction: Vector3D,
-    pub distant_light1_color: ColorRGB,
-    pub distant_light1_direction: Vector3D,
-    #[deku(pad_bytes_before = "12")]
-    pub reflection_tint: ColorARGB,
-    pub shadow_vector: Vector3D,
-    pub shadow_color: ColorRGB,
-    pub plane: Plane3D,
-    #[deku(pad_bytes_before = "4", assert = "*rendered_vertices_type == RenderedVerticesType::StructureBSPUncompressedRenderedVertices")]
+    #[offset(0x00)] pub(crate) shader: TagDependency,
+    #[offset(0x10)] pub shader_permutation: u16,
+    #[offset(0x12)] pub flags: u16,
+    #[offset(0x14)] pub surfaces: i32,
+    #[offset(0x18)] pub surface_count: i32,
+    #[offset(0x1C)] pub centroid: Point3D,
+    #[offset(0x28)] pub ambient_color: ColorRGB,
+    #[offset(0x34)] pub distant_light_count: u16,
+    #[offset(0x38)] pub distant_light0_color: ColorRGB,
+    #[offset(0x44)] pub distant_light0_direction: Vector3D,
+    #[offset(0x50)] pub distant_light1_color: ColorRGB,
+    #[offset(0x5C)] pub distant_light1_direction: Vector3D,
+    #[offset(0x74)] pub reflection_tint: ColorARGB,
+    #[offset(0x84)] pub shadow_vector: Vector3D,
+    #[offset(0x90)] pub shadow_color: ColorRGB,
+    #[offset(0x9C)] pub plane: Plane3D,
+    // compressed on xbox
+    #[offset(0xB0)]
+    #[deku(assert = "matches!(*rendered_vertices_type, RenderedVerticesType::StructureBSPUncompressedRenderedVertices | RenderedVerticesType::StructureBSPCompressedRenderedVertices)")]
     pub rendered_vertices_type: RenderedVerticesType,
-    #[deku(pad_bytes_before = "2")]
-    pub(crate) rendered_vertices: Block<RenderedVertex>,
-    #[deku(pad_bytes_before = "8")]
-    pub(crate) lightmap_vertices: Block<LightmapVertex>,
-    #[deku(pad_bytes_before = "4", assert = "_uncompressed_vertices.file_offset != 0")]
-    pub(crate) _uncompressed_vertices: TagDataOffset,
-    pub(crate) _compressed_vertices: TagDataOffset,
+    #[offset(0xB4)] pub(crate) rendered_vertices: Block<RenderedVertex>,
+    #[offset(0xC8)] pub(crate) lightmap_vertices: Block<LightmapVertex>,
+    #[offset(0xD8)] pub(crate) _uncompressed_vertices: TagDataOffset,
+    #[offset(0xEC)] pub(crate) _compressed_vertices: TagDataOffset,
 }
 
 #[wasm_bindgen(js_class = "HaloMaterial")]
@@ -159,21 +762,23 @@ impl BSPMaterial {
     }
 }
 
+#[tag_layout(size = 0x38)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct RenderedVertex {
-    pub position: Vector3D,
-    pub normal: Vector3D,
-    pub binormal: Vector3D,
-    pub tangent: Vector3D,
-    pub u: f32,
-    pub v: f32,
+    #[offset(0x00)] pub position: Vector3D,
+    #[offset(0x0C)] pub normal: Vector3D,
+    #[offset(0x18)] pub binormal: Vector3D,
+    #[offset(0x24)] pub tangent: Vector3D,
+    #[offset(0x30)] pub u: f32,
+    #[offset(0x34)] pub v: f32,
 }
 
+#[tag_layout(size = 0x14)]
 #[derive(Debug, Clone, DekuRead)]
 pub struct LightmapVertex {
-    pub normal: Vector3D,
-    pub u: f32,
-    pub v: f32,
+    #[offset(0x00)] pub normal: Vector3D,
+    #[offset(0x0C)] pub u: f32,
+    #[offset(0x10)] pub v: f32,
 }
 
 #[wasm_bindgen]
@@ -189,28 +794,53 @@ pub enum RenderedVerticesType {
     ModelCompressed = 5,
 }
 
+impl_tag_layout!(RenderedVerticesType => 2);
+
+#[tag_layout(size = 0x10)]
+#[wasm_bindgen(js_name = "HaloDecalInstance")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ScenarioDecal {
-    pub decal_type: u16,
-    pub yaw: i8,
-    pub pitch: i8,
-    pub position: Point3D,
+    #[offset(0x00)] pub decal_type: u16,
+    #[offset(0x02)] pub yaw: i8,
+    #[offset(0x03)] pub pitch: i8,
+    #[offset(0x04)] pub position: Point3D,
+}
+
+#[wasm_bindgen(js_class = "HaloDecalInstance")]
+impl ScenarioDecal {
+    // yaw and pitch are stored as signed fractions of a half turn
+    pub fn get_model_matrix(&self) -> Vec<f32> {
+        let rotation = Euler3D {
+            yaw: self.yaw as f32 / 128.0 * std::f32::consts::PI,
+            pitch: self.pitch as f32 / 128.0 * std::f32::consts::PI,
+            roll: 0.0,
+        };
+        compute_model_matrix(&self.position, &rotation).as_slice().to_vec()
+    }
 }
 
+#[tag_layout(size = 0x58)]
+#[wasm_bindgen(js_name = "HaloLightFixtureInstance")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ScenarioLightFixture {
-    pub light_type: u16,
-    pub name: u16,
-    pub not_placed: u16,
-    pub desired_permutation: u16,
-    pub position: Point3D,
-    pub rotation: Euler3D,
-    pub bsp_indices: u16,
-    pub power_group: u16,
-    pub position_group: u16,
-    pub device_flags: u32,
-    pub color: ColorRGB,
-    pub intensity: f32,
-    pub falloff_angle: f32,
-    pub cutoff_angle: f32,
+    #[offset(0x00)] pub light_type: u16,
+    #[offset(0x02)] pub name: u16,
+    #[offset(0x04)] pub not_placed: u16,
+    #[offset(0x06)] pub desired_permutation: u16,
+    #[offset(0x08)] pub position: Point3D,
+    #[offset(0x14)] pub rotation: Euler3D,
+    #[offset(0x28)] pub power_group: u16,
+    #[offset(0x2A)] pub position_group: u16,
+    #[offset(0x2C)] pub device_flags: u32,
+    #[offset(0x30)] pub color: ColorRGB,
+    #[offset(0x3C)] pub intensity: f32,
+    #[offset(0x40)] pub falloff_angle: f32,
+    #[offset(0x44)] pub cutoff_angle: f32,
+}
+
+#[wasm_bindgen(js_class = "HaloLightFixtureInstance")]
+impl ScenarioLightFixture {
+    pub fn get_model_matrix(&self) -> Vec<f32> {
+        compute_model_matrix(&self.position, &self.rotation).as_slice().to_vec()
+    }
 }
@@ -0,0 +1,440 @@
+use std::io::{Cursor, Seek, SeekFrom};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt};
+use deku::prelude::*;
+use noclip_macros::tag_layout;
+use wasm_bindgen::prelude::*;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::util::trim_tag_string;
+
+const NODE_TABLE_HEADER_SIZE: usize = 0x38;
+const NODE_SIZE: usize = 0x14;
+const NULL_NODE: u32 = 0xFFFFFFFF;
+// guards against absurdly deep syntax trees in broken maps. cycles are caught
+// separately, by expanding each node at most once per expression
+const MAX_DEPTH: usize = 256;
+
+const NODE_FLAG_PRIMITIVE: u16 = 0x1;
+const NODE_FLAG_GLOBAL: u16 = 0x4;
+
+#[wasm_bindgen(js_name = "HaloScriptType")]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum ScriptType {
+    Startup = 0,
+    Dormant = 1,
+    Continuous = 2,
+    Static = 3,
+    Stub = 4,
+}
+
+impl_tag_layout!(ScriptType => 2);
+
+impl ScriptType {
+    fn keyword(&self) -> &'static str {
+        match self {
+            ScriptType::Startup => "startup",
+            ScriptType::Dormant => "dormant",
+            ScriptType::Continuous => "continuous",
+            ScriptType::Static => "static",
+            ScriptType::Stub => "stub",
+        }
+    }
+}
+
+// indexed by the value type of script nodes, globals and static script return types
+const VALUE_TYPE_NAMES: &[&str] = &[
+    "unparsed", "special_form", "function_name", "passthrough", "void", "boolean",
+    "real", "short", "long", "string", "script", "trigger_volume", "cutscene_flag",
+    "cutscene_camera_point", "cutscene_title", "cutscene_recording", "device_group",
+    "ai", "ai_command_list", "starting_profile", "conversation", "navpoint",
+    "hud_message", "object_list", "sound", "effect", "damage", "looping_sound",
+    "animation_graph", "actor_variant", "damage_effect", "object_definition",
+    "game_difficulty", "team", "ai_default_state", "actor_type", "hud_corner",
+    "object", "unit", "vehicle", "weapon", "device", "scenery", "object_name",
+    "unit_name", "vehicle_name", "weapon_name", "device_name", "scenery_name",
+];
+
+const VALUE_TYPE_BOOLEAN: u16 = 5;
+const VALUE_TYPE_REAL: u16 = 6;
+const VALUE_TYPE_SHORT: u16 = 7;
+const VALUE_TYPE_LONG: u16 = 8;
+const VALUE_TYPE_STRING: u16 = 9;
+const VALUE_TYPE_SCRIPT: u16 = 10;
+
+pub fn value_type_name(value_type: u16) -> String {
+    match VALUE_TYPE_NAMES.get(value_type as usize) {
+        Some(name) => name.to_string(),
+        None => format!("<type {}>", value_type),
+    }
+}
+
+// value types that name something placed in the scenario or a tag. the enum
+// like ones from game_difficulty to hud_corner are left out
+fn is_reference_type(value_type: u16) -> bool {
+    value_type >= VALUE_TYPE_SCRIPT && !(32..=36).contains(&value_type)
+}
+
+#[tag_layout(size = 0x5C)]
+#[wasm_bindgen(js_name = "HaloScenarioScript")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioScript {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub script_type: ScriptType,
+    #[offset(0x22)] pub return_type: u16,
+    #[offset(0x24)] pub root_expression: u32,
+}
+
+#[wasm_bindgen(js_class = "HaloScenarioScript")]
+impl ScenarioScript {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+#[tag_layout(size = 0x5C)]
+#[wasm_bindgen(js_name = "HaloScenarioGlobal")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ScenarioGlobal {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub value_type: u16,
+    #[offset(0x28)] pub initialization_expression: u32,
+}
+
+#[wasm_bindgen(js_class = "HaloScenarioGlobal")]
+impl ScenarioGlobal {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+}
+
+// one node of the compiled syntax tree. function calls (including special
+// forms like begin and if) point at their first child, which names the
+// function, with the arguments following as its siblings
+#[derive(Debug, Clone)]
+pub struct ScriptNode {
+    pub function_index: u16,
+    pub value_type: u16,
+    pub flags: u16,
+    pub next_node: u32,
+    pub string_offset: u32,
+    pub data: u32,
+}
+
+impl ScriptNode {
+    fn is_primitive(&self) -> bool {
+        self.flags & NODE_FLAG_PRIMITIVE != 0
+    }
+
+    fn is_global(&self) -> bool {
+        self.flags & NODE_FLAG_GLOBAL != 0
+    }
+}
+
+// something a script refers to by name, e.g. a cutscene flag or an object
+#[wasm_bindgen(js_name = "HaloScriptReference")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptReference {
+    pub value_type: u16,
+    pub(crate) name: String,
+    pub(crate) script: String,
+}
+
+#[wasm_bindgen(js_class = "HaloScriptReference")]
+impl ScriptReference {
+    pub fn get_type_name(&self) -> String {
+        value_type_name(self.value_type)
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    // the script or global the reference appears in
+    pub fn get_script(&self) -> String {
+        self.script.clone()
+    }
+}
+
+// the scenario's script syntax data (a table of ScriptNodes) plus the string
+// data its names and literals point into
+#[derive(Debug, Clone, Default)]
+pub struct ScriptSyntax {
+    pub nodes: Vec<ScriptNode>,
+    pub strings: Vec<u8>,
+}
+
+impl ScriptSyntax {
+    pub fn new(syntax_data: &[u8], string_data: &[u8]) -> Result<Self> {
+        if syntax_data.is_empty() {
+            return Ok(ScriptSyntax { nodes: Vec::new(), strings: string_data.to_vec() });
+        }
+        if syntax_data.len() < NODE_TABLE_HEADER_SIZE {
+            return Err(MapReaderError::InvalidTag(format!("script syntax data too short ({} bytes)", syntax_data.len())).into());
+        }
+        let mut reader = Cursor::new(syntax_data);
+        reader.seek(SeekFrom::Start(0x22))?;
+        let element_size = reader.read_u16::<LittleEndian>()? as usize;
+        if element_size < NODE_SIZE {
+            return Err(MapReaderError::InvalidTag(format!("invalid script node size {}", element_size)).into());
+        }
+        let node_count = (syntax_data.len() - NODE_TABLE_HEADER_SIZE) / element_size;
+        let mut nodes = Vec::with_capacity(node_count);
+        for i in 0..node_count {
+            reader.seek(SeekFrom::Start((NODE_TABLE_HEADER_SIZE + i * element_size) as u64 + 2))?;
+            nodes.push(ScriptNode {
+                function_index: reader.read_u16::<LittleEndian>()?,
+                value_type: reader.read_u16::<LittleEndian>()?,
+                flags: reader.read_u16::<LittleEndian>()?,
+                next_node: reader.read_u32::<LittleEndian>()?,
+                string_offset: reader.read_u32::<LittleEndian>()?,
+                data: reader.read_u32::<LittleEndian>()?,
+            });
+        }
+        Ok(ScriptSyntax { nodes, strings: string_data.to_vec() })
+    }
+
+    // node references are datum handles, with the index in the low 16 bits
+    fn node(&self, handle: u32) -> Option<&ScriptNode> {
+        if handle == NULL_NODE {
+            return None;
+        }
+        self.nodes.get((handle & 0xFFFF) as usize)
+    }
+
+    fn string_at(&self, offset: u32) -> String {
+        match self.strings.get(offset as usize..) {
+            Some(bytes) => trim_tag_string(bytes),
+            None => String::new(),
+        }
+    }
+
+    // the arguments of a function call node, i.e. the siblings after the function name
+    fn call_children(&self, node: &ScriptNode) -> (String, Vec<u32>) {
+        let Some(name_node) = self.node(node.data) else {
+            return (String::new(), Vec::new());
+        };
+        let mut children = Vec::new();
+        let mut handle = name_node.next_node;
+        while let Some(child) = self.node(handle) {
+            if children.len() > self.nodes.len() {
+                break;
+            }
+            children.push(handle);
+            handle = child.next_node;
+        }
+        (self.string_at(name_node.string_offset), children)
+    }
+
+    fn primitive_to_string(&self, node: &ScriptNode) -> String {
+        if node.is_global() {
+            return self.string_at(node.string_offset);
+        }
+        match node.value_type {
+            VALUE_TYPE_BOOLEAN => if node.data & 0xFF != 0 { "true" } else { "false" }.to_string(),
+            VALUE_TYPE_REAL => format!("{:?}", f32::from_bits(node.data)),
+            VALUE_TYPE_SHORT => (node.data as u16 as i16).to_string(),
+            VALUE_TYPE_LONG => (node.data as i32).to_string(),
+            VALUE_TYPE_STRING => format!("{:?}", self.string_at(node.string_offset)),
+            _ => self.string_at(node.string_offset),
+        }
+    }
+
+    // marks a node as expanded, returning false if it already was. a well
+    // formed tree never reaches a node twice, so this only trips on cycles
+    fn visit(&self, handle: u32, visited: &mut Vec<bool>) -> bool {
+        if visited.is_empty() {
+            visited.resize(self.nodes.len(), false);
+        }
+        let index = (handle & 0xFFFF) as usize;
+        !std::mem::replace(&mut visited[index], true)
+    }
+
+    pub fn expression_to_string(&self, handle: u32) -> String {
+        self.write_expression(handle, 0, &mut Vec::new())
+    }
+
+    fn write_expression(&self, handle: u32, depth: usize, visited: &mut Vec<bool>) -> String {
+        let Some(node) = self.node(handle) else {
+            return "<invalid node>".to_string();
+        };
+        if depth > MAX_DEPTH || !self.visit(handle, visited) {
+            return "...".to_string();
+        }
+        if node.is_primitive() {
+            return self.primitive_to_string(node);
+        }
+        let (function, children) = self.call_children(node);
+        let mut result = format!("({}", function);
+        for child in children {
+            result.push(' ');
+            result.push_str(&self.write_expression(child, depth + 1, visited));
+        }
+        result.push(')');
+        result
+    }
+
+    // scripts are usually a begin block, whose expressions get a line each
+    fn script_body(&self, script: &ScenarioScript) -> Vec<String> {
+        if let Some(node) = self.node(script.root_expression) {
+            if !node.is_primitive() {
+                let (function, children) = self.call_children(node);
+                if function == "begin" {
+                    let mut visited = Vec::new();
+                    self.visit(script.root_expression, &mut visited);
+                    return children.iter().map(|&child| self.write_expression(child, 1, &mut visited)).collect();
+                }
+            }
+        }
+        vec![self.expression_to_string(script.root_expression)]
+    }
+
+    pub fn decompile(&self, scripts: &[ScenarioScript], globals: &[ScenarioGlobal]) -> String {
+        let mut result = String::new();
+        for global in globals {
+            result.push_str(&format!("(global {} {} {})\n", value_type_name(global.value_type), global.get_name(),
+                self.expression_to_string(global.initialization_expression)));
+        }
+        if !globals.is_empty() && !scripts.is_empty() {
+            result.push('\n');
+        }
+        for script in scripts {
+            result.push_str(&format!("(script {} ", script.script_type.keyword()));
+            if script.script_type == ScriptType::Static {
+                result.push_str(&format!("{} ", value_type_name(script.return_type)));
+            }
+            result.push_str(&script.get_name());
+            for line in self.script_body(script) {
+                result.push_str("\n    ");
+                result.push_str(&line);
+            }
+            result.push_str(")\n\n");
+        }
+        result
+    }
+
+    pub fn get_references(&self, scripts: &[ScenarioScript], globals: &[ScenarioGlobal]) -> Vec<ScriptReference> {
+        let mut result = Vec::new();
+        let roots = globals.iter().map(|global| (global.get_name(), global.initialization_expression))
+            .chain(scripts.iter().map(|script| (script.get_name(), script.root_expression)));
+        for (script, root) in roots {
+            self.collect_references(root, &script, 0, &mut Vec::new(), &mut result);
+        }
+        result
+    }
+
+    fn collect_references(&self, handle: u32, script: &str, depth: usize, visited: &mut Vec<bool>, result: &mut Vec<ScriptReference>) {
+        let Some(node) = self.node(handle) else {
+            return;
+        };
+        if depth > MAX_DEPTH || !self.visit(handle, visited) {
+            return;
+        }
+        if node.is_primitive() {
+            if !node.is_global() && is_reference_type(node.value_type) {
+                let reference = ScriptReference {
+                    value_type: node.value_type,
+                    name: self.string_at(node.string_offset),
+                    script: script.to_string(),
+                };
+                if !reference.name.is_empty() && !result.contains(&reference) {
+                    result.push(reference);
+                }
+            }
+            return;
+        }
+        for child in self.call_children(node).1 {
+            self.collect_references(child, script, depth + 1, visited, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo::layout::read_test_tag;
+
+    // (begin (sleep 30) (object_create "door"))
+    fn test_syntax() -> ScriptSyntax {
+        let strings = b"begin\0sleep\0object_create\0door\0".to_vec();
+        // (function_index, value_type, flags, next_node, string_offset, data)
+        let nodes: [(u16, u16, u16, u32, u32, u32); 8] = [
+            (0, 4, 0, NULL_NODE, 0, 1),           // 0: call begin
+            (0, 2, 1, 2, 0, 0),                   // 1: "begin"
+            (1, 4, 0, 4, 0, 3),                   // 2: call sleep
+            (1, 2, 1, 5, 6, 0),                   // 3: "sleep"
+            (2, 4, 0, NULL_NODE, 0, 6),           // 4: call object_create
+            (0, 7, 1, NULL_NODE, 0, 30),          // 5: 30, following sleep's name
+            (2, 2, 1, 7, 12, 0),                  // 6: "object_create"
+            (0, 43, 1, NULL_NODE, 26, 0),         // 7: door
+        ];
+        let mut data = vec![0u8; NODE_TABLE_HEADER_SIZE];
+        data[0x22..0x24].copy_from_slice(&(NODE_SIZE as u16).to_le_bytes());
+        for (i, (function_index, value_type, flags, next_node, string_offset, node_data)) in nodes.iter().enumerate() {
+            data.extend_from_slice(&(0xE741u16 + i as u16).to_le_bytes());
+            data.extend_from_slice(&function_index.to_le_bytes());
+            data.extend_from_slice(&value_type.to_le_bytes());
+            data.extend_from_slice(&flags.to_le_bytes());
+            data.extend_from_slice(&next_node.to_le_bytes());
+            data.extend_from_slice(&string_offset.to_le_bytes());
+            data.extend_from_slice(&node_data.to_le_bytes());
+        }
+        ScriptSyntax::new(&data, &strings).unwrap()
+    }
+
+    fn test_script(script_type: ScriptType) -> ScenarioScript {
+        read_test_tag(&[
+            (0x00, b"intro"),
+            (0x20, &(script_type as u16).to_le_bytes()),
+            (0x22, &4u16.to_le_bytes()),
+        ])
+    }
+
+    #[test]
+    fn test_decompile() {
+        let syntax = test_syntax();
+        assert_eq!(syntax.nodes.len(), 8);
+        assert_eq!(syntax.expression_to_string(0), "(begin (sleep 30) (object_create door))");
+
+        let script = test_script(ScriptType::Dormant);
+        assert_eq!(syntax.decompile(&[script], &[]), "(script dormant intro\n    (sleep 30)\n    (object_create door))\n\n");
+        let script = test_script(ScriptType::Static);
+        assert!(syntax.decompile(&[script], &[]).starts_with("(script static void intro\n"));
+
+        let references = syntax.get_references(&[test_script(ScriptType::Startup)], &[]);
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].get_type_name(), "object_name");
+        assert_eq!((references[0].get_name(), references[0].get_script()), ("door".to_string(), "intro".to_string()));
+    }
+
+    #[test]
+    fn test_broken_syntax() {
+        let mut syntax = test_syntax();
+        // begin's first argument is the begin call itself
+        syntax.nodes[1].next_node = 0;
+        assert!(syntax.expression_to_string(0).len() < 10_000);
+        assert_eq!(syntax.expression_to_string(100), "<invalid node>");
+        assert!(ScriptSyntax::new(&[0; 4], &[]).is_err());
+    }
+
+    #[test]
+    fn test_sibling_cycle() {
+        let mut syntax = test_syntax();
+        // sleep's call and object_create's call follow each other forever
+        syntax.nodes[4].next_node = 2;
+        let expression = syntax.expression_to_string(0);
+        assert!(expression.starts_with("(begin (sleep 30) (object_create door) ..."));
+        assert!(expression.len() < 10_000);
+        let script = test_script(ScriptType::Dormant);
+        assert!(syntax.decompile(std::slice::from_ref(&script), &[]).len() < 10_000);
+        assert_eq!(syntax.get_references(&[script], &[]).len(), 1);
+    }
+}
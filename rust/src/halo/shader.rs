@@ -1,7 +1,23 @@
 use deku::prelude::*;
 use wasm_bindgen::prelude::*;
+use noclip_macros::tag_layout;
 use super::tag::*;
 use super::common::*;
+use super::layout::impl_tag_layout;
+
+impl_tag_layout!(
+    RadiosityDetailLevel => 2,
+    ShaderTransparentGenericMapType => 2,
+    ShaderTransparentChicagoColorFunction => 2,
+    ShaderTransparentGlassReflectionType => 2,
+    FramebufferBlendFunction => 2,
+    FramebufferFadeMode => 2,
+    FunctionSource => 2,
+    AnimationFunction => 2,
+    ShaderEnvironmentType => 2,
+    ShaderEnvironmentReflectionType => 2,
+    DetailBitmapFunction => 2,
+);
 
 #[wasm_bindgen(js_name = "HaloShaderTransparencyChicago")]
 #[derive(Debug, Clone, DekuRead)]
@@ -53,38 +69,165 @@ pub enum ShaderTransparentChicagoColorFunction {
     BlendNextMapAlphaInverse = 12,
 }
 
+#[tag_layout(size = 0xDC)]
 #[wasm_bindgen(js_name = "HaloShaderTransparentChicagoBitmap")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ShaderTransparentChicagoBitmap {
-    pub flags: u16,
-    #[deku(pad_bytes_before = "42")]
-    pub color_function: ShaderTransparentChicagoColorFunction,
-    pub alpha_function: ShaderTransparentChicagoColorFunction,
-    #[deku(pad_bytes_before = "36")]
-    pub map_u_scale: f32,
-    pub map_v_scale: f32,
-    pub map_u_offset: f32,
-    pub map_v_offset: f32,
-    pub map_rotation: f32,
-    pub mipmap_bias: f32,
-    pub bitmap: TagDependency,
-    #[deku(pad_bytes_before = "40")]
-    pub u_animation_source: FunctionSource,
-    pub u_animation_function: AnimationFunction,
-    pub u_animation_period: f32,
-    pub u_animation_phase: f32,
-    pub u_animation_scale: f32,
-    pub v_animation_source: FunctionSource,
-    pub v_animation_function: AnimationFunction,
-    pub v_animation_period: f32,
-    pub v_animation_phase: f32,
-    pub v_animation_scale: f32,
-    pub rotation_animation_source: FunctionSource,
-    pub rotation_animation_function: AnimationFunction,
-    pub rotation_animation_period: f32,
-    pub rotation_animation_phase: f32,
-    pub rotation_animation_scale: f32,
-    pub rotation_animation_center: Point2D,
+    #[offset(0x00)] pub flags: u16,
+    #[offset(0x2C)] pub color_function: ShaderTransparentChicagoColorFunction,
+    #[offset(0x2E)] pub alpha_function: ShaderTransparentChicagoColorFunction,
+    #[offset(0x54)] pub map_u_scale: f32,
+    #[offset(0x58)] pub map_v_scale: f32,
+    #[offset(0x5C)] pub map_u_offset: f32,
+    #[offset(0x60)] pub map_v_offset: f32,
+    #[offset(0x64)] pub map_rotation: f32,
+    #[offset(0x68)] pub mipmap_bias: f32,
+    #[offset(0x6C)] pub bitmap: TagDependency,
+    #[offset(0xA4)] pub u_animation_source: FunctionSource,
+    #[offset(0xA6)] pub u_animation_function: AnimationFunction,
+    #[offset(0xA8)] pub u_animation_period: f32,
+    #[offset(0xAC)] pub u_animation_phase: f32,
+    #[offset(0xB0)] pub u_animation_scale: f32,
+    #[offset(0xB4)] pub v_animation_source: FunctionSource,
+    #[offset(0xB6)] pub v_animation_function: AnimationFunction,
+    #[offset(0xB8)] pub v_animation_period: f32,
+    #[offset(0xBC)] pub v_animation_phase: f32,
+    #[offset(0xC0)] pub v_animation_scale: f32,
+    #[offset(0xC4)] pub rotation_animation_source: FunctionSource,
+    #[offset(0xC6)] pub rotation_animation_function: AnimationFunction,
+    #[offset(0xC8)] pub rotation_animation_period: f32,
+    #[offset(0xCC)] pub rotation_animation_phase: f32,
+    #[offset(0xD0)] pub rotation_animation_scale: f32,
+    #[offset(0xD4)] pub rotation_animation_center: Point2D,
+}
+
+// chicago with separate map sets for 4 and 2 texture stage hardware
+#[tag_layout(size = 0x78)]
+#[wasm_bindgen(js_name = "HaloShaderTransparencyChicagoExtended")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ShaderTransparentChicagoExtended {
+    #[offset(0x00)] pub radiosity_flags: u16,
+    #[offset(0x02)] pub radiosity_detail_level: RadiosityDetailLevel,
+    #[offset(0x04)] pub radiosity_light_power: f32,
+    #[offset(0x08)] pub radiosity_light_color: ColorRGB,
+    #[offset(0x14)] pub radiosity_tint_color: ColorRGB,
+    #[offset(0x28)] pub numeric_counter_limit: u8,
+    #[offset(0x29)] pub flags: u8,
+    #[offset(0x2A)] pub first_map_type: ShaderTransparentGenericMapType,
+    #[offset(0x2C)] pub framebuffer_blend_function: FramebufferBlendFunction,
+    #[offset(0x2E)] pub framebuffer_fade_mode: FramebufferFadeMode,
+    #[offset(0x30)] pub framebuffer_fade_source: FunctionSource,
+    #[offset(0x34)] pub lens_flare_spacing: f32,
+    #[offset(0x38)] pub lens_flare: TagDependency,
+    #[offset(0x48)] pub(crate) extra_layers: Block<TagDependency>,
+    #[offset(0x54)] pub(crate) four_stage_bitmaps: Block<ShaderTransparentChicagoBitmap>, // max of 4
+    #[offset(0x60)] pub(crate) two_stage_bitmaps: Block<ShaderTransparentChicagoBitmap>, // max of 2
+    #[offset(0x6C)] pub extra_flags: u32,
+}
+
+#[wasm_bindgen(js_class = "HaloShaderTransparencyChicagoExtended")]
+impl ShaderTransparentChicagoExtended {
+    // we can always use the 4 stage maps, falling back to the 2 stage ones
+    // for shaders that only define those
+    pub fn get_bitmaps(&self) -> Vec<ShaderTransparentChicagoBitmap> {
+        let bitmaps = if self.four_stage_bitmaps.count > 0 { &self.four_stage_bitmaps } else { &self.two_stage_bitmaps };
+        bitmaps.items.as_ref().cloned().unwrap_or_default()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum ShaderTransparentGlassReflectionType {
+    BumpedCubeMap = 0,
+    FlatCubeMap = 1,
+    DynamicMirror = 2,
+}
+
+#[tag_layout(size = 0x1E0)]
+#[wasm_bindgen(js_name = "HaloShaderTransparencyGlass")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ShaderTransparentGlass {
+    #[offset(0x000)] pub radiosity_flags: u16,
+    #[offset(0x002)] pub radiosity_detail_level: RadiosityDetailLevel,
+    #[offset(0x004)] pub radiosity_light_power: f32,
+    #[offset(0x008)] pub radiosity_light_color: ColorRGB,
+    #[offset(0x014)] pub radiosity_tint_color: ColorRGB,
+    #[offset(0x028)] pub flags: u16,
+    #[offset(0x054)] pub background_tint_color: ColorRGB,
+    #[offset(0x060)] pub background_tint_map_scale: f32,
+    #[offset(0x064)] pub background_tint_map: TagDependency,
+    #[offset(0x08A)] pub reflection_type: ShaderTransparentGlassReflectionType,
+    #[offset(0x08C)] pub perpendicular_brightness: f32,
+    #[offset(0x090)] pub perpendicular_tint_color: ColorRGB,
+    #[offset(0x09C)] pub parallel_brightness: f32,
+    #[offset(0x0A0)] pub parallel_tint_color: ColorRGB,
+    #[offset(0x0AC)] pub reflection_map: TagDependency,
+    #[offset(0x0BC)] pub bump_map_scale: f32,
+    #[offset(0x0C0)] pub bump_map: TagDependency,
+    #[offset(0x154)] pub diffuse_map_scale: f32,
+    #[offset(0x158)] pub diffuse_map: TagDependency,
+    #[offset(0x168)] pub diffuse_detail_map_scale: f32,
+    #[offset(0x16C)] pub diffuse_detail_map: TagDependency,
+    #[offset(0x19C)] pub specular_map_scale: f32,
+    #[offset(0x1A0)] pub specular_map: TagDependency,
+    #[offset(0x1B0)] pub specular_detail_map_scale: f32,
+    #[offset(0x1B4)] pub specular_detail_map: TagDependency,
+}
+
+#[tag_layout(size = 0x14C)]
+#[wasm_bindgen(js_name = "HaloShaderTransparencyPlasma")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ShaderTransparentPlasma {
+    #[offset(0x000)] pub radiosity_flags: u16,
+    #[offset(0x002)] pub radiosity_detail_level: RadiosityDetailLevel,
+    #[offset(0x004)] pub radiosity_light_power: f32,
+    #[offset(0x008)] pub radiosity_light_color: ColorRGB,
+    #[offset(0x014)] pub radiosity_tint_color: ColorRGB,
+    #[offset(0x02C)] pub intensity_source: FunctionSource,
+    #[offset(0x030)] pub intensity_exponent: f32,
+    #[offset(0x034)] pub offset_source: FunctionSource,
+    #[offset(0x038)] pub offset_amount: f32,
+    #[offset(0x03C)] pub offset_exponent: f32,
+    #[offset(0x060)] pub perpendicular_brightness: f32,
+    #[offset(0x064)] pub perpendicular_tint_color: ColorRGB,
+    #[offset(0x070)] pub parallel_brightness: f32,
+    #[offset(0x074)] pub parallel_tint_color: ColorRGB,
+    #[offset(0x080)] pub tint_color_source: FunctionSource,
+    #[offset(0x0A8)] pub primary_animation_period: f32,
+    #[offset(0x0AC)] pub primary_animation_direction: Vector3D,
+    #[offset(0x0B8)] pub primary_noise_map_scale: f32,
+    #[offset(0x0BC)] pub primary_noise_map: TagDependency,
+    #[offset(0x0F0)] pub secondary_animation_period: f32,
+    #[offset(0x0F4)] pub secondary_animation_direction: Vector3D,
+    #[offset(0x100)] pub secondary_noise_map_scale: f32,
+    #[offset(0x104)] pub secondary_noise_map: TagDependency,
+}
+
+#[tag_layout(size = 0x104)]
+#[wasm_bindgen(js_name = "HaloShaderTransparencyMeter")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct ShaderTransparentMeter {
+    #[offset(0x00)] pub radiosity_flags: u16,
+    #[offset(0x02)] pub radiosity_detail_level: RadiosityDetailLevel,
+    #[offset(0x04)] pub radiosity_light_power: f32,
+    #[offset(0x08)] pub radiosity_light_color: ColorRGB,
+    #[offset(0x14)] pub radiosity_tint_color: ColorRGB,
+    #[offset(0x28)] pub flags: u16,
+    #[offset(0x4C)] pub map: TagDependency,
+    #[offset(0x7C)] pub gradient_min_color: ColorRGB,
+    #[offset(0x88)] pub gradient_max_color: ColorRGB,
+    #[offset(0x94)] pub background_color: ColorRGB,
+    #[offset(0xA0)] pub flash_color: ColorRGB,
+    #[offset(0xAC)] pub tint_color: ColorRGB,
+    #[offset(0xB8)] pub meter_transparency: f32,
+    #[offset(0xBC)] pub background_transparency: f32,
+    #[offset(0xD8)] pub meter_brightness_source: FunctionSource,
+    #[offset(0xDA)] pub flash_brightness_source: FunctionSource,
+    #[offset(0xDC)] pub value_source: FunctionSource,
+    #[offset(0xDE)] pub gradient_source: FunctionSource,
+    #[offset(0xE0)] pub flash_extension_source: FunctionSource,
 }
 
 #[wasm_bindgen(js_name = "HaloShaderTransparencyGeneric")]
@@ -458,55 +601,42 @@ pub enum FunctionSource {
     D = 4,
 }
 
+#[tag_layout(size = 0x344)]
 #[wasm_bindgen(js_name = "HaloShaderEnvironment")]
 #[derive(Debug, Clone, DekuRead)]
 pub struct ShaderEnvironment {
-    pub radiosity_flags: u16,
-    pub radiosity_detail_level: RadiosityDetailLevel,
-    pub radiosity_light_power: f32,
-    pub radiosity_light_color: ColorRGB,
-    pub radiosity_tint_color: ColorRGB,
-    #[deku(pad_bytes_before = "8")]
-    pub flags: u16,
-    pub shader_environment_type: ShaderEnvironmentType,
-    pub lens_flare_spacing: f32,
-    pub lens_flare: TagDependency,
-    #[deku(pad_bytes_before = "44")]
-    pub diffuse_flags: u16,
-    #[deku(pad_bytes_before = "26")]
-    pub base_bitmap: TagDependency,
-    #[deku(pad_bytes_before = "24")]
-    pub detail_bitmap_function: DetailBitmapFunction,
-    #[deku(pad_bytes_before = "2")]
-    pub primary_detail_bitmap_scale: f32,
-    pub primary_detail_bitmap: TagDependency,
-    pub secondary_detail_bitmap_scale: f32,
-    pub secondary_detail_bitmap: TagDependency,
-    #[deku(pad_bytes_before = "24")]
-    pub micro_detail_bitmap_function: DetailBitmapFunction,
-    #[deku(pad_bytes_before = "2")]
-    pub micro_detail_bitmap_scale: f32,
-    pub micro_detail_bitmap: TagDependency,
-    pub material_color: ColorRGB,
-    #[deku(pad_bytes_before = "12")]
-    pub bump_map_scale: f32,
-    pub bump_map: TagDependency,
-    #[deku(pad_bytes_before = "324")]
-    pub specular_flags: u16,
-    #[deku(pad_bytes_before = "18")]
-    pub brightness: f32,
-    #[deku(pad_bytes_before = "20")]
-    pub perpendicular_color: ColorRGB,
-    pub parallel_color: ColorRGB,
-    #[deku(pad_bytes_before = "16")]
-    pub reflection_flags: u16,
-    pub reflection_type: ShaderEnvironmentReflectionType,
-    pub lightmap_brightness_scale: f32,
-    #[deku(pad_bytes_before = "28")]
-    pub perpendicular_brightness: f32,
-    pub parallel_brightness: f32,
-    #[deku(pad_bytes_before = "40")]
-    pub reflection_cube_map: TagDependency,
+    #[offset(0x000)] pub radiosity_flags: u16,
+    #[offset(0x002)] pub radiosity_detail_level: RadiosityDetailLevel,
+    #[offset(0x004)] pub radiosity_light_power: f32,
+    #[offset(0x008)] pub radiosity_light_color: ColorRGB,
+    #[offset(0x014)] pub radiosity_tint_color: ColorRGB,
+    #[offset(0x028)] pub flags: u16,
+    #[offset(0x02A)] pub shader_environment_type: ShaderEnvironmentType,
+    #[offset(0x02C)] pub lens_flare_spacing: f32,
+    #[offset(0x030)] pub lens_flare: TagDependency,
+    #[offset(0x06C)] pub diffuse_flags: u16,
+    #[offset(0x088)] pub base_bitmap: TagDependency,
+    #[offset(0x0B0)] pub detail_bitmap_function: DetailBitmapFunction,
+    #[offset(0x0B4)] pub primary_detail_bitmap_scale: f32,
+    #[offset(0x0B8)] pub primary_detail_bitmap: TagDependency,
+    #[offset(0x0C8)] pub secondary_detail_bitmap_scale: f32,
+    #[offset(0x0CC)] pub secondary_detail_bitmap: TagDependency,
+    #[offset(0x0F4)] pub micro_detail_bitmap_function: DetailBitmapFunction,
+    #[offset(0x0F8)] pub micro_detail_bitmap_scale: f32,
+    #[offset(0x0FC)] pub micro_detail_bitmap: TagDependency,
+    #[offset(0x10C)] pub material_color: ColorRGB,
+    #[offset(0x124)] pub bump_map_scale: f32,
+    #[offset(0x128)] pub bump_map: TagDependency,
+    #[offset(0x27C)] pub specular_flags: u16,
+    #[offset(0x290)] pub brightness: f32,
+    #[offset(0x2A8)] pub perpendicular_color: ColorRGB,
+    #[offset(0x2B4)] pub parallel_color: ColorRGB,
+    #[offset(0x2D0)] pub reflection_flags: u16,
+    #[offset(0x2D2)] pub reflection_type: ShaderEnvironmentReflectionType,
+    #[offset(0x2D4)] pub lightmap_brightness_scale: f32,
+    #[offset(0x2F4)] pub perpendicular_brightness: f32,
+    #[offset(0x2F8)] pub parallel_brightness: f32,
+    #[offset(0x324)] pub reflection_cube_map: TagDependency,
 }
 
 #[wasm_bindgen]
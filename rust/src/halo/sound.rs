@@ -0,0 +1,329 @@
+use deku::prelude::*;
+use wasm_bindgen::prelude::*;
+
+use noclip_macros::tag_layout;
+
+use crate::halo::common::*;
+use crate::halo::layout::impl_tag_layout;
+use crate::halo::tag::*;
+use crate::halo::util::trim_tag_string;
+
+// samples per 36 byte xbox adpcm block, per channel: the header sample plus 64 nibbles
+const XBOX_ADPCM_BLOCK_SIZE: usize = 36;
+const XBOX_ADPCM_BLOCK_SAMPLES: usize = 65;
+
+const IMA_INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+const IMA_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45,
+    50, 55, 60, 66, 73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230,
+    253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327,
+    3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442,
+    11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum SoundSampleRate {
+    Rate22kHz = 0,
+    Rate44kHz = 1,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum SoundEncoding {
+    Mono = 0,
+    Stereo = 1,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, DekuRead)]
+#[deku(id_type = "u16")]
+#[repr(u16)]
+pub enum SoundCompression {
+    None = 0,
+    XboxAdpcm = 1,
+    ImaAdpcm = 2,
+    Ogg = 3,
+}
+
+impl_tag_layout!(SoundSampleRate => 2, SoundEncoding => 2, SoundCompression => 2);
+
+#[tag_layout(size = 0xA4)]
+#[wasm_bindgen(js_name = "HaloSound")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct Sound {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub class: u16,
+    #[offset(0x06)] pub sample_rate: SoundSampleRate,
+    #[offset(0x08)] pub minimum_distance: f32,
+    #[offset(0x0C)] pub maximum_distance: f32,
+    #[offset(0x10)] pub skip_fraction: f32,
+    #[offset(0x14)] pub random_pitch_bounds: Bounds,
+    #[offset(0x1C)] pub inner_cone_angle: f32,
+    #[offset(0x20)] pub outer_cone_angle: f32,
+    #[offset(0x24)] pub outer_cone_gain: f32,
+    #[offset(0x28)] pub gain_modifier: f32,
+    #[offset(0x2C)] pub maximum_bend_per_second: f32,
+    #[offset(0x3C)] pub skip_fraction_modifier: f32,
+    #[offset(0x40)] pub gain_modifier_scale: f32,
+    #[offset(0x44)] pub pitch_modifier: f32,
+    #[offset(0x54)] pub encoding: SoundEncoding,
+    #[offset(0x56)] pub compression: SoundCompression,
+    #[offset(0x58)] pub promotion_sound: TagDependency,
+    #[offset(0x68)] pub promotion_count: u16,
+    #[offset(0x98)] pub(crate) pitch_ranges: Block<SoundPitchRange>,
+}
+
+#[wasm_bindgen(js_class = "HaloSound")]
+impl Sound {
+    pub fn get_sample_rate(&self) -> u32 {
+        match self.sample_rate {
+            SoundSampleRate::Rate22kHz => 22050,
+            SoundSampleRate::Rate44kHz => 44100,
+        }
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        match self.encoding {
+            SoundEncoding::Mono => 1,
+            SoundEncoding::Stereo => 2,
+        }
+    }
+
+    pub fn get_num_pitch_ranges(&self) -> usize {
+        self.pitch_ranges.items.as_ref().map_or(0, |ranges| ranges.len())
+    }
+
+    pub fn get_pitch_range_name(&self, pitch_range: usize) -> Option<String> {
+        Some(trim_tag_string(&self.pitch_ranges.items.as_ref()?.get(pitch_range)?.name))
+    }
+
+    pub fn get_natural_pitch(&self, pitch_range: usize) -> Option<f32> {
+        Some(self.pitch_ranges.items.as_ref()?.get(pitch_range)?.natural_pitch)
+    }
+
+    // only the first actual_permutation_count permutations can be picked to
+    // play, the rest are chained on via next_permutation_index
+    pub fn get_num_actual_permutations(&self, pitch_range: usize) -> usize {
+        self.pitch_ranges.items.as_ref()
+            .and_then(|ranges| ranges.get(pitch_range))
+            .map_or(0, |range| range.actual_permutation_count.max(0) as usize)
+    }
+
+    pub fn get_permutations(&self, pitch_range: usize) -> Vec<SoundPermutation> {
+        self.pitch_ranges.items.as_ref()
+            .and_then(|ranges| ranges.get(pitch_range))
+            .and_then(|range| range.permutations.items.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Sound {
+    pub fn decode_samples(&self, permutation: &SoundPermutation, data: &[u8]) -> anyhow::Result<Vec<f32>> {
+        let channels = self.get_num_channels();
+        match permutation.compression {
+            SoundCompression::None => Ok(decode_pcm16(data)),
+            SoundCompression::XboxAdpcm => Ok(decode_xbox_adpcm(data, channels)),
+            // ima adpcm blocks are laid out differently from xbox adpcm ones
+            SoundCompression::ImaAdpcm => Err(MapReaderError::InvalidTag("ima adpcm compressed sounds aren't supported".into()).into()),
+            SoundCompression::Ogg => Err(MapReaderError::InvalidTag("ogg compressed sounds aren't supported".into()).into()),
+        }
+    }
+}
+
+#[tag_layout(size = 0x48)]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SoundPitchRange {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub name: Vec<u8>,
+    #[offset(0x20)] pub natural_pitch: f32,
+    #[offset(0x24)] pub bend_bounds: Bounds,
+    #[offset(0x2C)] pub actual_permutation_count: i16,
+    #[offset(0x3C)] pub permutations: Block<SoundPermutation>,
+}
+
+#[tag_layout(size = 0x7C)]
+#[wasm_bindgen(js_name = "HaloSoundPermutation")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SoundPermutation {
+    #[offset(0x00)]
+    #[deku(count = "32")]
+    pub(crate) name: Vec<u8>,
+    #[offset(0x20)] pub skip_fraction: f32,
+    #[offset(0x24)] pub gain: f32,
+    #[offset(0x28)] pub compression: SoundCompression,
+    #[offset(0x2A)] pub next_permutation_index: i16,
+    // followed by the mouth and subtitle data
+    #[offset(0x40)] pub(crate) samples: TagDataOffset,
+}
+
+#[wasm_bindgen(js_class = "HaloSoundPermutation")]
+impl SoundPermutation {
+    pub fn get_name(&self) -> String {
+        trim_tag_string(&self.name)
+    }
+
+    // external samples live in sounds.map rather than the map itself
+    pub fn is_external(&self) -> bool {
+        self.samples.is_external()
+    }
+}
+
+#[tag_layout(size = 0x80)]
+#[wasm_bindgen(js_name = "HaloSoundLooping")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SoundLooping {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub detail_sound_period_at_zero: f32,
+    #[offset(0x10)] pub detail_sound_period_at_one: f32,
+    #[offset(0x58)] pub continuous_damage_effect: TagDependency,
+    #[offset(0x68)] pub(crate) tracks: Block<SoundLoopingTrack>,
+    #[offset(0x74)] pub(crate) detail_sounds: Block<SoundLoopingDetail>,
+}
+
+#[wasm_bindgen(js_class = "HaloSoundLooping")]
+impl SoundLooping {
+    pub fn get_tracks(&self) -> Vec<SoundLoopingTrack> {
+        self.tracks.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_detail_sounds(&self) -> Vec<SoundLoopingDetail> {
+        self.detail_sounds.items.clone().unwrap_or_default()
+    }
+}
+
+#[tag_layout(size = 0xA0)]
+#[wasm_bindgen(js_name = "HaloSoundLoopingTrack")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SoundLoopingTrack {
+    #[offset(0x00)] pub flags: u32,
+    #[offset(0x04)] pub gain: f32,
+    #[offset(0x08)] pub fade_in_duration: f32,
+    #[offset(0x0C)] pub fade_out_duration: f32,
+    #[offset(0x30)] pub start: TagDependency,
+    #[offset(0x40)] pub(crate) loop_sound: TagDependency,
+    #[offset(0x50)] pub end: TagDependency,
+    #[offset(0x80)] pub alternate_loop: TagDependency,
+    #[offset(0x90)] pub alternate_end: TagDependency,
+}
+
+#[tag_layout(size = 0x68)]
+#[wasm_bindgen(js_name = "HaloSoundLoopingDetail")]
+#[derive(Debug, Clone, DekuRead)]
+pub struct SoundLoopingDetail {
+    #[offset(0x00)] pub(crate) sound: TagDependency,
+    #[offset(0x10)] pub random_period_bounds: Bounds,
+    #[offset(0x18)] pub gain: f32,
+    #[offset(0x1C)] pub flags: u32,
+    #[offset(0x50)] pub yaw_bounds: Bounds,
+    #[offset(0x58)] pub pitch_bounds: Bounds,
+    #[offset(0x60)] pub distance_bounds: Bounds,
+}
+
+pub fn decode_pcm16(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0)
+        .collect()
+}
+
+// xbox adpcm is ima adpcm in fixed 36 byte blocks per channel. each channel's
+// block starts with a 4 byte header (initial sample and step index), then the
+// channels take turns with 4 bytes (8 samples) of nibbles at a time
+pub fn decode_xbox_adpcm(data: &[u8], channels: usize) -> Vec<f32> {
+    if channels == 0 {
+        return Vec::new();
+    }
+    let block_size = XBOX_ADPCM_BLOCK_SIZE * channels;
+    let num_blocks = data.len() / block_size;
+    let mut result = vec![0.0; num_blocks * XBOX_ADPCM_BLOCK_SAMPLES * channels];
+    let mut channel_samples = [0i16; XBOX_ADPCM_BLOCK_SAMPLES];
+    for (block_index, block) in data.chunks_exact(block_size).enumerate() {
+        let out = &mut result[block_index * XBOX_ADPCM_BLOCK_SAMPLES * channels..];
+        for channel in 0..channels {
+            let header = &block[channel * 4..channel * 4 + 4];
+            let mut predictor = i16::from_le_bytes([header[0], header[1]]) as i32;
+            let mut step_index = (header[2] as i32).min(88);
+            channel_samples[0] = predictor as i16;
+            let mut n = 1;
+            for chunk in 0..8 {
+                let start = channels * 4 + (chunk * channels + channel) * 4;
+                for byte in &block[start..start + 4] {
+                    for nibble in [byte & 0xF, byte >> 4] {
+                        channel_samples[n] = decode_ima_nibble(nibble, &mut predictor, &mut step_index);
+                        n += 1;
+                    }
+                }
+            }
+            for (i, sample) in channel_samples.iter().enumerate() {
+                out[i * channels + channel] = *sample as f32 / 32768.0;
+            }
+        }
+    }
+    result
+}
+
+fn decode_ima_nibble(nibble: u8, predictor: &mut i32, step_index: &mut i32) -> i16 {
+    let step = IMA_STEP_TABLE[*step_index as usize];
+    let mut diff = step >> 3;
+    if nibble & 1 != 0 { diff += step >> 2; }
+    if nibble & 2 != 0 { diff += step >> 1; }
+    if nibble & 4 != 0 { diff += step; }
+    if nibble & 8 != 0 { diff = -diff; }
+    *predictor = (*predictor + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+    *step_index = (*step_index + IMA_INDEX_TABLE[nibble as usize]).clamp(0, 88);
+    *predictor as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_pcm16() {
+        let data = [0x00, 0x80, 0x00, 0x00, 0x00, 0x40, 0xFF];
+        assert_eq!(decode_pcm16(&data), vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_decode_xbox_adpcm() {
+        // zero nibbles at the smallest step size leave the sample unchanged
+        let mut block = vec![0u8; XBOX_ADPCM_BLOCK_SIZE];
+        block[0..2].copy_from_slice(&100i16.to_le_bytes());
+        let samples = decode_xbox_adpcm(&block, 1);
+        assert_eq!(samples.len(), XBOX_ADPCM_BLOCK_SAMPLES);
+        assert_eq!(samples[0], 100.0 / 32768.0);
+        assert_eq!(samples[1], 100.0 / 32768.0);
+
+        // a positive max nibble (7) raises the sample and the step index
+        block[4] = 0x07;
+        let samples = decode_xbox_adpcm(&block, 1);
+        assert_eq!(samples[1], (100.0 + 7.0 + 3.0 + 1.0) / 32768.0);
+
+        // stereo interleaves the channels, and trailing partial blocks are dropped
+        let mut stereo = vec![0u8; XBOX_ADPCM_BLOCK_SIZE * 2 + 10];
+        stereo[4..6].copy_from_slice(&(-200i16).to_le_bytes());
+        let samples = decode_xbox_adpcm(&stereo, 2);
+        assert_eq!(samples.len(), XBOX_ADPCM_BLOCK_SAMPLES * 2);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[1], -200.0 / 32768.0);
+    }
+
+    #[test]
+    fn test_unsupported_compression() {
+        use crate::halo::layout::read_test_tag;
+
+        let sound: Sound = read_test_tag(&[]);
+        for (compression, supported) in [(0u16, true), (1, true), (2, false), (3, false)] {
+            let permutation: SoundPermutation = read_test_tag(&[(0x28, &compression.to_le_bytes())]);
+            let block = [0u8; XBOX_ADPCM_BLOCK_SIZE];
+            assert_eq!(sound.decode_samples(&permutation, &block).is_ok(), supported);
+        }
+    }
+}
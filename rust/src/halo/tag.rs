@@ -7,6 +7,14 @@ use crate::halo::scenario::*;
 use crate::halo::bitmap::*;
 use crate::halo::shader::*;
 use crate::halo::model::*;
+use crate::halo::decal::*;
+use crate::halo::animation::*;
+use crate::halo::collision::*;
+use crate::halo::sound::*;
+use crate::halo::fog::*;
+use crate::halo::particle::*;
+use crate::halo::netgame::*;
+use crate::halo::layout::{TagField, TagLayout};
 
 #[wasm_bindgen(js_name = "HaloTagDependency")]
 #[derive(Debug, Clone, Copy, DekuRead)]
@@ -124,6 +132,36 @@ pub struct TagHeader {
     pub path: String, // read in after deserialization
 }
 
+// a tag header as seen by the tag browser
+#[wasm_bindgen(js_name = "HaloTagInfo", getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub tag_id: u32,
+    pub primary_class: TagClass,
+    pub secondary_class: TagClass,
+    pub tertiary_class: TagClass,
+    pub path: String,
+}
+
+impl From<&TagHeader> for TagInfo {
+    fn from(header: &TagHeader) -> Self {
+        TagInfo {
+            tag_id: header.tag_id,
+            primary_class: header.primary_class,
+            secondary_class: header.secondary_class,
+            tertiary_class: header.tertiary_class,
+            path: header.path.clone(),
+        }
+    }
+}
+
+// e.g. "levels\b30\b30 (Scenario @ 0x40440028)", for error messages
+impl std::fmt::Display for TagHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?} @ 0x{:x})", self.path, self.primary_class, self.tag_data)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TagData {
     Scenario(Scenario),
@@ -134,64 +172,106 @@ pub enum TagData {
     ShaderTransparentChicago(ShaderTransparentChicago),
     ShaderTransparentGeneric(ShaderTransparentGeneric),
     ShaderTransparentWater(ShaderTransparentWater),
+    ShaderTransparentChicagoExtended(ShaderTransparentChicagoExtended),
+    ShaderTransparentGlass(ShaderTransparentGlass),
+    ShaderTransparentPlasma(ShaderTransparentPlasma),
+    ShaderTransparentMeter(ShaderTransparentMeter),
     Scenery(Scenery),
     Sky(Sky),
     GbxModel(GbxModel),
+    DeviceLightFixture(DeviceLightFixture),
+    Object(GameObject),
+    ModelAnimations(ModelAnimations),
+    ModelCollisionGeometry(ModelCollisionGeometry),
+    Sound(Sound),
+    SoundLooping(SoundLooping),
+    Decal(Decal),
+    DetailObjectCollection(DetailObjectCollection),
+    Fog(Fog),
+    Particle(Particle),
+    ParticleSystem(ParticleSystem),
+    WeatherParticleSystem(WeatherParticleSystem),
+    LensFlare(LensFlare),
+    Glow(Glow),
+    ItemCollection(ItemCollection),
 }
 
-impl<'a> TryFrom<&'a TagData> for &'a Scenario {
-    type Error = String;
-
-    fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
-        match data {
-            TagData::Scenario(x) => Ok(x),
-            t => Err(format!("invalid tag type: expected Scenario, got {:?}", t))
-        }
-    }
-}
-
-impl<'a> TryFrom<&'a TagData> for &'a Bitmap {
-    type Error = String;
-
-    fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
-        match data {
-            TagData::Bitmap(x) => Ok(x),
-            t => Err(format!("invalid tag type: expected Bitmap, got {:?}", t))
+impl TagData {
+    // only tags declared with #[tag_layout] can be dumped field by field
+    pub fn get_layout_fields(&self) -> Option<Vec<TagField>> {
+        match self {
+            TagData::Scenario(x) => Some(x.fields()),
+            TagData::Bitmap(x) => Some(x.fields()),
+            TagData::BSP(x) => Some(x.fields()),
+            TagData::ShaderEnvironment(x) => Some(x.fields()),
+            TagData::Scenery(x) => Some(x.fields()),
+            TagData::Sky(x) => Some(x.fields()),
+            TagData::GbxModel(x) => Some(x.fields()),
+            TagData::DeviceLightFixture(x) => Some(x.fields()),
+            TagData::Object(x) => Some(x.fields()),
+            TagData::ModelAnimations(x) => Some(x.fields()),
+            TagData::ModelCollisionGeometry(x) => Some(x.fields()),
+            TagData::Sound(x) => Some(x.fields()),
+            TagData::SoundLooping(x) => Some(x.fields()),
+            TagData::Decal(x) => Some(x.fields()),
+            TagData::DetailObjectCollection(x) => Some(x.fields()),
+            TagData::Fog(x) => Some(x.fields()),
+            TagData::Particle(x) => Some(x.fields()),
+            TagData::ParticleSystem(x) => Some(x.fields()),
+            TagData::WeatherParticleSystem(x) => Some(x.fields()),
+            TagData::LensFlare(x) => Some(x.fields()),
+            TagData::Glow(x) => Some(x.fields()),
+            TagData::ItemCollection(x) => Some(x.fields()),
+            TagData::ShaderTransparentChicagoExtended(x) => Some(x.fields()),
+            TagData::ShaderTransparentGlass(x) => Some(x.fields()),
+            TagData::ShaderTransparentPlasma(x) => Some(x.fields()),
+            TagData::ShaderTransparentMeter(x) => Some(x.fields()),
+            _ => None,
         }
     }
 }
 
-impl<'a> TryFrom<&'a TagData> for &'a BSP {
-    type Error = String;
+// lets tag data be taken out of a TagData by type, e.g. with get_dependency_data()
+macro_rules! impl_try_from_tag_data {
+    ($($variant:ident => $ty:ty),* $(,)?) => {
+        $(
+            impl<'a> TryFrom<&'a TagData> for &'a $ty {
+                type Error = String;
 
-    fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
-        match data {
-            TagData::BSP(x) => Ok(x),
-            t => Err(format!("invalid tag type: expected BSP, got {:?}", t))
-        }
-    }
+                fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
+                    match data {
+                        TagData::$variant(x) => Ok(x),
+                        t => Err(format!("invalid tag type: expected {}, got {:?}", stringify!($variant), t))
+                    }
+                }
+            }
+        )*
+    };
 }
-impl<'a> TryFrom<&'a TagData> for &'a ShaderEnvironment {
-    type Error = String;
 
-    fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
-        match data {
-            TagData::ShaderEnvironment(x) => Ok(x),
-            t => Err(format!("invalid tag type: expected ShaderEnvironment, got {:?}", t))
-        }
-    }
-}
-
-impl<'a> TryFrom<&'a TagData> for &'a Scenery {
-    type Error = String;
-
-    fn try_from(data: &'a TagData) -> std::result::Result<Self, Self::Error> {
-        match data {
-            TagData::Scenery(x) => Ok(x),
-            t => Err(format!("invalid tag type: expected Scenery, got {:?}", t))
-        }
-    }
-}
+impl_try_from_tag_data!(
+    Scenario => Scenario,
+    Bitmap => Bitmap,
+    BSP => BSP,
+    ShaderEnvironment => ShaderEnvironment,
+    Scenery => Scenery,
+    Sky => Sky,
+    GbxModel => GbxModel,
+    DeviceLightFixture => DeviceLightFixture,
+    Object => GameObject,
+    ModelAnimations => ModelAnimations,
+    Sound => Sound,
+    SoundLooping => SoundLooping,
+    ModelCollisionGeometry => ModelCollisionGeometry,
+    Decal => Decal,
+    DetailObjectCollection => DetailObjectCollection,
+    Fog => Fog,
+    ParticleSystem => ParticleSystem,
+    WeatherParticleSystem => WeatherParticleSystem,
+    LensFlare => LensFlare,
+    Glow => Glow,
+    ItemCollection => ItemCollection,
+);
 
 #[derive(Debug, Clone)]
 pub struct Tag {
@@ -21,3 +21,9 @@ pub fn read_null_terminated_string<T: Read>(data: &mut T) -> Result<String> {
     }
     Ok(res)
 }
+
+// fixed size tag strings are null padded
+pub fn trim_tag_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
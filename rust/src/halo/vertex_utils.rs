@@ -0,0 +1,131 @@
+// xbox maps store model and bsp vertices compressed. these expand them to the
+// pc layouts the renderer expects
+
+use std::convert::TryInto;
+
+pub const MODEL_VERTEX_SIZE: usize = 68;
+pub const COMPRESSED_MODEL_VERTEX_SIZE: usize = 32;
+pub const BSP_VERTEX_SIZE: usize = 56;
+pub const COMPRESSED_BSP_VERTEX_SIZE: usize = 32;
+pub const LIGHTMAP_VERTEX_SIZE: usize = 20;
+pub const COMPRESSED_LIGHTMAP_VERTEX_SIZE: usize = 8;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i16(data: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+// unit vectors are packed as 11, 11 and 10 bit signed fixed point
+pub fn decompress_vector(packed: u32) -> [f32; 3] {
+    [
+        sign_extend(packed & 0x7FF, 11) as f32 / 1023.0,
+        sign_extend((packed >> 11) & 0x7FF, 11) as f32 / 1023.0,
+        sign_extend(packed >> 22, 10) as f32 / 511.0,
+    ]
+}
+
+fn normalized_i16(value: i16) -> f32 {
+    (value as f32 / 32767.0).max(-1.0)
+}
+
+fn push_f32s(result: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        result.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+// position, packed normal/binormal/tangent, i16 uv, two node indices (times
+// three) and the first node's weight, to position, normal, binormal, tangent,
+// f32 uv, i16 node indices and both weights
+pub fn decompress_model_vertices(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() / COMPRESSED_MODEL_VERTEX_SIZE * MODEL_VERTEX_SIZE);
+    for vertex in data.chunks_exact(COMPRESSED_MODEL_VERTEX_SIZE) {
+        result.extend_from_slice(&vertex[0..12]);
+        for i in 0..3 {
+            push_f32s(&mut result, &decompress_vector(read_u32(vertex, 12 + i * 4)));
+        }
+        push_f32s(&mut result, &[normalized_i16(read_i16(vertex, 24)), normalized_i16(read_i16(vertex, 26))]);
+        for node_index in [vertex[28] as i8, vertex[29] as i8] {
+            result.extend_from_slice(&(node_index as i16 / 3).to_le_bytes());
+        }
+        let weight = normalized_i16(read_i16(vertex, 30));
+        push_f32s(&mut result, &[weight, 1.0 - weight]);
+    }
+    result
+}
+
+// position, packed normal/binormal/tangent and f32 uv
+pub fn decompress_bsp_vertices(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() / COMPRESSED_BSP_VERTEX_SIZE * BSP_VERTEX_SIZE);
+    for vertex in data.chunks_exact(COMPRESSED_BSP_VERTEX_SIZE) {
+        result.extend_from_slice(&vertex[0..12]);
+        for i in 0..3 {
+            push_f32s(&mut result, &decompress_vector(read_u32(vertex, 12 + i * 4)));
+        }
+        result.extend_from_slice(&vertex[24..32]);
+    }
+    result
+}
+
+// packed normal and i16 uv
+pub fn decompress_lightmap_vertices(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() / COMPRESSED_LIGHTMAP_VERTEX_SIZE * LIGHTMAP_VERTEX_SIZE);
+    for vertex in data.chunks_exact(COMPRESSED_LIGHTMAP_VERTEX_SIZE) {
+        push_f32s(&mut result, &decompress_vector(read_u32(vertex, 0)));
+        push_f32s(&mut result, &[normalized_i16(read_i16(vertex, 4)), normalized_i16(read_i16(vertex, 6))]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_at(data: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_decompress_vector() {
+        assert_eq!(decompress_vector(1023), [1.0, 0.0, 0.0]);
+        assert_eq!(decompress_vector(1023 << 11), [0.0, 1.0, 0.0]);
+        assert_eq!(decompress_vector(511 << 22), [0.0, 0.0, 1.0]);
+        // -1023 in 11 bits
+        assert_eq!(decompress_vector(0x401), [-1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_decompress_model_vertices() {
+        let mut vertex = Vec::new();
+        push_f32s(&mut vertex, &[1.0, 2.0, 3.0]);
+        for packed in [511u32 << 22, 1023, 1023 << 11] {
+            vertex.extend_from_slice(&packed.to_le_bytes());
+        }
+        for x in [32767i16, -32767] {
+            vertex.extend_from_slice(&x.to_le_bytes());
+        }
+        vertex.extend_from_slice(&[6, 0xFD]);
+        vertex.extend_from_slice(&16384i16.to_le_bytes());
+        assert_eq!(vertex.len(), COMPRESSED_MODEL_VERTEX_SIZE);
+
+        let result = decompress_model_vertices(&vertex);
+        assert_eq!(result.len(), MODEL_VERTEX_SIZE);
+        assert_eq!(&result[0..12], &vertex[0..12]);
+        assert_eq!((f32_at(&result, 12), f32_at(&result, 20)), (0.0, 1.0));
+        assert_eq!((f32_at(&result, 48), f32_at(&result, 52)), (1.0, -1.0));
+        assert_eq!((read_i16(&result, 56), read_i16(&result, 58)), (2, -1));
+        assert!((f32_at(&result, 60) - 0.5).abs() < 1e-3);
+        assert!((f32_at(&result, 60) + f32_at(&result, 64) - 1.0).abs() < 1e-6);
+
+        assert_eq!(decompress_bsp_vertices(&vertex).len(), BSP_VERTEX_SIZE);
+        assert_eq!(decompress_lightmap_vertices(&vertex).len(), 4 * LIGHTMAP_VERTEX_SIZE);
+    }
+}
@@ -1,34 +1,61 @@
+use std::{convert::{TryFrom, TryInto}, rc::Rc};
 use js_sys::Array;
 use wasm_bindgen::prelude::*;
 
+use crate::halo::common::Block;
 use crate::halo::map::*;
 use crate::halo::scenario::*;
 use crate::halo::bitmap::*;
 use crate::halo::tag::*;
 use crate::halo::model::*;
+use crate::halo::decal::*;
+use crate::halo::animation::*;
+use crate::halo::collision::*;
+use crate::halo::sound::*;
+use crate::halo::fog::*;
+use crate::halo::particle::*;
+use crate::halo::emitter::*;
+use crate::halo::netgame::*;
+use crate::halo::cutscene::*;
+use crate::halo::script::*;
+use crate::camera_path::CameraPath;
+use crate::halo::layout::format_fields;
+use crate::halo::vertex_utils::*;
 
 #[wasm_bindgen]
 pub struct HaloSceneManager {
     mgr: MapManager,
+    // why dependencies resolved to null, for the caller to log
+    errors: Vec<String>,
 }
 
 #[wasm_bindgen(js_class = "HaloSceneManager")]
 impl HaloSceneManager {
-    pub fn new(map_data: Vec<u8>) -> Self {
-        let mgr = MapManager::new(map_data).unwrap();
-        HaloSceneManager { mgr }
+    pub fn new(map_data: Vec<u8>) -> Result<HaloSceneManager, String> {
+        let mgr = MapManager::new(map_data)
+            .map_err(|err| format!("failed to read map: {}", err))?;
+        Ok(HaloSceneManager { mgr, errors: Vec::new() })
+    }
+
+    // the errors behind any null palette entries or dependencies since the last call
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
     }
 
     fn get_shader(&mut self, shader_hdr: TagHeader) -> JsValue {
-        match self.mgr.read_tag(&shader_hdr) {
-            Ok(tag) => match tag.data {
+        match self.mgr.get_tag(&shader_hdr) {
+            Ok(tag) => match &tag.data {
                 TagData::ShaderEnvironment(s) if s.base_bitmap.path_pointer != 0 => {
-                    JsValue::from(s)
+                    JsValue::from(s.clone())
                 },
-                TagData::ShaderModel(s) => JsValue::from(s),
-                TagData::ShaderTransparentGeneric(s) => JsValue::from(s),
-                TagData::ShaderTransparentChicago(s) => JsValue::from(s),
-                TagData::ShaderTransparentWater(s) => JsValue::from(s),
+                TagData::ShaderModel(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentGeneric(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentChicago(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentWater(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentChicagoExtended(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentGlass(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentPlasma(s) => JsValue::from(s.clone()),
+                TagData::ShaderTransparentMeter(s) => JsValue::from(s.clone()),
                 _ => JsValue::NULL,
             },
             Err(_) => JsValue::NULL,
@@ -37,147 +64,517 @@ impl HaloSceneManager {
 
     pub fn get_model_shaders(&mut self, model: &GbxModel) -> Array {
         let result = Array::new();
-        for model_shader in model.shaders.items.as_ref().unwrap() {
+        for model_shader in model.shaders.items.as_deref().unwrap_or_default() {
             // FIXME do we need the permutation value?
-            let shader_hdr = self.mgr.resolve_dependency(&model_shader.shader).unwrap();
-            let js_value = self.get_shader(shader_hdr);
+            let js_value = match self.mgr.resolve_dependency(&model_shader.shader) {
+                Some(shader_hdr) => self.get_shader(shader_hdr),
+                None => JsValue::NULL,
+            };
             result.push(&js_value);
         }
         result
     }
 
     pub fn get_material_shader(&mut self, material: &BSPMaterial) -> JsValue {
-        let shader_hdr = self.mgr.resolve_dependency(&material.shader).unwrap();
-        self.get_shader(shader_hdr)
+        match self.mgr.resolve_dependency(&material.shader) {
+            Some(shader_hdr) => self.get_shader(shader_hdr),
+            None => JsValue::NULL,
+        }
     }
 
     pub fn get_model_parts(&mut self, model: &GbxModel) -> Vec<GbxModelPart> {
         let mut result = Vec::new();
-        for geometry in model.geometries.items.as_ref().unwrap() {
-            for part in geometry.parts.items.as_ref().unwrap() {
+        for geometry in model.geometries.items.as_deref().unwrap_or_default() {
+            for part in geometry.parts.items.as_deref().unwrap_or_default() {
                 result.push(part.clone());
             }
         }
         result
     }
 
+    pub fn get_model_permutation_parts(&mut self, model: &GbxModel, permutation_index: usize, detail_level: GbxModelDetailLevel) -> Vec<GbxModelPart> {
+        model.get_parts(permutation_index, detail_level)
+    }
+
     pub fn get_scenery_model(&mut self, scenery: &Scenery) -> Option<GbxModel> {
         self.resolve_model_dependency(&scenery.model)
     }
 
-    pub fn get_scenery_palette(&mut self) -> Vec<Scenery> {
-        let scenario_tag = self.mgr.get_scenario().unwrap();
-        let scenario = match scenario_tag.data {
-            TagData::Scenario(s) => s,
-            _ => unreachable!(),
-        };
-        let mut result = Vec::new();
-        for palette_entry in scenario.scenery_palette.items.as_ref().unwrap() {
-            let scenery_header = self.mgr.resolve_dependency(&palette_entry.obj).unwrap();
-            let scenery_tag = self.mgr.read_tag(&scenery_header).unwrap();
-            match scenery_tag.data {
-                TagData::Scenery(s) => result.push(s),
-                _ => unreachable!(),
+    // like the other palettes, entries are null where a tag is missing or
+    // can't be read, so that instances can still index into the result
+    pub fn get_scenery_palette(&mut self) -> Result<Array, String> {
+        self.with_scenario(|mgr, scenario| {
+            mgr.get_palette(&scenario.scenery_palette, |palette_entry| &palette_entry.obj, |scenery: Scenery| scenery.into())
+        })
+    }
+
+    pub fn get_scenery_instances(&mut self) -> Result<Vec<ScenarioScenery>, String> {
+        self.with_scenario(|_, scenario| scenario.scenery.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_object_instances(&mut self, object_type: ObjectType) -> Result<Vec<ScenarioObjectInstance>, String> {
+        self.with_scenario(|_, scenario| scenario.get_object_instances(object_type).unwrap_or_default())
+    }
+
+    pub fn get_object_palette(&mut self, object_type: ObjectType) -> Result<Array, String> {
+        self.with_scenario(|mgr, scenario| {
+            let result = Array::new();
+            for palette_entry in scenario.get_object_palette(object_type).unwrap_or_default() {
+                match mgr.resolve_object(&palette_entry.obj) {
+                    Some(object) => result.push(&JsValue::from(object)),
+                    None => result.push(&JsValue::NULL),
+                };
+            }
+            result
+        })
+    }
+
+    pub fn get_object_model(&mut self, object: &GameObject) -> Option<GbxModel> {
+        self.resolve_model_dependency(&object.model)
+    }
+
+    pub fn get_object_animations(&mut self, object: &GameObject) -> Option<ModelAnimations> {
+        self.resolve_tag_data(&object.animation_graph)
+    }
+
+    pub fn get_object_collision(&mut self, object: &GameObject) -> Option<ModelCollisionGeometry> {
+        self.resolve_tag_data(&object.collision_model)
+    }
+
+    pub fn get_object_looping_sounds(&mut self, object: &GameObject) -> Vec<SoundLooping> {
+        self.resolve_attachments(object, TagClass::SoundLooping)
+    }
+
+    // attachments are placed at markers, which we don't resolve, so these
+    // should be updated with the object's origin
+    pub fn get_object_glow_emitters(&mut self, object: &GameObject) -> Vec<GlowEmitter> {
+        self.resolve_attachments(object, TagClass::Glow).iter()
+            .map(GlowEmitter::new)
+            .collect()
+    }
+
+    // one emitter per particle type of each attached particle system
+    pub fn get_object_particle_system_emitters(&mut self, object: &GameObject) -> Vec<ParticleSystemEmitter> {
+        self.resolve_attachments::<ParticleSystem>(object, TagClass::ParticleSystem).iter()
+            .flat_map(|particle_system| particle_system.particle_types.items.as_deref().unwrap_or_default())
+            .map(ParticleSystemEmitter::new)
+            .collect()
+    }
+
+    pub fn get_sound_looping_track_sound(&mut self, track: &SoundLoopingTrack) -> Option<Sound> {
+        self.resolve_tag_data(&track.loop_sound)
+    }
+
+    pub fn get_sound_looping_detail_sound(&mut self, detail: &SoundLoopingDetail) -> Option<Sound> {
+        self.resolve_tag_data(&detail.sound)
+    }
+
+    // returns interleaved samples for permutations stored in the map itself;
+    // external ones have to be read from sounds.map with HaloBitmapReader
+    pub fn get_sound_samples(&mut self, sound: &Sound, permutation: &SoundPermutation) -> Result<Vec<f32>, String> {
+        if permutation.is_external() {
+            return Err(format!("permutation {} is stored in sounds.map", permutation.get_name()));
+        }
+        let data = self.mgr.read_map_bytes(permutation.samples.file_offset as u64, permutation.samples.size as usize)
+            .map_err(|err| format!("failed to read permutation {}: {}", permutation.get_name(), err))?;
+        sound.decode_samples(permutation, &data)
+            .map_err(|err| format!("failed to decode permutation {}: {}", permutation.get_name(), err))
+    }
+
+    // player spawns, then netgame flags, then netgame equipment
+    pub fn get_netgame_markers(&mut self) -> Result<Vec<NetgameMarker>, String> {
+        self.with_scenario(|_, scenario| scenario.get_netgame_markers())
+    }
+
+    pub fn get_marker_item_collection(&mut self, marker: &NetgameMarker) -> Option<ItemCollection> {
+        self.resolve_tag_data(marker.item_collection.as_ref()?)
+    }
+
+    // like the palettes, entries are null where an item can't be read
+    pub fn get_item_collection_objects(&mut self, collection: &ItemCollection) -> Array {
+        let result = Array::new();
+        for permutation in collection.permutations.items.as_deref().unwrap_or_default() {
+            match self.resolve_object(&permutation.item) {
+                Some(object) => result.push(&JsValue::from(object)),
+                None => result.push(&JsValue::NULL),
             };
         }
         result
     }
 
-    pub fn get_scenery_instances(&mut self) -> Vec<ScenarioScenery> {
-        let scenario_tag = self.mgr.get_scenario().unwrap();
-        let TagData::Scenario(scenario) = scenario_tag.data else {
-            unreachable!();
+    pub fn get_cutscene_flags(&mut self) -> Result<Vec<CutsceneFlag>, String> {
+        self.with_scenario(|_, scenario| scenario.cutscene_flags.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_cutscene_camera_points(&mut self) -> Result<Vec<CutsceneCameraPoint>, String> {
+        self.with_scenario(|_, scenario| scenario.cutscene_camera_points.items.clone().unwrap_or_default())
+    }
+
+    // a path visiting the given camera points (or all of them, if none are
+    // given) one after another, seconds_per_point apart
+    pub fn get_cutscene_camera_path(&mut self, point_indices: &[u32], seconds_per_point: f32, aspect: f32) -> Result<CameraPath, String> {
+        self.with_scenario(|_, scenario| {
+            let points = scenario.cutscene_camera_points.items.as_deref().unwrap_or_default();
+            let selected: Vec<&CutsceneCameraPoint> = if point_indices.is_empty() {
+                points.iter().collect()
+            } else {
+                point_indices.iter()
+                    .map(|&i| points.get(i as usize).ok_or_else(|| format!("no cutscene camera point {}", i)))
+                    .collect::<Result<_, _>>()?
+            };
+            let keyframes = selected.iter().enumerate()
+                .map(|(i, point)| point.to_keyframe(i as f32 * seconds_per_point, aspect))
+                .collect();
+            Ok(CameraPath::from_keyframes(keyframes))
+        })?
+    }
+
+    pub fn get_recorded_animations(&mut self) -> Result<Vec<RecordedAnimation>, String> {
+        self.with_scenario(|_, scenario| scenario.recorded_animations.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_scripts(&mut self) -> Result<Vec<ScenarioScript>, String> {
+        self.with_scenario(|_, scenario| scenario.scripts.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_script_globals(&mut self) -> Result<Vec<ScenarioGlobal>, String> {
+        self.with_scenario(|_, scenario| scenario.globals.items.clone().unwrap_or_default())
+    }
+
+    // the scenario's globals and scripts, reconstructed as hsc source
+    pub fn get_script_source(&mut self) -> Result<String, String> {
+        self.with_scenario(|_, scenario| {
+            let syntax = scenario.get_script_syntax().map_err(|err| format!("failed to decode script syntax: {}", err))?;
+            Ok(syntax.decompile(scenario.scripts.items.as_deref().unwrap_or_default(), scenario.globals.items.as_deref().unwrap_or_default()))
+        })?
+    }
+
+    // the objects, cutscene flags, camera points etc. that scripts name
+    pub fn get_script_references(&mut self) -> Result<Vec<ScriptReference>, String> {
+        self.with_scenario(|_, scenario| {
+            let syntax = scenario.get_script_syntax().map_err(|err| format!("failed to decode script syntax: {}", err))?;
+            Ok(syntax.get_references(scenario.scripts.items.as_deref().unwrap_or_default(), scenario.globals.items.as_deref().unwrap_or_default()))
+        })?
+    }
+
+    pub fn get_light_fixture_instances(&mut self) -> Result<Vec<ScenarioLightFixture>, String> {
+        self.with_scenario(|_, scenario| scenario.light_fixtures.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_light_fixture_palette(&mut self) -> Result<Array, String> {
+        self.with_scenario(|mgr, scenario| {
+            mgr.get_palette(&scenario.light_fixture_palette, |palette_entry| &palette_entry.obj, |light_fixture: DeviceLightFixture| light_fixture.into())
+        })
+    }
+
+    pub fn get_light_fixture_model(&mut self, light_fixture: &DeviceLightFixture) -> Option<GbxModel> {
+        self.resolve_model_dependency(&light_fixture.model)
+    }
+
+    pub fn get_decal_instances(&mut self) -> Result<Vec<ScenarioDecal>, String> {
+        self.with_scenario(|_, scenario| scenario.decals.items.clone().unwrap_or_default())
+    }
+
+    pub fn get_decal_palette(&mut self) -> Result<Array, String> {
+        self.with_scenario(|mgr, scenario| {
+            mgr.get_palette(&scenario.decal_palette, |dependency| dependency, |decal: Decal| decal.into())
+        })
+    }
+
+    pub fn get_decal_bitmap(&mut self, decal: &Decal) -> Option<Bitmap> {
+        self.resolve_bitmap_dependency(&decal.map)
+    }
+
+    pub fn get_detail_object_collection_palette(&mut self) -> Result<Array, String> {
+        self.with_scenario(|mgr, scenario| {
+            mgr.get_palette(&scenario.detail_object_collection_palette, |palette_entry| &palette_entry.obj, |collection: DetailObjectCollection| collection.into())
+        })
+    }
+
+    pub fn get_detail_object_collection_bitmap(&mut self, collection: &DetailObjectCollection) -> Option<Bitmap> {
+        self.resolve_bitmap_dependency(&collection.sprite_plate)
+    }
+
+    // skies that can't be read are left out
+    pub fn get_skies(&mut self) -> Result<Vec<Sky>, String> {
+        self.with_scenario(|mgr, scenario| {
+            scenario.skies.items.as_deref().unwrap_or_default().iter()
+                .filter_map(|dependency| mgr.resolve_tag_data(dependency))
+                .collect()
+        })
+    }
+
+    // indoor clusters (those without a sky) use the indoor fog of the
+    // scenario's first sky. None if the scenario has no readable sky at all
+    pub fn get_cluster_fog(&mut self, bsp: &BSP, cluster: u32) -> Result<Option<ClusterFog>, String> {
+        let cluster = cluster as usize;
+        if cluster >= bsp.get_num_clusters() {
+            return Err(format!("cluster {} out of range", cluster));
+        }
+        let cluster_sky = bsp.get_cluster_sky(cluster);
+        let sky_dependency = self.with_scenario(|_, scenario| {
+            scenario.skies.items.as_deref().unwrap_or_default().get(cluster_sky.unwrap_or(0) as usize).copied()
+        })?;
+        let Some(sky) = sky_dependency.and_then(|dependency| self.resolve_tag_data::<Sky>(&dependency)) else {
+            return Ok(None);
         };
-        scenario.scenery.items.as_ref().cloned().unwrap()
+        let planar_fog = bsp.get_cluster_fog_dependency(cluster)
+            .and_then(|dependency| self.resolve_tag_data(dependency));
+        Ok(Some(ClusterFog::new(&sky, cluster_sky.is_some(), planar_fog)))
     }
 
-    pub fn get_skies(&mut self) -> Vec<Sky> {
-        let mut result = Vec::new();
-        let TagData::Scenario(scenario_data) = self.mgr.get_scenario().unwrap().data else {
-            unreachable!();
+    pub fn get_fog_plane_fog(&mut self, bsp: &BSP, fog_plane: u32) -> Option<Fog> {
+        let dependency = bsp.get_fog_plane_fog_dependency(fog_plane as usize)?;
+        self.resolve_tag_data(dependency)
+    }
+
+    // one emitter per particle type of the cluster's weather, blown by its wind
+    pub fn get_cluster_weather_emitters(&mut self, bsp: &BSP, cluster: u32) -> Vec<WeatherEmitter> {
+        let Some(weather) = bsp.get_cluster_weather(cluster as usize) else {
+            return Vec::new();
         };
-        for dependency in scenario_data.skies.items.as_ref().unwrap() {
-            let sky_header = self.mgr.resolve_dependency(dependency).unwrap();
-            match self.mgr.read_tag(&sky_header).unwrap().data {
-                TagData::Sky(s) => {
-                    result.push(s);
-                },
-                _ => unreachable!(),
-            }
-        }
-        result
+        let Some(particle_system) = self.resolve_tag_data::<WeatherParticleSystem>(&weather.particle_system) else {
+            return Vec::new();
+        };
+        let wind_direction = [weather.wind_direction.i, weather.wind_direction.j, weather.wind_direction.k];
+        particle_system.particle_types.items.as_deref().unwrap_or_default().iter()
+            .map(|particle_type| WeatherEmitter::new(particle_type, &wind_direction, weather.wind_magnitude))
+            .collect()
+    }
+
+    pub fn resolve_lens_flare_dependency(&mut self, dependency: &TagDependency) -> Option<LensFlare> {
+        self.resolve_tag_data(dependency)
+    }
+
+    pub fn resolve_glow_dependency(&mut self, dependency: &TagDependency) -> Option<Glow> {
+        self.resolve_tag_data(dependency)
     }
 
-    pub fn get_bsps(&mut self) -> Vec<BSP> {
-        let scenario_tag = self.mgr.get_scenario().unwrap();
-        self.mgr.get_scenario_bsps(&scenario_tag).unwrap().iter()
-            .map(|tag| match &tag.data {
-                TagData::BSP(bsp) => bsp.clone(),
-                _ => unreachable!(),
-            }).collect()
+    pub fn get_bsps(&mut self) -> Result<Vec<BSP>, String> {
+        let scenario_tag = self.get_scenario_tag()?;
+        let bsp_tags = self.mgr.get_scenario_bsps(&scenario_tag)
+            .map_err(|err| format!("failed to read bsps: {}", err))?;
+        bsp_tags.iter()
+            .map(|tag| {
+                let bsp: &BSP = (&tag.data).try_into()
+                    .map_err(|err| format!("{}: {}", tag.header, err))?;
+                Ok(bsp.clone())
+            })
+            .collect()
     }
 
     pub fn get_bsp_lightmaps(&self, bsp: &BSP) -> Vec<BSPLightmap> {
-        bsp.lightmaps.items.as_ref().cloned().unwrap()
+        bsp.lightmaps.items.clone().unwrap_or_default()
     }
 
     pub fn get_lightmap_materials(&self, lightmap: &BSPLightmap) -> Vec<BSPMaterial> {
-        lightmap.materials.items.as_ref().cloned().unwrap()
+        lightmap.materials.items.clone().unwrap_or_default()
+    }
+
+    pub fn get_tags(&self, class: Option<TagClass>, path_prefix: Option<String>) -> Vec<TagInfo> {
+        self.mgr.find_tags(class, path_prefix.as_deref()).into_iter()
+            .map(TagInfo::from)
+            .collect()
+    }
+
+    pub fn get_tag_info(&self, tag_id: u32) -> Option<TagInfo> {
+        self.mgr.get_tag_header(tag_id).map(TagInfo::from)
+    }
+
+    // best effort, see MapManager::get_tag_dependencies
+    pub fn get_tag_dependencies(&mut self, tag_id: u32) -> Result<Vec<TagInfo>, String> {
+        let tag_header = self.get_tag_header(tag_id)?;
+        let dependencies = self.mgr.get_tag_dependencies(&tag_header)
+            .map_err(|err| format!("failed to find dependencies of {}: {}", tag_header, err))?;
+        Ok(dependencies.iter().map(TagInfo::from).collect())
+    }
+
+    // decodes any supported tag into its Halo* class
+    pub fn decode_tag(&mut self, tag_id: u32) -> Result<JsValue, String> {
+        let tag_header = self.get_tag_header(tag_id)?;
+        let tag = self.mgr.get_tag(&tag_header)
+            .map_err(|err| format!("failed to read {}: {}", tag_header, err))?;
+        Ok(match &tag.data {
+            TagData::Scenario(x) => x.clone().into(),
+            TagData::Bitmap(x) => x.clone().into(),
+            TagData::BSP(x) => x.clone().into(),
+            TagData::ShaderEnvironment(x) => x.clone().into(),
+            TagData::ShaderModel(x) => x.clone().into(),
+            TagData::ShaderTransparentChicago(x) => x.clone().into(),
+            TagData::ShaderTransparentGeneric(x) => x.clone().into(),
+            TagData::ShaderTransparentWater(x) => x.clone().into(),
+            TagData::ShaderTransparentChicagoExtended(x) => x.clone().into(),
+            TagData::ShaderTransparentGlass(x) => x.clone().into(),
+            TagData::ShaderTransparentPlasma(x) => x.clone().into(),
+            TagData::ShaderTransparentMeter(x) => x.clone().into(),
+            TagData::Scenery(x) => x.clone().into(),
+            TagData::Sky(x) => x.clone().into(),
+            TagData::GbxModel(x) => x.clone().into(),
+            TagData::DeviceLightFixture(x) => x.clone().into(),
+            TagData::Object(x) => x.clone().into(),
+            TagData::ModelAnimations(x) => x.clone().into(),
+            TagData::ModelCollisionGeometry(x) => x.clone().into(),
+            TagData::Sound(x) => x.clone().into(),
+            TagData::SoundLooping(x) => x.clone().into(),
+            TagData::Decal(x) => x.clone().into(),
+            TagData::DetailObjectCollection(x) => x.clone().into(),
+            TagData::Fog(x) => x.clone().into(),
+            TagData::Particle(x) => x.clone().into(),
+            TagData::ParticleSystem(x) => x.clone().into(),
+            TagData::WeatherParticleSystem(x) => x.clone().into(),
+            TagData::LensFlare(x) => x.clone().into(),
+            TagData::Glow(x) => x.clone().into(),
+            TagData::ItemCollection(x) => x.clone().into(),
+        })
+    }
+
+    // a text dump of every field and its offset, for tags with a declared layout
+    pub fn dump_tag(&mut self, tag_id: u32) -> Result<String, String> {
+        let tag_header = self.get_tag_header(tag_id)?;
+        let tag = self.mgr.get_tag(&tag_header)
+            .map_err(|err| format!("failed to read {}: {}", tag_header, err))?;
+        let fields = tag.data.get_layout_fields()
+            .ok_or_else(|| format!("{} has no declared layout to dump", tag_header))?;
+        Ok(format!("{}\n{}", tag_header, format_fields(&fields)))
+    }
+
+    pub fn get_map_version(&self) -> MapVersion {
+        self.mgr.header.version
     }
 
-    pub fn get_model_part_indices(&mut self, part: &GbxModelPart) -> Vec<u16> {
-        let offset = part.tri_offset + self.mgr.tag_index_header.model_data_file_offset + self.mgr.tag_index_header.vertex_data_size;
-        let count = part.tri_count();
-        self.mgr.read_map_u16s(offset as u64, count as usize).unwrap()
+    pub fn get_model_part_indices(&mut self, part: &GbxModelPart) -> Result<Vec<u16>, String> {
+        self.mgr.read_model_indices(part)
+            .map_err(|err| format!("failed to read {} model indices at 0x{:x}: {}", part.tri_count(), self.mgr.get_model_index_data_offset(part), err))
     }
 
-    pub fn get_model_part_vertices(&mut self, part: &GbxModelPart) -> Vec<u8> {
-        let offset = part.vert_offset + self.mgr.tag_index_header.model_data_file_offset;
-        let count = part.vert_count;
-        let item_size = 68;
-        self.mgr.read_map_bytes(offset as u64, item_size * count as usize).unwrap()
+    // always 68 byte pc vertices, xbox ones are decompressed
+    pub fn get_model_part_vertices(&mut self, part: &GbxModelPart) -> Result<Vec<u8>, String> {
+        self.mgr.read_model_vertices(part)
+            .map_err(|err| format!("failed to read {} model vertices at 0x{:x}: {}", part.vert_count, self.mgr.get_model_vertex_data_offset(part), err))
     }
 
     pub fn get_bsp_indices(&self, bsp: &BSP) -> Vec<u16> {
         let mut indices = Vec::new();
-        for tri in bsp.surfaces.items.as_ref().unwrap() {
+        for tri in bsp.surfaces.items.as_deref().unwrap_or_default() {
             indices.extend_from_slice(&[tri.v0, tri.v1, tri.v2]);
         }
         indices
     }
 
     pub fn resolve_model_dependency(&mut self, dependency: &TagDependency) -> Option<GbxModel> {
-        let hdr = self.mgr.resolve_dependency(dependency)?;
-        match self.mgr.read_tag(&hdr).unwrap().data {
-            TagData::GbxModel(model) => Some(model),
-            _ => unreachable!(),
-        }
+        self.resolve_tag_data(dependency)
     }
 
     pub fn resolve_bitmap_dependency(&mut self, dependency: &TagDependency) -> Option<Bitmap> {
-        let hdr = self.mgr.resolve_dependency(dependency)?;
-        match self.mgr.read_tag(&hdr).unwrap().data {
-            TagData::Bitmap(bitmap) => Some(bitmap),
-            _ => unreachable!(),
-        }
+        self.resolve_tag_data(dependency)
     }
 
-    pub fn get_and_convert_bitmap_data(&mut self, bitmap: &Bitmap, submap: usize) -> Vec<u8> {
-        let bitmap_data = &bitmap.data.items.as_ref().unwrap()[submap];
+    pub fn get_and_convert_bitmap_data(&mut self, bitmap: &Bitmap, submap: usize) -> Result<Vec<u8>, String> {
+        let Some(bitmap_data) = bitmap.data.items.as_ref().and_then(|data| data.get(submap)) else {
+            return Err(format!("bitmap has no submap {}", submap));
+        };
         get_and_convert_bitmap_data(&mut self.mgr.reader.data, bitmap_data)
+            .map_err(|err| format!("failed to read bitmap data at 0x{:x}: {}", bitmap_data.pixel_data_offset, err))
     }
 
-    pub fn get_material_vertex_data(&mut self, material: &BSPMaterial, bsp: &BSP) -> Vec<u8> {
-        let offset = bsp.header.as_ref().unwrap().rendered_vertices_offset + material.rendered_vertices.base_pointer;
-        let count = material.rendered_vertices.count;
-        let item_size = 56; // position + normal + binormal + tangent + uv
-        self.mgr.read_map_bytes(offset as u64, count as usize * item_size).unwrap()
+    // like model vertices, these are always in the pc layout
+    pub fn get_material_vertex_data(&mut self, material: &BSPMaterial, bsp: &BSP) -> Result<Vec<u8>, String> {
+        let offset = get_bsp_header(bsp)?.rendered_vertices_offset + material.rendered_vertices.base_pointer;
+        let count = material.rendered_vertices.count as usize;
+        let compressed = self.mgr.header.version.has_compressed_vertices();
+        let item_size = if compressed { COMPRESSED_BSP_VERTEX_SIZE } else { BSP_VERTEX_SIZE };
+        let data = self.mgr.read_map_bytes(offset as u64, count * item_size)
+            .map_err(|err| format!("failed to read {} bsp vertices at 0x{:x}: {}", count, offset, err))?;
+        Ok(if compressed { decompress_bsp_vertices(&data) } else { data })
     }
 
-    pub fn get_material_lightmap_data(&mut self, material: &BSPMaterial, bsp: &BSP) -> Vec<u8> {
-        let offset = bsp.header.as_ref().unwrap().rendered_vertices_offset + material.lightmap_vertices.base_pointer;
-        let count = material.rendered_vertices.count;
-        let item_size = 20; // normal + uv
-        self.mgr.read_map_bytes(offset as u64, count as usize * item_size).unwrap()
+    pub fn get_material_lightmap_data(&mut self, material: &BSPMaterial, bsp: &BSP) -> Result<Vec<u8>, String> {
+        let offset = get_bsp_header(bsp)?.rendered_vertices_offset + material.lightmap_vertices.base_pointer;
+        let count = material.rendered_vertices.count as usize;
+        let compressed = self.mgr.header.version.has_compressed_vertices();
+        let item_size = if compressed { COMPRESSED_LIGHTMAP_VERTEX_SIZE } else { LIGHTMAP_VERTEX_SIZE };
+        let data = self.mgr.read_map_bytes(offset as u64, count * item_size)
+            .map_err(|err| format!("failed to read {} lightmap vertices at 0x{:x}: {}", count, offset, err))?;
+        Ok(if compressed { decompress_lightmap_vertices(&data) } else { data })
     }
 }
+
+impl HaloSceneManager {
+    fn get_tag_header(&self, tag_id: u32) -> Result<TagHeader, String> {
+        self.mgr.get_tag_header(tag_id).cloned()
+            .ok_or_else(|| format!("no tag with id 0x{:x}", tag_id))
+    }
+
+    fn get_scenario_tag(&mut self) -> Result<Rc<Tag>, String> {
+        self.mgr.get_scenario()
+            .map_err(|err| format!("failed to read scenario: {}", err))
+    }
+
+    fn with_scenario<T>(&mut self, f: impl FnOnce(&mut Self, &Scenario) -> T) -> Result<T, String> {
+        let scenario_tag = self.get_scenario_tag()?;
+        let scenario: &Scenario = (&scenario_tag.data).try_into()
+            .map_err(|err| format!("{}: {}", scenario_tag.header, err))?;
+        Ok(f(self, scenario))
+    }
+
+    fn get_palette<E, T: Clone>(&mut self, palette: &Block<E>, get_dependency: impl Fn(&E) -> &TagDependency, to_js: impl Fn(T) -> JsValue) -> Array
+        where for<'a> &'a T: TryFrom<&'a TagData, Error = String>
+    {
+        let result = Array::new();
+        for palette_entry in palette.items.as_deref().unwrap_or_default() {
+            match self.resolve_tag_data(get_dependency(palette_entry)) {
+                Some(data) => result.push(&to_js(data)),
+                None => result.push(&JsValue::NULL),
+            };
+        }
+        result
+    }
+
+    fn resolve_attachments<T: Clone>(&mut self, object: &GameObject, tag_class: TagClass) -> Vec<T>
+        where for<'a> &'a T: TryFrom<&'a TagData, Error = String>
+    {
+        object.attachments.items.as_deref().unwrap_or_default().iter()
+            .filter(|attachment| attachment.attachment_type.tag_class == tag_class)
+            .filter_map(|attachment| self.resolve_tag_data(&attachment.attachment_type))
+            .collect()
+    }
+
+    // like resolve_tag_data, but for any kind of object
+    fn resolve_object(&mut self, dependency: &TagDependency) -> Option<GameObject> {
+        if dependency.tag_class == TagClass::Null {
+            return None;
+        }
+        let Some(tag_header) = self.mgr.resolve_dependency(dependency) else {
+            self.errors.push(format!("couldn't resolve dependency {:?}", dependency));
+            return None;
+        };
+        match self.mgr.read_object(&tag_header) {
+            Ok(object) => Some(object),
+            Err(err) => {
+                self.errors.push(format!("failed to read {}: {}", tag_header, err));
+                None
+            },
+        }
+    }
+
+    // a missing or mistyped dependency resolves to None rather than panicking,
+    // with the reason kept for take_errors. null dependencies aren't errors
+    fn resolve_tag_data<T: Clone>(&mut self, dependency: &TagDependency) -> Option<T>
+        where for<'a> &'a T: TryFrom<&'a TagData, Error = String>
+    {
+        if dependency.tag_class == TagClass::Null {
+            return None;
+        }
+        match self.mgr.get_dependency_data(dependency) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                self.errors.push(err.to_string());
+                None
+            },
+        }
+    }
+}
+
+fn get_bsp_header(bsp: &BSP) -> Result<&BSPHeader, String> {
+    bsp.header.as_ref().ok_or_else(|| "bsp wasn't read through its scenario, so it has no header".to_string())
+}
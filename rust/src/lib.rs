@@ -11,3 +11,4 @@ pub mod wow;
 pub mod geometry;
 pub mod crazytaxi;
 pub mod spline;
+pub mod camera_path;
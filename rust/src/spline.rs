@@ -1,3 +1,5 @@
+use std::{error::Error, fmt::Display};
+
 use nalgebra_glm::{vec3, vec4, Vec3, Vec4};
 
 fn get_coeff_bezier(p0: f32, p1: f32, p2: f32, p3: f32) -> Vec4 {
@@ -9,6 +11,45 @@ fn get_coeff_bezier(p0: f32, p1: f32, p2: f32, p3: f32) -> Vec4 {
     )
 }
 
+// coefficients for a cubic hermite segment from p0 to p1 with tangents m0 and m1
+fn get_coeff_hermite(p0: f32, m0: f32, p1: f32, m1: f32) -> Vec4 {
+    vec4(
+        (p0 *  2.0) + (m0 *  1.0) + (p1 * -2.0) + (m1 *  1.0),
+        (p0 * -3.0) + (m0 * -2.0) + (p1 *  3.0) + (m1 * -1.0),
+        (p0 *  0.0) + (m0 *  1.0) + (p1 *  0.0) + (m1 *  0.0),
+        (p0 *  1.0) + (m0 *  0.0) + (p1 *  0.0) + (m1 *  0.0),
+    )
+}
+
+fn get_coeff_bspline(p0: f32, p1: f32, p2: f32, p3: f32) -> Vec4 {
+    vec4(
+        ((p0 * -1.0) + (p1 *  3.0) + (p2 * -3.0) + (p3 *  1.0)) / 6.0,
+        ((p0 *  3.0) + (p1 * -6.0) + (p2 *  3.0) + (p3 *  0.0)) / 6.0,
+        ((p0 * -3.0) + (p1 *  0.0) + (p2 *  3.0) + (p3 *  0.0)) / 6.0,
+        ((p0 *  1.0) + (p1 *  4.0) + (p2 *  1.0) + (p3 *  0.0)) / 6.0,
+    )
+}
+
+pub fn get_point_hermite(p0: f32, m0: f32, p1: f32, m1: f32, t: f32) -> f32 {
+    let v = get_coeff_hermite(p0, m0, p1, m1);
+    get_point_cubic(v, t)
+}
+
+pub fn get_derivative_hermite(p0: f32, m0: f32, p1: f32, m1: f32, t: f32) -> f32 {
+    let v = get_coeff_hermite(p0, m0, p1, m1);
+    get_derivative_cubic(v, t)
+}
+
+pub fn get_point_bspline(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let v = get_coeff_bspline(p0, p1, p2, p3);
+    get_point_cubic(v, t)
+}
+
+pub fn get_derivative_bspline(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let v = get_coeff_bspline(p0, p1, p2, p3);
+    get_derivative_cubic(v, t)
+}
+
 pub fn get_point_bezier(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
     let v = get_coeff_bezier(p0, p1, p2, p3);
     return get_point_cubic(v, t);
@@ -27,84 +68,274 @@ pub fn get_derivative_cubic(cf: Vec4, t: f32) -> f32 {
     return (3.0 * cf[0] * t + 2.0 * cf[1]) * t + cf[2];
 }
 
-#[derive(Debug, Clone)]
-pub struct BezierSpline {
-    pub points: Vec<Vec3>,
-    pub total_length: Option<f32>,
-    pub segment_lengths: Option<Vec<f32>>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplineError {
+    NotEnoughPoints { required: usize, found: usize },
+    MismatchedTangents { points: usize, tangents: usize },
 }
 
-impl BezierSpline {
-    pub fn new(points: Vec<Vec3>) -> Self {
-        let mut spline = BezierSpline {
-            points,
-            total_length: None,
-            segment_lengths: None,
-        };
-        spline.calculate_segment_lengths();
-        spline
+impl Display for SplineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplineError::NotEnoughPoints { required, found } =>
+                write!(f, "spline needs at least {} points, found {}", required, found),
+            SplineError::MismatchedTangents { points, tangents } =>
+                write!(f, "spline has {} points but {} tangents", points, tangents),
+        }
     }
+}
 
-    fn calculate_segment_lengths(&mut self) {
-        let mut segment_lengths = Vec::new();
-        let mut total_length = 0.0;
-        let num_segments = (self.points.len() - 1) / 3;
-        let iterations_per_segment = 20;
-        let dt = 1.0 / iterations_per_segment as f32;
-        let mut last_pos = self.evaluate_segment(0, 0.0);
-        let mut curr_pos;
+impl Error for SplineError {}
 
-        for segment in 0..num_segments {
-            let mut length = 0.0;
-
-            let mut t = dt;
-            for _ in 0..iterations_per_segment {
-                curr_pos = self.evaluate_segment(segment, t);
-                length += curr_pos.metric_distance(&last_pos);
-                last_pos = curr_pos;
-                t += dt;
-            }
-            segment_lengths.push(length);
-            total_length += length;
-        }
+fn check_point_count(points: &[Vec3], required: usize) -> Result<(), SplineError> {
+    if points.len() < required {
+        return Err(SplineError::NotEnoughPoints { required, found: points.len() });
+    }
+    Ok(())
+}
 
-        self.segment_lengths = Some(segment_lengths);
-        self.total_length = Some(total_length);
+// A piecewise curve made of segments, each evaluated over a local t in [0, 1]. Global t values
+// passed to evaluate() are split evenly between segments, while the sample_parametric()
+// family uses arc length so that equal steps in t travel equal distances along the curve.
+// Out-of-range t values are clamped rather than treated as errors.
+pub trait Spline {
+    fn num_segments(&self) -> usize;
+    fn evaluate_segment(&self, segment: usize, t: f32) -> Vec3;
+    fn evaluate_segment_derivative(&self, segment: usize, t: f32) -> Vec3;
+    fn arc_length_table(&self) -> &ArcLengthTable;
+
+    fn segment_lengths(&self) -> &[f32] {
+        self.arc_length_table().segment_lengths()
     }
 
-    pub fn calculate_paramateric_spline(&self, t: f32) -> Vec3 {
-        assert!(t >= 0.0 && t <= 1.0);
+    fn arc_length(&self) -> f32 {
+        self.arc_length_table().total_length()
+    }
+
+    fn evaluate(&self, t: f32) -> Vec3 {
+        let (segment, segment_t) = self.find_segment(t);
+        self.evaluate_segment(segment, segment_t)
+    }
+
+    // derivative with respect to the global t
+    fn evaluate_derivative(&self, t: f32) -> Vec3 {
+        let (segment, segment_t) = self.find_segment(t);
+        self.evaluate_segment_derivative(segment, segment_t) * self.num_segments() as f32
+    }
+
+    fn sample_parametric(&self, t: f32) -> Vec3 {
         let (segment, segment_t) = self.find_parametric_segment(t);
         self.evaluate_segment(segment, segment_t)
     }
 
-    pub fn calculate_parametric_spline_derivative(&self, t: f32) -> Vec3 {
-        assert!(t >= 0.0 && t <= 1.0);
+    // derivative with respect to the arc length fraction t. t moves along the
+    // curve at a constant rate, so this is the tangent direction scaled by the
+    // total arc length
+    fn sample_parametric_derivative(&self, t: f32) -> Vec3 {
         let (segment, segment_t) = self.find_parametric_segment(t);
-        self.evaluate_derivative(segment, segment_t)
+        let derivative = self.evaluate_segment_derivative(segment, segment_t);
+        if derivative.magnitude_squared() > 0.0 {
+            derivative.normalize() * self.arc_length()
+        } else {
+            derivative
+        }
     }
 
-    fn segment_len(&self, i: usize) -> f32 {
-        self.segment_lengths.as_ref().expect("spline uninitialized")[i]
+    // position and orientation at an arc length fraction t, see SplineFrame::new()
+    fn sample_parametric_frame(&self, t: f32, up: &Vec3) -> SplineFrame {
+        let (segment, segment_t) = self.find_parametric_segment(t);
+        let position = self.evaluate_segment(segment, segment_t);
+        let derivative = self.evaluate_segment_derivative(segment, segment_t);
+        SplineFrame::new(position, &derivative, up)
     }
 
+    // maps a global t to a segment and the t within it
+    fn find_segment(&self, t: f32) -> (usize, f32) {
+        let num_segments = self.num_segments();
+        split_global_param(t.clamp(0.0, 1.0) * num_segments as f32, num_segments)
+    }
+
+    // like find_segment(), but treats t as a fraction of the spline's total arc length
     fn find_parametric_segment(&self, t: f32) -> (usize, f32) {
-        let target_length = t * self.total_length.expect("spline uninitialized");
-        let mut length = 0.0;
-        let num_segments = (self.points.len() - 1) / 3;
+        let table = self.arc_length_table();
+        table.find_segment_for_distance(t * table.total_length())
+    }
+}
+
+// splits a parameter in [0, num_segments] into a segment index and the t within it
+fn split_global_param(u: f32, num_segments: usize) -> (usize, f32) {
+    let segment = (u.max(0.0) as usize).min(num_segments.saturating_sub(1));
+    (segment, (u - segment as f32).clamp(0.0, 1.0))
+}
+
+// 5-point Gauss-Legendre abscissae and weights over [-1, 1]
+const GAUSS_LEGENDRE_POINTS: [(f32, f32); 5] = [
+    (0.0, 0.568_888_9),
+    (-0.538_469_3, 0.478_628_67),
+    (0.538_469_3, 0.478_628_67),
+    (-0.906_179_8, 0.236_926_88),
+    (0.906_179_8, 0.236_926_88),
+];
+const ARC_LENGTH_TOLERANCE: f32 = 1e-5;
+const ARC_LENGTH_MAX_DEPTH: u32 = 8;
+const ARC_LENGTH_SAMPLES_PER_SEGMENT: usize = 16;
+
+fn gauss_legendre_length<S: Spline + ?Sized>(spline: &S, segment: usize, t0: f32, t1: f32) -> f32 {
+    let half_width = (t1 - t0) * 0.5;
+    let midpoint = (t1 + t0) * 0.5;
+    let mut sum = 0.0;
+    for (x, weight) in GAUSS_LEGENDRE_POINTS {
+        let speed = spline.evaluate_segment_derivative(segment, midpoint + half_width * x).magnitude();
+        sum += weight * speed;
+    }
+    sum * half_width
+}
+
+// integrates the spline's speed between t0 and t1, subdividing until both halves agree with
+// the whole
+fn adaptive_length<S: Spline + ?Sized>(spline: &S, segment: usize, t0: f32, t1: f32, whole: f32, depth: u32) -> f32 {
+    let mid = (t0 + t1) * 0.5;
+    let left = gauss_legendre_length(spline, segment, t0, mid);
+    let right = gauss_legendre_length(spline, segment, mid, t1);
+    let halves = left + right;
+    if depth == 0 || (halves - whole).abs() <= ARC_LENGTH_TOLERANCE * halves.max(ARC_LENGTH_TOLERANCE) {
+        return halves;
+    }
+    adaptive_length(spline, segment, t0, mid, left, depth - 1)
+        + adaptive_length(spline, segment, mid, t1, right, depth - 1)
+}
+
+pub fn calculate_length<S: Spline + ?Sized>(spline: &S, segment: usize, t0: f32, t1: f32) -> f32 {
+    let whole = gauss_legendre_length(spline, segment, t0, t1);
+    adaptive_length(spline, segment, t0, t1, whole, ARC_LENGTH_MAX_DEPTH)
+}
+
+// Cumulative arc length sampled along a spline, used to map distances back to segment t
+// values for constant-speed sampling.
+#[derive(Debug, Clone, Default)]
+pub struct ArcLengthTable {
+    params: Vec<f32>, // segment index + segment t
+    distances: Vec<f32>,
+    segment_lengths: Vec<f32>,
+}
+
+impl ArcLengthTable {
+    pub fn new<S: Spline + ?Sized>(spline: &S) -> Self {
+        let num_segments = spline.num_segments();
+        let samples = ARC_LENGTH_SAMPLES_PER_SEGMENT;
+        let mut table = ArcLengthTable {
+            params: Vec::with_capacity(num_segments * samples + 1),
+            distances: Vec::with_capacity(num_segments * samples + 1),
+            segment_lengths: Vec::with_capacity(num_segments),
+        };
+        table.params.push(0.0);
+        table.distances.push(0.0);
+        let mut distance = 0.0;
         for segment in 0..num_segments {
-            let segment_length = self.segment_len(segment);
-            if length + segment_length < target_length {
-                length += segment_length;
-            } else {
-                let segment_t = (target_length - length) / segment_length;
-                return (segment, segment_t);
+            let mut segment_length = 0.0;
+            for i in 0..samples {
+                let t0 = i as f32 / samples as f32;
+                let t1 = (i + 1) as f32 / samples as f32;
+                segment_length += calculate_length(spline, segment, t0, t1);
+                table.params.push(segment as f32 + t1);
+                table.distances.push(distance + segment_length);
             }
+            distance += segment_length;
+            table.segment_lengths.push(segment_length);
         }
-        panic!("failed to find spline segment for parametric t={}", t);
+        table
+    }
+
+    pub fn total_length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
     }
 
-    fn evaluate_derivative(&self, segment: usize, t: f32) -> Vec3 {
+    pub fn segment_lengths(&self) -> &[f32] {
+        &self.segment_lengths
+    }
+
+    pub fn find_segment_for_distance(&self, distance: f32) -> (usize, f32) {
+        let num_segments = self.segment_lengths.len();
+        let total_length = self.total_length();
+        if num_segments == 0 || total_length <= 0.0 {
+            return (0, 0.0);
+        }
+        let distance = distance.clamp(0.0, total_length);
+        // index of the first sample at or past the target distance
+        let i = self.distances.partition_point(|&d| d < distance).clamp(1, self.distances.len() - 1);
+        let (d0, d1) = (self.distances[i - 1], self.distances[i]);
+        let (u0, u1) = (self.params[i - 1], self.params[i]);
+        let u = if d1 > d0 {
+            u0 + (u1 - u0) * (distance - d0) / (d1 - d0)
+        } else {
+            u0
+        };
+        split_global_param(u, num_segments)
+    }
+}
+
+// An orthonormal frame along a spline, suitable for orienting a camera. The normal is kept as
+// close to the given up vector as possible, so unlike a Frenet frame it doesn't flip around on
+// straight sections or at inflection points.
+#[derive(Debug, Clone, Copy)]
+pub struct SplineFrame {
+    pub position: Vec3,
+    pub tangent: Vec3,
+    pub normal: Vec3,
+    pub binormal: Vec3,
+}
+
+impl SplineFrame {
+    pub fn new(position: Vec3, derivative: &Vec3, up: &Vec3) -> Self {
+        let tangent = if derivative.magnitude_squared() > 0.0 {
+            derivative.normalize()
+        } else {
+            vec3(0.0, 0.0, -1.0)
+        };
+        let mut binormal = tangent.cross(up);
+        if binormal.magnitude_squared() < 1e-8 {
+            // moving along the up vector, so any perpendicular axis will do
+            let fallback = if tangent.x.abs() < 0.9 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 1.0, 0.0) };
+            binormal = tangent.cross(&fallback);
+        }
+        binormal.normalize_mut();
+        let normal = binormal.cross(&tangent);
+        SplineFrame { position, tangent, normal, binormal }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BezierSpline {
+    pub points: Vec<Vec3>,
+    arc_length_table: ArcLengthTable,
+}
+
+impl BezierSpline {
+    pub fn new(points: Vec<Vec3>) -> Result<Self, SplineError> {
+        check_point_count(&points, 4)?;
+        let mut spline = BezierSpline {
+            points,
+            arc_length_table: ArcLengthTable::default(),
+        };
+        spline.arc_length_table = ArcLengthTable::new(&spline);
+        Ok(spline)
+    }
+
+    pub fn calculate_paramateric_spline(&self, t: f32) -> Vec3 {
+        self.sample_parametric(t)
+    }
+
+    pub fn calculate_parametric_spline_derivative(&self, t: f32) -> Vec3 {
+        self.sample_parametric_derivative(t)
+    }
+}
+
+impl Spline for BezierSpline {
+    fn num_segments(&self) -> usize {
+        (self.points.len() - 1) / 3
+    }
+
+    fn evaluate_segment_derivative(&self, segment: usize, t: f32) -> Vec3 {
         let p0 = self.points[segment * 3 + 0];
         let p1 = self.points[segment * 3 + 1];
         let p2 = self.points[segment * 3 + 2];
@@ -127,4 +358,349 @@ impl BezierSpline {
             get_point_bezier(p0[2], p1[2], p2[2], p3[2], t),
         )
     }
+
+    fn arc_length_table(&self) -> &ArcLengthTable {
+        &self.arc_length_table
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CatmullRomType {
+    Uniform,
+    Centripetal,
+    Chordal,
+}
+
+impl CatmullRomType {
+    fn alpha(&self) -> f32 {
+        match self {
+            CatmullRomType::Uniform => 0.0,
+            CatmullRomType::Centripetal => 0.5,
+            CatmullRomType::Chordal => 1.0,
+        }
+    }
+}
+
+// Interpolates through every point. Each segment is converted into an equivalent hermite
+// segment, which keeps evaluation and derivatives cheap for the non-uniform variants.
+#[derive(Debug, Clone)]
+pub struct CatmullRomSpline {
+    pub points: Vec<Vec3>,
+    pub catmull_rom_type: CatmullRomType,
+    tangents: Vec<(Vec3, Vec3)>,
+    arc_length_table: ArcLengthTable,
+}
+
+impl CatmullRomSpline {
+    pub fn new(points: Vec<Vec3>, catmull_rom_type: CatmullRomType) -> Result<Self, SplineError> {
+        check_point_count(&points, 2)?;
+        let mut spline = CatmullRomSpline {
+            points,
+            catmull_rom_type,
+            tangents: Vec::new(),
+            arc_length_table: ArcLengthTable::default(),
+        };
+        spline.tangents = (0..spline.num_segments())
+            .map(|segment| spline.calculate_tangents(segment))
+            .collect();
+        spline.arc_length_table = ArcLengthTable::new(&spline);
+        Ok(spline)
+    }
+
+    fn calculate_tangents(&self, segment: usize) -> (Vec3, Vec3) {
+        let p1 = self.points[segment];
+        let p2 = self.points[segment + 1];
+        // the curve's endpoints don't have neighbors, so mirror the adjacent point instead
+        let p0 = if segment > 0 { self.points[segment - 1] } else { p1 * 2.0 - p2 };
+        let p3 = if segment + 2 < self.points.len() { self.points[segment + 2] } else { p2 * 2.0 - p1 };
+
+        let alpha = self.catmull_rom_type.alpha();
+        let mut dt1 = p1.metric_distance(&p2).powf(alpha);
+        if dt1 < 1e-4 {
+            dt1 = 1.0;
+        }
+        let mut dt0 = p0.metric_distance(&p1).powf(alpha);
+        if dt0 < 1e-4 {
+            dt0 = dt1;
+        }
+        let mut dt2 = p2.metric_distance(&p3).powf(alpha);
+        if dt2 < 1e-4 {
+            dt2 = dt1;
+        }
+
+        let m1 = (p1 - p0) / dt0 - (p2 - p0) / (dt0 + dt1) + (p2 - p1) / dt1;
+        let m2 = (p2 - p1) / dt1 - (p3 - p1) / (dt1 + dt2) + (p3 - p2) / dt2;
+        (m1 * dt1, m2 * dt1)
+    }
+}
+
+impl Spline for CatmullRomSpline {
+    fn num_segments(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    fn evaluate_segment(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let (m0, m1) = self.tangents[segment];
+        vec3(
+            get_point_hermite(p0[0], m0[0], p1[0], m1[0], t),
+            get_point_hermite(p0[1], m0[1], p1[1], m1[1], t),
+            get_point_hermite(p0[2], m0[2], p1[2], m1[2], t),
+        )
+    }
+
+    fn evaluate_segment_derivative(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let (m0, m1) = self.tangents[segment];
+        vec3(
+            get_derivative_hermite(p0[0], m0[0], p1[0], m1[0], t),
+            get_derivative_hermite(p0[1], m0[1], p1[1], m1[1], t),
+            get_derivative_hermite(p0[2], m0[2], p1[2], m1[2], t),
+        )
+    }
+
+    fn arc_length_table(&self) -> &ArcLengthTable {
+        &self.arc_length_table
+    }
+}
+
+// Interpolates through every point, using an explicit tangent for each one (e.g. from hermite
+// animation keys)
+#[derive(Debug, Clone)]
+pub struct HermiteSpline {
+    pub points: Vec<Vec3>,
+    pub tangents: Vec<Vec3>,
+    arc_length_table: ArcLengthTable,
+}
+
+impl HermiteSpline {
+    pub fn new(points: Vec<Vec3>, tangents: Vec<Vec3>) -> Result<Self, SplineError> {
+        check_point_count(&points, 2)?;
+        if points.len() != tangents.len() {
+            return Err(SplineError::MismatchedTangents { points: points.len(), tangents: tangents.len() });
+        }
+        let mut spline = HermiteSpline {
+            points,
+            tangents,
+            arc_length_table: ArcLengthTable::default(),
+        };
+        spline.arc_length_table = ArcLengthTable::new(&spline);
+        Ok(spline)
+    }
+}
+
+impl Spline for HermiteSpline {
+    fn num_segments(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    fn evaluate_segment(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let m0 = self.tangents[segment];
+        let m1 = self.tangents[segment + 1];
+        vec3(
+            get_point_hermite(p0[0], m0[0], p1[0], m1[0], t),
+            get_point_hermite(p0[1], m0[1], p1[1], m1[1], t),
+            get_point_hermite(p0[2], m0[2], p1[2], m1[2], t),
+        )
+    }
+
+    fn evaluate_segment_derivative(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let m0 = self.tangents[segment];
+        let m1 = self.tangents[segment + 1];
+        vec3(
+            get_derivative_hermite(p0[0], m0[0], p1[0], m1[0], t),
+            get_derivative_hermite(p0[1], m0[1], p1[1], m1[1], t),
+            get_derivative_hermite(p0[2], m0[2], p1[2], m1[2], t),
+        )
+    }
+
+    fn arc_length_table(&self) -> &ArcLengthTable {
+        &self.arc_length_table
+    }
+}
+
+// Uniform cubic B-spline. Smoother than the interpolating splines, but only approximates its
+// control points.
+#[derive(Debug, Clone)]
+pub struct UniformBSpline {
+    pub points: Vec<Vec3>,
+    arc_length_table: ArcLengthTable,
+}
+
+impl UniformBSpline {
+    pub fn new(points: Vec<Vec3>) -> Result<Self, SplineError> {
+        check_point_count(&points, 4)?;
+        let mut spline = UniformBSpline {
+            points,
+            arc_length_table: ArcLengthTable::default(),
+        };
+        spline.arc_length_table = ArcLengthTable::new(&spline);
+        Ok(spline)
+    }
+}
+
+impl Spline for UniformBSpline {
+    fn num_segments(&self) -> usize {
+        self.points.len() - 3
+    }
+
+    fn evaluate_segment(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let p2 = self.points[segment + 2];
+        let p3 = self.points[segment + 3];
+        vec3(
+            get_point_bspline(p0[0], p1[0], p2[0], p3[0], t),
+            get_point_bspline(p0[1], p1[1], p2[1], p3[1], t),
+            get_point_bspline(p0[2], p1[2], p2[2], p3[2], t),
+        )
+    }
+
+    fn evaluate_segment_derivative(&self, segment: usize, t: f32) -> Vec3 {
+        let p0 = self.points[segment];
+        let p1 = self.points[segment + 1];
+        let p2 = self.points[segment + 2];
+        let p3 = self.points[segment + 3];
+        vec3(
+            get_derivative_bspline(p0[0], p1[0], p2[0], p3[0], t),
+            get_derivative_bspline(p0[1], p1[1], p2[1], p3[1], t),
+            get_derivative_bspline(p0[2], p1[2], p2[2], p3[2], t),
+        )
+    }
+
+    fn arc_length_table(&self) -> &ArcLengthTable {
+        &self.arc_length_table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        assert!(a.metric_distance(&b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn zigzag() -> Vec<Vec3> {
+        vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 2.0, 0.0),
+            vec3(5.0, 2.0, 1.0),
+            vec3(6.0, 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn test_catmull_rom_interpolates() {
+        for ty in [CatmullRomType::Uniform, CatmullRomType::Centripetal, CatmullRomType::Chordal] {
+            let spline = CatmullRomSpline::new(zigzag(), ty).unwrap();
+            assert_eq!(spline.num_segments(), 3);
+            for (i, p) in zigzag().iter().enumerate() {
+                assert_vec3_eq(spline.evaluate(i as f32 / 3.0), *p);
+            }
+            assert_vec3_eq(spline.sample_parametric(0.0), zigzag()[0]);
+            assert_vec3_eq(spline.sample_parametric(1.0), zigzag()[3]);
+        }
+    }
+
+    #[test]
+    fn test_uniform_catmull_rom_tangents() {
+        let spline = CatmullRomSpline::new(zigzag(), CatmullRomType::Uniform).unwrap();
+        let points = zigzag();
+        assert_vec3_eq(spline.evaluate_segment_derivative(1, 0.0), (points[2] - points[0]) * 0.5);
+    }
+
+    #[test]
+    fn test_hermite() {
+        let spline = HermiteSpline::new(
+            vec![vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)],
+            vec![vec3(1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)],
+        ).unwrap();
+        // a hermite segment whose tangents match its chord is a straight line
+        assert_vec3_eq(spline.evaluate(0.25), vec3(0.25, 0.0, 0.0));
+        assert_vec3_eq(spline.evaluate_derivative(0.5), vec3(1.0, 0.0, 0.0));
+        assert!((spline.arc_length() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_bspline() {
+        // a b-spline over collinear, evenly spaced points stays on the line
+        let points: Vec<Vec3> = (0..6).map(|i| vec3(i as f32, 0.0, 0.0)).collect();
+        let spline = UniformBSpline::new(points).unwrap();
+        assert_eq!(spline.num_segments(), 3);
+        assert_vec3_eq(spline.evaluate(0.0), vec3(1.0, 0.0, 0.0));
+        assert_vec3_eq(spline.evaluate(1.0), vec3(4.0, 0.0, 0.0));
+        assert_vec3_eq(spline.sample_parametric(0.5), vec3(2.5, 0.0, 0.0));
+        assert_vec3_eq(spline.sample_parametric_derivative(0.5), vec3(3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_bezier_trait() {
+        let spline = BezierSpline::new(zigzag()).unwrap();
+        assert_eq!(spline.num_segments(), 1);
+        assert_vec3_eq(spline.evaluate(0.0), zigzag()[0]);
+        assert_vec3_eq(spline.evaluate(1.0), zigzag()[3]);
+        assert_vec3_eq(spline.sample_parametric(1.0), zigzag()[3]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            BezierSpline::new(vec![vec3(0.0, 0.0, 0.0)]).unwrap_err(),
+            SplineError::NotEnoughPoints { required: 4, found: 1 },
+        );
+        assert!(HermiteSpline::new(zigzag(), vec![vec3(0.0, 0.0, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn test_bezier_arc_length() {
+        // standard cubic approximation of a quarter circle of radius 1
+        let k = 0.552_284_8;
+        let spline = BezierSpline::new(vec![
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, k, 0.0),
+            vec3(k, 1.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+        ]).unwrap();
+        assert!((spline.arc_length() - std::f32::consts::FRAC_PI_2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_constant_speed_sampling() {
+        // control points bunched up at the start, so the curve's speed varies a lot with t
+        let spline = BezierSpline::new(vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.1, 0.0, 0.0),
+            vec3(0.2, 0.0, 0.0),
+            vec3(3.0, 0.0, 0.0),
+        ]).unwrap();
+        assert!((spline.arc_length() - 3.0).abs() < 1e-4);
+        for i in 0..=10 {
+            let t = i as f32 / 10.0;
+            assert!((spline.sample_parametric(t).x - 3.0 * t).abs() < 1e-2);
+        }
+        // out of range values are clamped
+        assert_vec3_eq(spline.calculate_paramateric_spline(1.5), vec3(3.0, 0.0, 0.0));
+        assert_vec3_eq(spline.calculate_paramateric_spline(-1.0), vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_frame() {
+        let spline = CatmullRomSpline::new(zigzag(), CatmullRomType::Centripetal).unwrap();
+        let up = vec3(0.0, 0.0, 1.0);
+        for i in 0..=10 {
+            let frame = spline.sample_parametric_frame(i as f32 / 10.0, &up);
+            assert!((frame.tangent.magnitude() - 1.0).abs() < 1e-4);
+            assert!((frame.normal.magnitude() - 1.0).abs() < 1e-4);
+            assert!(frame.tangent.dot(&frame.normal).abs() < 1e-4);
+            assert!(frame.normal.dot(&up) > 0.0);
+            assert_vec3_eq(frame.tangent.cross(&frame.normal), frame.binormal);
+        }
+    }
 }
@@ -206,7 +206,7 @@ impl Emitter {
         if !spline_points.is_empty() {
             // convert WowVec3 -> nalgebra::Vec3
             let points = spline_points.drain(..).map(|p| p.into()).collect();
-            spline = Some(BezierSpline::new(points));
+            spline = BezierSpline::new(points).ok();
         }
         let tex_col_bits = (m2_emitter.texture_dimensions_cols as f32).log2().ceil() as u32;
         let tex_col_mask = (1 << tex_col_bits) - 1;
@@ -219,18 +219,24 @@ impl Wmo {
                 continue;
             }
 
-            if portal.in_frustum(frustum) || portal.aabb_contains_point(eye) {
-                let portal_frustum = portal.clip_frustum(eye, frustum);
-                let other_group = self.get_group(other_group_id);
-                // if this portal causes us to look outside, save its frustum for culling the outdoors
-                if !group.flags.exterior && other_group.flags.exterior {
-                    exterior_frustums.push(portal_frustum.clone());
-                }
-                // create a new visited_set just for this uniquely clipped frustum
-                let mut visited_local = visited_set.clone();
-                visited_local.insert(group_id);
-                self.traverse_portals(eye, &portal_frustum, other_group_id, visible_set, &mut visited_local, exterior_frustums);
+            let portal_frustum = if portal.aabb_contains_point(eye) {
+                // we're too close to the portal to get a stable narrowed frustum from it
+                portal.clip_frustum(eye, frustum)
+            } else if let Some(narrowed) = portal.narrow_frustum(eye, frustum) {
+                narrowed
+            } else {
+                continue;
+            };
+
+            let other_group = self.get_group(other_group_id);
+            // if this portal causes us to look outside, save its frustum for culling the outdoors
+            if !group.flags.exterior && other_group.flags.exterior {
+                exterior_frustums.push(portal_frustum.clone());
             }
+            // create a new visited_set just for this uniquely clipped frustum
+            let mut visited_local = visited_set.clone();
+            visited_local.insert(group_id);
+            self.traverse_portals(eye, &portal_frustum, other_group_id, visible_set, &mut visited_local, exterior_frustums);
         }
     }
 
@@ -536,6 +542,14 @@ impl PortalData {
         frustum.contains_aabb(&self.aabb)
     }
 
+    // shrinks the frustum down to the part of the portal that's visible through it
+    fn narrow_frustum(&self, eye: &Vec3, frustum: &ConvexHull) -> Option<ConvexHull> {
+        if !self.in_frustum(frustum) {
+            return None;
+        }
+        frustum.narrow_to_portal(eye, &self.vertices)
+    }
+
     fn clip_frustum(&self, eye: &Vec3, frustum: &ConvexHull) -> ConvexHull {
         let mut result = frustum.clone();
         for i in 0..self.vertices.len() {
@@ -1,14 +1,14 @@
 
 import { mat4, ReadonlyMat4, vec3, vec4 } from 'gl-matrix';
-import { AnimationFunction, FramebufferBlendFunction, FunctionSource, HaloBitmap, HaloBitmapReader, HaloBSP, HaloLightmap, HaloMaterial, HaloModel, HaloModelPart, HaloSceneManager, HaloScenery, HaloSceneryInstance, HaloShaderEnvironment, HaloShaderModel, HaloShaderTransparencyChicago, HaloShaderTransparencyGeneric, HaloShaderTransparentChicagoBitmap, HaloShaderTransparentGenericMap, HaloShaderTransparentWater, HaloShaderTransparentWaterRipple, HaloSky, ShaderAlphaInput, ShaderInput, ShaderMapping, ShaderOutput, ShaderOutputFunction, ShaderOutputMapping, ShaderTransparentChicagoColorFunction } from 'noclip-rust-support';
+import { AnimationFunction, FramebufferBlendFunction, FunctionSource, HaloBitmap, HaloBitmapReader, HaloBSP, HaloGlowEmitter, HaloLightmap, HaloMaterial, HaloModel, HaloModelPart, HaloObject, HaloParticleSystemEmitter, HaloSceneManager, HaloScenery, HaloSceneryInstance, HaloShaderEnvironment, HaloShaderModel, HaloShaderTransparencyChicago, HaloShaderTransparencyChicagoExtended, HaloShaderTransparencyGeneric, HaloShaderTransparentChicagoBitmap, HaloShaderTransparentGenericMap, HaloShaderTransparentWater, HaloShaderTransparentWaterRipple, HaloSky, HaloWeatherEmitter, ShaderAlphaInput, ShaderInput, ShaderMapping, ShaderOutput, ShaderOutputFunction, ShaderOutputMapping, ShaderTransparentChicagoColorFunction } from 'noclip-rust-support';
 import { CameraController, computeViewSpaceDepthFromWorldSpacePoint } from '../Camera.js';
 import { Color, colorCopy, colorNewCopy, White } from '../Color.js';
 import { fullscreenMegaState, setAttachmentStateSimple } from '../gfx/helpers/GfxMegaStateDescriptorHelpers.js';
 import { GfxShaderLibrary, glslGenerateFloat } from '../gfx/helpers/GfxShaderLibrary.js';
 import { makeBackbufferDescSimple, standardFullClearRenderPassDescriptor } from '../gfx/helpers/RenderGraphHelpers.js';
-import { convertToTriangles, getTriangleIndexCountForTopologyIndexCount, GfxTopology } from '../gfx/helpers/TopologyHelpers.js';
+import { convertToTriangles, getTriangleIndexCountForTopologyIndexCount, GfxTopology, makeTriangleIndexBuffer } from '../gfx/helpers/TopologyHelpers.js';
 import { fillColor, fillMatrix4x2, fillMatrix4x4, fillVec3v, fillVec4, fillVec4v } from '../gfx/helpers/UniformBufferHelpers.js';
-import { GfxBindingLayoutDescriptor, GfxBlendFactor, GfxBlendMode, GfxBuffer, GfxBufferFrequencyHint, GfxBufferUsage, GfxCullMode, GfxDevice, GfxFrontFaceMode, GfxIndexBufferDescriptor, GfxInputLayout, GfxInputLayoutBufferDescriptor, GfxMegaStateDescriptor, GfxProgram, GfxSamplerFormatKind, GfxTexture, GfxTextureDimension, GfxTextureUsage, GfxVertexAttributeDescriptor, GfxVertexBufferDescriptor, GfxVertexBufferFrequency } from '../gfx/platform/GfxPlatform.js';
+import { GfxBindingLayoutDescriptor, GfxBlendFactor, GfxBlendMode, GfxBuffer, GfxBufferFrequencyHint, GfxBufferUsage, GfxCullMode, GfxDevice, GfxFrontFaceMode, GfxIndexBufferDescriptor, GfxInputLayout, GfxInputLayoutBufferDescriptor, GfxMegaStateDescriptor, GfxProgram, GfxSamplerFormatKind, GfxTexture, GfxTextureDimension, GfxTextureUsage, GfxVertexAttributeDescriptor, GfxVertexBufferDescriptor, GfxVertexBufferFrequency, makeTextureDescriptor2D } from '../gfx/platform/GfxPlatform.js';
 import { GfxFormat } from "../gfx/platform/GfxPlatformFormat.js";
 import { GfxRenderCache } from '../gfx/render/GfxRenderCache.js';
 import { GfxrAttachmentSlot, GfxrGraphBuilder } from '../gfx/render/GfxRenderGraph.js';
@@ -628,7 +628,7 @@ layout(std140) uniform ub_ShaderParams {
 };
 `;
 
-    constructor(private mgr: HaloSceneManager, public shader: HaloShaderTransparencyChicago) {
+    constructor(private mgr: HaloSceneManager, public shader: HaloShaderTransparencyChicago | HaloShaderTransparencyChicagoExtended) {
         super([ShaderTransparencyChicagoProgram.BindingsDefinition])
         this.frag = this.generateFragSection();
     }
@@ -691,7 +691,7 @@ class MaterialRender_TransparencyChicago {
     public sortKeyBase: number = 0;
     public visible = true;
 
-    constructor(private mgr: HaloSceneManager, textureCache: TextureCache, cache: GfxRenderCache, private shader: HaloShaderTransparencyChicago, fogEnabled: boolean) {
+    constructor(private mgr: HaloSceneManager, textureCache: TextureCache, cache: GfxRenderCache, private shader: HaloShaderTransparencyChicago | HaloShaderTransparencyChicagoExtended, fogEnabled: boolean) {
         const bitmaps = shader.get_bitmaps();
         function loadBitmap(index: number): rust.HaloBitmap | undefined {
             if (index >= bitmaps.length) return undefined;
@@ -1468,13 +1468,22 @@ class LightmapRenderer {
     constructor(public textureCache: TextureCache, renderCache: GfxRenderCache, public trisBuf: GfxBuffer, public bsp: HaloBSP, public mgr: HaloSceneManager, public bspIndex: number, public lightmap: HaloLightmap, public lightmapTex: TextureMapping | null, public fogEnabled: boolean) {
         this.modelData = [];
         this.materialRenderers = [];
-        mgr.get_lightmap_materials(lightmap).forEach(material => {
+        mgr.get_lightmap_materials(lightmap).forEach((material, i) => {
+            let modelData: LightmapModelData;
+            try {
+                modelData = new LightmapModelData(renderCache, mgr, bsp, material, trisBuf);
+            } catch (e) {
+                console.warn(`skipping material ${i} of lightmap: ${e}`);
+                material.free();
+                return;
+            }
+
             const shader = this.mgr.get_material_shader(material);
             if (shader instanceof rust.HaloShaderEnvironment) {
                 this.materialRenderers.push(new MaterialRender_Environment(this.mgr, textureCache, renderCache, shader, lightmapTex, fogEnabled));
             } else if (shader instanceof rust.HaloShaderTransparencyGeneric) {
                 this.materialRenderers.push(new MaterialRender_TransparencyGeneric(this.mgr, textureCache, renderCache, shader, fogEnabled));
-            } else if (shader instanceof rust.HaloShaderTransparencyChicago) {
+            } else if (shader instanceof rust.HaloShaderTransparencyChicago || shader instanceof rust.HaloShaderTransparencyChicagoExtended) {
                 this.materialRenderers.push(new MaterialRender_TransparencyChicago(this.mgr, textureCache, renderCache, shader, fogEnabled));
             } else if (shader instanceof rust.HaloShaderTransparentWater) {
                 this.materialRenderers.push(new MaterialRender_TransparencyWater(this.mgr, textureCache, renderCache, shader, fogEnabled));
@@ -1483,7 +1492,7 @@ class LightmapRenderer {
                 this.materialRenderers.push(null);
             }
 
-            this.modelData.push(new LightmapModelData(renderCache, mgr, bsp, material, trisBuf));
+            this.modelData.push(modelData);
         });
     }
 
@@ -1530,10 +1539,13 @@ class LightmapModelData {
     private indexOffset: number;
 
     constructor(cache: GfxRenderCache, mgr: HaloSceneManager, bsp: HaloBSP, public material: HaloMaterial, private indexBuffer: GfxBuffer) {
+        // these throw on broken geometry, so read both before creating any buffers
+        const vertexData = mgr.get_material_vertex_data(this.material, bsp);
+        const lightmapData = mgr.get_material_lightmap_data(this.material, bsp);
         this.inputLayout = this.getInputLayout(cache);
         this.modelMatrix = mat4.create();
-        this.vertexBuffer = createBufferFromData(cache.device, GfxBufferUsage.Vertex, GfxBufferFrequencyHint.Static, mgr.get_material_vertex_data(this.material, bsp).buffer);
-        this.lightmapVertexBuffer = createBufferFromData(cache.device, GfxBufferUsage.Vertex, GfxBufferFrequencyHint.Static, mgr.get_material_lightmap_data(this.material, bsp).buffer);
+        this.vertexBuffer = createBufferFromData(cache.device, GfxBufferUsage.Vertex, GfxBufferFrequencyHint.Static, vertexData.buffer);
+        this.lightmapVertexBuffer = createBufferFromData(cache.device, GfxBufferUsage.Vertex, GfxBufferFrequencyHint.Static, lightmapData.buffer);
         this.indexCount = this.material.get_num_indices();
         this.indexOffset = this.material.get_index_offset();
 
@@ -1595,7 +1607,7 @@ class ModelRenderer {
                 this.materialRenderers.push(new MaterialRender_Model(this.mgr, textureCache, renderCache, shader, fogEnabled));
             } else if (shader instanceof rust.HaloShaderTransparencyGeneric) {
                 this.materialRenderers.push(new MaterialRender_TransparencyGeneric(this.mgr, textureCache, renderCache, shader, fogEnabled));
-            } else if (shader instanceof rust.HaloShaderTransparencyChicago) {
+            } else if (shader instanceof rust.HaloShaderTransparencyChicago || shader instanceof rust.HaloShaderTransparencyChicagoExtended) {
                 this.materialRenderers.push(new MaterialRender_TransparencyChicago(this.mgr, textureCache, renderCache, shader, fogEnabled));
             } else if (shader instanceof rust.HaloShaderTransparentWater) {
                 this.materialRenderers.push(new MaterialRender_TransparencyWater(this.mgr, textureCache, renderCache, shader, fogEnabled));
@@ -1703,10 +1715,28 @@ class ModelData {
             return a.shader_index - b.shader_index;
         });
 
+        // read everything up front, so a part with broken geometry can be skipped
+        // without leaving a hole in the combined buffers
+        const partIndices: Uint16Array[] = [];
+        const partVertices: Uint8Array[] = [];
+        const readableParts = parts.filter((part) => {
+            try {
+                const indices = mgr.get_model_part_indices(part);
+                const vertices = mgr.get_model_part_vertices(part);
+                partIndices.push(indices);
+                partVertices.push(vertices);
+                return true;
+            } catch (e) {
+                console.warn(`skipping part ${(part as any).index} of model: ${e}`);
+                part.free();
+                return false;
+            }
+        });
+
         let vertexCount = 0, indexCount = 0;
-        for (let i = 0; i < parts.length; i++) {
-            vertexCount += parts[i].vert_count;
-            indexCount += getTriangleIndexCountForTopologyIndexCount(GfxTopology.TriStrips, parts[i].tri_count());
+        for (let i = 0; i < readableParts.length; i++) {
+            vertexCount += readableParts[i].vert_count;
+            indexCount += getTriangleIndexCountForTopologyIndexCount(GfxTopology.TriStrips, readableParts[i].tri_count());
         }
         assert(vertexCount <= 0xFFFF);
 
@@ -1717,22 +1747,22 @@ class ModelData {
 
         let indexOffs = 0, vertexOffs = 0, vertexBase = 0;
 
-        for (let i = 0; i < parts.length; i++) {
-            const shaderIndex = parts[i].shader_index;
+        for (let i = 0; i < readableParts.length; i++) {
+            const shaderIndex = readableParts[i].shader_index;
 
             if (this.parts[shaderIndex] === undefined)
-                this.parts[shaderIndex] = new ModelPartData(parts[i], indexOffs);
+                this.parts[shaderIndex] = new ModelPartData(readableParts[i], indexOffs);
 
-            const indexBuffer = mgr.get_model_part_indices(parts[i]);
+            const indexBuffer = partIndices[i];
 
             const indexCount = convertToTriangles(indexData, indexOffs, GfxTopology.TriStrips, indexBuffer, vertexBase);
             indexOffs += indexCount;
             this.parts[shaderIndex].indexCount += indexCount;
 
-            const vertexData = mgr.get_model_part_vertices(parts[i]);
+            const vertexData = partVertices[i];
             device.uploadBufferData(this.vertexBuffer, vertexOffs, vertexData);
             vertexOffs += vertexData.byteLength;
-            vertexBase += parts[i].vert_count;
+            vertexBase += readableParts[i].vert_count;
         }
 
         this.indexBuffer = createBufferFromData(device, GfxBufferUsage.Index, GfxBufferFrequencyHint.Static, indexData.buffer);
@@ -1849,6 +1879,145 @@ class BSPRenderer {
     }
 }
 
+class ParticleProgram extends DeviceProgram {
+    public static ub_SceneParams = 0;
+    public static ub_EmitterParams = 1;
+
+    public override both = `
+precision highp float;
+
+${GfxShaderLibrary.MatrixLibrary}
+${GfxShaderLibrary.saturate}
+${GfxShaderLibrary.invlerp}
+
+layout(std140) uniform ub_SceneParams {
+    Mat4x4 u_ProjectionView;
+    vec3 u_PlayerPos;
+    vec4 u_FogColor;
+    vec4 u_FogDistances;
+};
+
+layout(std140) uniform ub_EmitterParams {
+    vec4 u_CameraRight;
+    vec4 u_CameraUp;
+};
+
+layout(binding = 0) uniform sampler2D u_Texture0;
+// one row per particle: position and size, color, then rotation and sprite frame
+layout(binding = 1) uniform sampler2D u_DataTexture;
+
+varying vec2 v_UV;
+varying vec4 v_Color;
+varying vec3 v_Position;
+
+${BaseProgram.CalcFog}
+`;
+
+    public override vert = `
+void mainVS() {
+    int t_CornerIndex = int(gl_VertexID) % 4;
+    int t_Particle = int(gl_VertexID) / 4;
+    vec4 t_PositionSize = texelFetch(TEXTURE(u_DataTexture), ivec2(0, t_Particle), 0);
+    v_Color = texelFetch(TEXTURE(u_DataTexture), ivec2(1, t_Particle), 0);
+    float t_Rotation = texelFetch(TEXTURE(u_DataTexture), ivec2(2, t_Particle), 0).x;
+
+    vec2 t_Corner = vec2((t_CornerIndex == 1 || t_CornerIndex == 2) ? 1.0 : -1.0, t_CornerIndex < 2 ? 1.0 : -1.0);
+    v_UV = t_Corner * vec2(0.5, -0.5) + 0.5;
+    vec2 t_Offset = mat2(cos(t_Rotation), sin(t_Rotation), -sin(t_Rotation), cos(t_Rotation)) * t_Corner * t_PositionSize.w;
+    v_Position = t_PositionSize.xyz + t_Offset.x * u_CameraRight.xyz + t_Offset.y * u_CameraUp.xyz;
+    gl_Position = UnpackMatrix(u_ProjectionView) * vec4(v_Position, 1.0);
+}
+`;
+
+    public override frag = `
+void mainPS() {
+    vec4 t_Color = texture(SAMPLER_2D(u_Texture0), v_UV) * v_Color;
+    CalcFog(t_Color, v_Position);
+    gl_FragColor = t_Color;
+}
+`;
+}
+
+type HaloEmitter = HaloWeatherEmitter | HaloGlowEmitter | HaloParticleSystemEmitter;
+
+// draws the particles of a weather, glow or particle system emitter as camera facing sprites
+class ParticleEmitterRenderer {
+    private textureMapping: (TextureMapping | null)[] = nArray(2, () => null);
+    private gfxProgram: GfxProgram;
+    private megaStateFlags: Partial<GfxMegaStateDescriptor> = { depthWrite: false, cullMode: GfxCullMode.None };
+    private dataTexture: GfxTexture;
+    private pixelData: Float32Array;
+    private indexBuffer: GfxBuffer;
+    private inputLayout: GfxInputLayout;
+    private sortKeyBase: number;
+    public visible = true;
+
+    // origin is null for weather, which follows the camera
+    constructor(textureCache: TextureCache, cache: GfxRenderCache, mgr: HaloSceneManager, public emitter: HaloEmitter, public origin: vec3 | null, fogEnabled: boolean) {
+        // throws on blend functions we can't draw, before anything is allocated
+        setBlendMode(this.megaStateFlags, emitter.get_framebuffer_blend_function());
+        const maxParticles = emitter.max_particles;
+        assert(maxParticles > 0 && maxParticles * 4 <= 0xFFFF);
+
+        const bitmapDependency = emitter.get_bitmap();
+        const bitmap = bitmapDependency !== undefined ? mgr.resolve_bitmap_dependency(bitmapDependency) : undefined;
+        this.textureMapping[0] = textureCache.getTextureMapping(bitmap);
+
+        const device = cache.device;
+        const texelsPerParticle = rust.HaloWeatherEmitter.get_texels_per_particle();
+        this.dataTexture = device.createTexture(makeTextureDescriptor2D(GfxFormat.F32_RGBA, texelsPerParticle, maxParticles, 1));
+        this.pixelData = new Float32Array(texelsPerParticle * maxParticles * 4);
+        this.textureMapping[1] = new TextureMapping();
+        this.textureMapping[1].gfxTexture = this.dataTexture;
+
+        const indexData = makeTriangleIndexBuffer(GfxTopology.Quads, 0, maxParticles * 4);
+        this.indexBuffer = createBufferFromData(device, GfxBufferUsage.Index, GfxBufferFrequencyHint.Static, indexData.buffer);
+        this.inputLayout = cache.createInputLayout({ vertexAttributeDescriptors: [], vertexBufferDescriptors: [], indexBufferFormat: GfxFormat.U16_R });
+
+        const prog = new ParticleProgram();
+        prog.setDefineBool('USE_FOG', fogEnabled);
+        this.gfxProgram = cache.createProgram(prog);
+        this.sortKeyBase = makeSortKeyTranslucent(SortKey.Translucent);
+    }
+
+    public update(dtMilliseconds: number, cameraPos: vec3): void {
+        this.emitter.update(dtMilliseconds, (this.origin ?? cameraPos) as Float32Array);
+    }
+
+    public prepareToRender(device: GfxDevice, renderInstManager: GfxRenderInstManager, view: View): void {
+        const numParticles = this.emitter.num_particles();
+        if (!this.visible || numParticles === 0)
+            return;
+
+        this.emitter.fill_texture(this.pixelData);
+        device.uploadTextureData(this.dataTexture, 0, [this.pixelData]);
+
+        const renderInst = renderInstManager.newRenderInst();
+        renderInst.setBindingLayouts(particleBindingLayouts);
+        renderInst.setGfxProgram(this.gfxProgram);
+        renderInst.setSamplerBindingsFromTextureMappings(this.textureMapping);
+        renderInst.setMegaStateFlags(this.megaStateFlags);
+        renderInst.sortKey = this.sortKeyBase;
+
+        // the view's axes in halo space, to billboard with
+        const worldFromView = view.worldFromViewMatrix;
+        let offs = renderInst.allocateUniformBuffer(ParticleProgram.ub_EmitterParams, 8);
+        const mapped = renderInst.mapUniformBufferF32(ParticleProgram.ub_EmitterParams);
+        offs += fillVec4(mapped, offs, worldFromView[0], worldFromView[1], worldFromView[2]);
+        offs += fillVec4(mapped, offs, worldFromView[4], worldFromView[5], worldFromView[6]);
+
+        renderInst.setVertexInput(this.inputLayout, null, { buffer: this.indexBuffer });
+        renderInst.setDrawCount(numParticles * 6);
+        renderInstManager.submitRenderInst(renderInst);
+    }
+
+    public destroy(device: GfxDevice): void {
+        device.destroyTexture(this.dataTexture);
+        device.destroyBuffer(this.indexBuffer);
+        this.emitter.free();
+    }
+}
+
 const bindingLayouts: GfxBindingLayoutDescriptor[] = [
     { numUniformBuffers: 3, numSamplers: 8, samplerEntries: [
         { dimension: GfxTextureDimension.n2D, formatKind: GfxSamplerFormatKind.Float, }, // 0
@@ -1862,6 +2031,13 @@ const bindingLayouts: GfxBindingLayoutDescriptor[] = [
     ] },
 ];
 
+const particleBindingLayouts: GfxBindingLayoutDescriptor[] = [
+    { numUniformBuffers: 2, numSamplers: 2, samplerEntries: [
+        { dimension: GfxTextureDimension.n2D, formatKind: GfxSamplerFormatKind.Float, }, // 0
+        { dimension: GfxTextureDimension.n2D, formatKind: GfxSamplerFormatKind.UnfilterableFloat, }, // 1
+    ] },
+];
+
 // A "View" is effectively camera settings, but in Halo space.
 class View {
     // aka viewMatrix
@@ -1900,6 +2076,13 @@ class HaloScene implements Viewer.SceneGfx {
     public fogEnabled: boolean
     public fogColor = vec4.create();
     public fogDistances = vec4.create();
+    private fogBSPIndex = -1;
+    private fogCluster = -1;
+    private weatherRenderers: ParticleEmitterRenderer[] = [];
+    private weatherBSPIndex = -1;
+    private weatherCluster = -1;
+    // glows and particle systems attached to scenery
+    private attachmentRenderers: ParticleEmitterRenderer[] = [];
     private mainView = new View();
 
     constructor(public device: GfxDevice, public mgr: HaloSceneManager, public bitmapReader: HaloBitmapReader, public fogSettings: FogSettings) {
@@ -1924,16 +2107,79 @@ class HaloScene implements Viewer.SceneGfx {
             }
         }
         const sceneryInstances: HaloSceneryInstance[] = mgr.get_scenery_instances();
-        this.sceneryRenderers = mgr.get_scenery_palette().map((scenery, i) => {
+        const sceneryPalette: (HaloScenery | null)[] = mgr.get_scenery_palette();
+        this.sceneryRenderers = [];
+        sceneryPalette.forEach((scenery, i) => {
+            // palette entries are null when their tag couldn't be read
+            if (scenery === null)
+                return;
             const instances = sceneryInstances.filter(instance => instance.scenery_type === i);
-            return new SceneryRenderer(this.textureCache, this.renderHelper.renderCache, this.mgr, scenery, instances, this.fogEnabled);
+            this.sceneryRenderers.push(new SceneryRenderer(this.textureCache, this.renderHelper.renderCache, this.mgr, scenery, instances, this.fogEnabled));
         });
+        const sceneryObjects: (HaloObject | null)[] = mgr.get_object_palette(rust.HaloObjectType.Scenery);
+        sceneryObjects.forEach((object, i) => {
+            if (object === null)
+                return;
+            for (const instance of sceneryInstances) {
+                if (instance.scenery_type !== i)
+                    continue;
+                const position = instance.position;
+                const origin = vec3.fromValues(position.x, position.y, position.z);
+                position.free();
+                const emitters: HaloEmitter[] = [...mgr.get_object_glow_emitters(object), ...mgr.get_object_particle_system_emitters(object)];
+                for (const emitter of emitters) {
+                    const renderer = this.createParticleRenderer(emitter, origin);
+                    if (renderer !== null)
+                        this.attachmentRenderers.push(renderer);
+                }
+            }
+            object.free();
+        });
+    }
+
+    private createParticleRenderer(emitter: HaloEmitter, origin: vec3 | null): ParticleEmitterRenderer | null {
+        if (emitter.max_particles === 0) {
+            emitter.free();
+            return null;
+        }
+        try {
+            return new ParticleEmitterRenderer(this.textureCache, this.renderHelper.renderCache, this.mgr, emitter, origin, this.fogEnabled);
+        } catch (e) {
+            console.warn(`skipping particle emitter: ${e}`);
+            emitter.free();
+            return null;
+        }
+    }
+
+    // swap in the weather of whichever cluster the camera is in
+    private updateClusterWeather() {
+        const cameraPos = this.mainView.cameraPos;
+        for (let i = 0; i < this.bspRenderers.length; i++) {
+            const bsp = this.bspRenderers[i].bsp;
+            const cluster = bsp.find_cluster(cameraPos[0], cameraPos[1], cameraPos[2]);
+            if (cluster === undefined)
+                continue;
+            if (i === this.weatherBSPIndex && cluster === this.weatherCluster)
+                return;
+            this.weatherBSPIndex = i;
+            this.weatherCluster = cluster;
+
+            this.weatherRenderers.forEach(r => r.destroy(this.device));
+            this.weatherRenderers = [];
+            for (const emitter of this.mgr.get_cluster_weather_emitters(bsp, cluster)) {
+                const renderer = this.createParticleRenderer(emitter, null);
+                if (renderer !== null)
+                    this.weatherRenderers.push(renderer);
+            }
+            return;
+        }
     }
 
     private setupFogSettings() {
         this.fogEnabled = this.fogSettings !== FogSettings.Disabled;
         if (this.activeSky && this.fogEnabled) {
-            const fogLocation = this.fogSettings === FogSettings.Outdoor ? 'outdoor' : 'indoor';
+            // cluster fog starts out with the outdoor fog until the camera is in a cluster
+            const fogLocation = this.fogSettings === FogSettings.Indoor ? 'indoor' : 'outdoor';
             const color =  this.activeSky[`${fogLocation}_fog_color`];
             this.fogColor = vec4.fromValues(color.r, color.g, color.b, this.activeSky[`${fogLocation}_fog_max_density`]);
             color.free();
@@ -1945,6 +2191,37 @@ class HaloScene implements Viewer.SceneGfx {
         }
     }
 
+    // switch to the atmospheric fog of whichever cluster the camera is in
+    private updateClusterFog() {
+        const cameraPos = this.mainView.cameraPos;
+        for (let i = 0; i < this.bspRenderers.length; i++) {
+            const bsp = this.bspRenderers[i].bsp;
+            const cluster = bsp.find_cluster(cameraPos[0], cameraPos[1], cameraPos[2]);
+            if (cluster === undefined)
+                continue;
+            if (i === this.fogBSPIndex && cluster === this.fogCluster)
+                return;
+            this.fogBSPIndex = i;
+            this.fogCluster = cluster;
+
+            let fog;
+            try {
+                fog = this.mgr.get_cluster_fog(bsp, cluster);
+            } catch (e) {
+                console.warn(`failed to get fog for cluster ${cluster} of BSP ${i}: ${e}`);
+                return;
+            }
+            if (fog === undefined)
+                return;
+            const color = fog.atmospheric_color;
+            vec4.set(this.fogColor, color.r, color.g, color.b, fog.atmospheric_max_density);
+            color.free();
+            vec4.set(this.fogDistances, fog.atmospheric_start_distance, fog.atmospheric_opaque_distance, 0, 0);
+            fog.free();
+            return;
+        }
+    }
+
     public adjustCameraController(c: CameraController) {
         c.setSceneMoveSpeedMult(1/1000);
     }
@@ -1958,6 +2235,8 @@ class HaloScene implements Viewer.SceneGfx {
         template.setBindingLayouts(bindingLayouts);
 
         this.mainView.setupFromViewerInput(viewerInput);
+        if (this.fogEnabled && this.fogSettings === FogSettings.Cluster)
+            this.updateClusterFog();
 
         let offs = template.allocateUniformBuffer(BaseProgram.ub_SceneParams, 32 + 12);
         const mapped = template.mapUniformBufferF32(BaseProgram.ub_SceneParams);
@@ -1973,6 +2252,18 @@ class HaloScene implements Viewer.SceneGfx {
         });
 
         this.sceneryRenderers.forEach(r => r.prepareToRender(this.renderHelper.renderInstManager, this.mainView));
+
+        this.updateClusterWeather();
+        for (const r of this.weatherRenderers) {
+            r.update(viewerInput.deltaTime, this.mainView.cameraPos);
+            (r.emitter as HaloWeatherEmitter).cull_sheltered(this.bspRenderers[this.weatherBSPIndex].bsp);
+            r.prepareToRender(device, this.renderHelper.renderInstManager, this.mainView);
+        }
+        for (const r of this.attachmentRenderers) {
+            r.update(viewerInput.deltaTime, this.mainView.cameraPos);
+            r.prepareToRender(device, this.renderHelper.renderInstManager, this.mainView);
+        }
+
         if (this.skyboxRenderer) {
             this.skyboxRenderer.prepareToRender(this.renderHelper.renderInstManager, this.mainView)
         }
@@ -2022,6 +2313,8 @@ class HaloScene implements Viewer.SceneGfx {
         this.bspRenderers.forEach(r => r.destroy(device));
         this.textureCache.destroy(device);
         this.sceneryRenderers.forEach(r => r.destroy(device));
+        this.weatherRenderers.forEach(r => r.destroy(device));
+        this.attachmentRenderers.forEach(r => r.destroy(device));
         if (this.skyboxRenderer) {
             this.skyboxRenderer.destroy(device);
         }
@@ -2040,10 +2333,12 @@ enum FogSettings {
     Indoor,
     Outdoor,
     Disabled,
+    // follows the cluster the camera is in
+    Cluster,
 }
 
 class HaloSceneDesc implements Viewer.SceneDesc {
-    constructor(public id: string, public name: string, public specificBSPs: number[] = [], public fogSettings = FogSettings.Outdoor) {
+    constructor(public id: string, public name: string, public specificBSPs: number[] = [], public fogSettings = FogSettings.Cluster) {
         this.id;
     }
 
@@ -2067,6 +2362,8 @@ class HaloSceneDesc implements Viewer.SceneDesc {
             bsps = bsps.filter((_, i) => this.specificBSPs.indexOf(i) >= 0);
         }
         bsps.forEach((bsp, i) => renderer.addBSP(bsp, i));
+        for (const error of mapManager.take_errors())
+            console.warn(error);
         return renderer;
     }
 
@@ -72,7 +72,7 @@ function makeTexture(device: GfxDevice, bitmap: HaloBitmap, mgr: HaloSceneManage
     const bitmapMetadata = bitmap.get_metadata_for_index(submap);
     let bitmapData;
     if (bitmapMetadata.is_external()) {
-        bitmapData = bitmapReader.get_and_convert_bitmap_data(bitmap, submap);
+        bitmapData = bitmapReader.get_and_convert_external_bitmap_data(bitmap, submap);
     } else {
         bitmapData = mgr.get_and_convert_bitmap_data(bitmap, submap);
     }